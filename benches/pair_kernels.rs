@@ -0,0 +1,46 @@
+//! Compare the time to evaluate the forces with the particles stored as an array of structures
+//! ([`PairSearch::MinimumImage`]) and as a structure of arrays ([`PairSearch::Vectorized`]).
+//! The structure of arrays is updated after each move of the particles, so its update is timed with the evaluation.
+//!
+//! Run with `cargo bench --bench pair_kernels`.
+
+use std::{hint::black_box, path::Path, time::Instant};
+
+use mlom::parameters::{PairSearch, SimulationParameters};
+use mlom::potential::LennardJones;
+use mlom::system::System;
+
+/// The number of evaluations timed for each kernel
+const NB_EVALUATIONS: u32 = 50;
+
+/// The mean time of an evaluation of the forces, in milliseconds
+fn time_evaluation(pair_search: PairSearch) -> f64 {
+	let parameters = SimulationParameters {
+		pair_search,
+		seed: Some(0),
+		..Default::default()
+	};
+	let mut system = System::from_file_with_parameters(Path::new("dataset/particles.xyz"), 0, parameters).unwrap();
+	// The concrete potential, rather than the runtime choice of the parameters, so that the kernel is specialized
+	let potential = LennardJones::new(system.parameters().r_star, system.parameters().epsilon_star);
+
+	// Warm up the caches
+	system.update_particle_arrays();
+	black_box(system.evaluate_forces_serial(&potential));
+
+	let start = Instant::now();
+	for _ in 0..NB_EVALUATIONS {
+		black_box(&mut system).update_particle_arrays();
+		black_box(black_box(&system).evaluate_forces_serial(&potential));
+	}
+	return start.elapsed().as_secs_f64() * 1000.0 / NB_EVALUATIONS as f64;
+}
+
+fn main() {
+	let array_of_structures = time_evaluation(PairSearch::MinimumImage);
+	let structure_of_arrays = time_evaluation(PairSearch::Vectorized);
+
+	println!("array of structures: {array_of_structures:.3} ms per evaluation");
+	println!("structure of arrays: {structure_of_arrays:.3} ms per evaluation");
+	println!("speedup: {:.2}x", array_of_structures / structure_of_arrays);
+}
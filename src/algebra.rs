@@ -1,6 +1,7 @@
 //! Linear algebra operations needed for the simulation
 
-use rand::{Rng, distr::Uniform, rng};
+use rand::{Rng, distr::Uniform};
+use rand_distr::StandardNormal;
 
 /// Checks if 2 floats are approximately equal.
 #[macro_export]
@@ -16,9 +17,9 @@ macro_rules! assert_approx_eq {
 macro_rules! assert_vector_approx_eq {
 	($L: expr, $R: expr) => {
 		let difference = $L - $R;
-		assert!(difference.x() < 1e-5, "{}", format!("{:?} =/= {:?}", $L, $R));
-		assert!(difference.y() < 1e-5, "{}", format!("{:?} =/= {:?}", $L, $R));
-		assert!(difference.z() < 1e-5, "{}", format!("{:?} =/= {:?}", $L, $R));
+		assert!(difference.x().abs() < 1e-5, "{}", format!("{:?} =/= {:?}", $L, $R));
+		assert!(difference.y().abs() < 1e-5, "{}", format!("{:?} =/= {:?}", $L, $R));
+		assert!(difference.z().abs() < 1e-5, "{}", format!("{:?} =/= {:?}", $L, $R));
 	};
 }
 
@@ -100,9 +101,12 @@ impl Vector3 {
 	}
 
 	/// Create a random vector in the unit cube, i.e. each component is in the range [-1, 1]
-	pub fn random_in_unit_cube() -> Self {
+	///
+	/// # Arguments
+	///
+	/// * `rng` - The random number generator to draw the components from
+	pub fn random_in_unit_cube(rng: &mut impl Rng) -> Self {
 		let distribution = Uniform::new_inclusive(-1.0f64, 1.0f64).unwrap();
-		let mut rng = rng();
 
 		return Vector3 {
 			x: rng.sample(distribution),
@@ -111,6 +115,19 @@ impl Vector3 {
 		};
 	}
 
+	/// Create a random vector whose components follow the standard normal distribution
+	///
+	/// # Arguments
+	///
+	/// * `rng` - The random number generator to draw the components from
+	pub fn random_gaussian(rng: &mut impl Rng) -> Self {
+		return Vector3 {
+			x: rng.sample(StandardNormal),
+			y: rng.sample(StandardNormal),
+			z: rng.sample(StandardNormal),
+		};
+	}
+
 	/// The x component of the vector
 	pub fn x(&self) -> f64 {
 		self.x
@@ -141,6 +158,20 @@ impl Vector3 {
 		(self.x.powi(2) + self.y.powi(2) + self.z.powi(2)).sqrt()
 	}
 
+	/// Compute the dot product with another vector
+	pub fn dot(&self, rhs: &Self) -> f64 {
+		self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+	}
+
+	/// Compute the cross product with another vector
+	pub fn cross(&self, rhs: &Self) -> Self {
+		Self::from(
+			self.y * rhs.z - self.z * rhs.y,
+			self.z * rhs.x - self.x * rhs.z,
+			self.x * rhs.y - self.y * rhs.x,
+		)
+	}
+
 	pub fn as_point(self) -> Point3 {
 		Point3 {
 			x: self.x,
@@ -280,18 +311,18 @@ impl std::ops::Div<f64> for Vector3 {
 	type Output = Vector3;
 	fn div(self, rhs: f64) -> Self::Output {
 		Self::Output {
-			x: self.x * rhs,
-			y: self.y * rhs,
-			z: self.z * rhs,
+			x: self.x / rhs,
+			y: self.y / rhs,
+			z: self.z / rhs,
 		}
 	}
 }
 
 impl std::ops::DivAssign<f64> for Vector3 {
 	fn div_assign(&mut self, rhs: f64) {
-		self.x *= rhs;
-		self.y *= rhs;
-		self.z *= rhs;
+		self.x /= rhs;
+		self.y /= rhs;
+		self.z /= rhs;
 	}
 }
 
@@ -324,6 +355,24 @@ mod tests {
 		assert!((p.distance_to(&q) - 5.0).abs() < 1e-12);
 	}
 
+	#[test]
+	fn vector_division() {
+		let mut v = Vector3::from(2.0, -4.0, 6.0);
+		assert_eq!(v / 2.0, Vector3::from(1.0, -2.0, 3.0));
+		v /= 4.0;
+		assert_eq!(v, Vector3::from(0.5, -1.0, 1.5));
+	}
+
+	#[test]
+	fn vector_cross_product() {
+		let x = Vector3::from(1.0, 0.0, 0.0);
+		let y = Vector3::from(0.0, 1.0, 0.0);
+		assert_eq!(x.cross(&y), Vector3::from(0.0, 0.0, 1.0));
+		assert_eq!(y.cross(&x), Vector3::from(0.0, 0.0, -1.0));
+		let v = Vector3::from(2.0, -3.0, 0.5);
+		assert_eq!(v.cross(&v), Vector3::zero());
+	}
+
 	#[test]
 	fn vector_zero_and_norms() {
 		let z = Vector3::zero();
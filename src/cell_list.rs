@@ -0,0 +1,198 @@
+//! Cell lists (linked cells), to find the pairs of particles closer than the cut in linear time
+//!
+//! The box is split into cubic cells whose side is at least the radius of the cut,
+//! so that a particle only interacts with the particles of its cell and of the 26 neighbouring cells.
+
+use crate::{
+	algebra::{Point3, Vector3},
+	periodic_conditions::minimum_image,
+	system::{Particle, System},
+};
+
+/// The particles of a [system](System), sorted into the cells of the box
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellList {
+	/// The number of cells along each side of the box
+	nb_cells_per_side: usize,
+	/// The side of the cubic box
+	box_side: f64,
+	/// The indices of the particles in each cell
+	cells: Vec<Vec<usize>>,
+}
+
+impl CellList {
+	/// Sort the given particles into cells
+	///
+	/// # Arguments
+	///
+	/// * `particles` - The particles to sort
+	/// * `box_side` - The side of the cubic box, centered on the origin
+	/// * `radius_cut` - The distance above which pairs are not needed, which must not be larger than half the box
+	pub fn new(particles: &[Particle], box_side: f64, radius_cut: f64) -> Self {
+		assert!(radius_cut <= box_side / 2.0);
+
+		// With less than 3 cells per side, a cell would be the neighbour of another one several times,
+		// so a single cell is used, i.e. all pairs are visited
+		let mut nb_cells_per_side = (box_side / radius_cut).floor() as usize;
+		if nb_cells_per_side < 3 {
+			nb_cells_per_side = 1;
+		}
+
+		let mut cell_list = Self {
+			nb_cells_per_side,
+			box_side,
+			cells: vec![Vec::new(); nb_cells_per_side.pow(3)],
+		};
+		for (i, particle) in particles.iter().enumerate() {
+			let cell = cell_list.cell_of(&particle.coordinates);
+			cell_list.cells[cell].push(i);
+		}
+
+		return cell_list;
+	}
+
+	/// The number of cells along each side of the box
+	pub fn nb_cells_per_side(&self) -> usize {
+		self.nb_cells_per_side
+	}
+
+	/// The index of the cell with the given coordinates, which wrap around the box
+	fn cell_index(&self, x: isize, y: isize, z: isize) -> usize {
+		let n = self.nb_cells_per_side as isize;
+		return (x.rem_euclid(n) + n * (y.rem_euclid(n) + n * z.rem_euclid(n))) as usize;
+	}
+
+	/// The coordinates of the cell containing the given point, which may be outside of the box
+	fn cell_coordinates(&self, point: &Point3) -> (isize, isize, isize) {
+		let coordinate = |c: f64| {
+			let wrapped = (c + self.box_side / 2.0).rem_euclid(self.box_side);
+			((wrapped / self.box_side * self.nb_cells_per_side as f64) as isize).min(self.nb_cells_per_side as isize - 1)
+		};
+		return (coordinate(point.x()), coordinate(point.y()), coordinate(point.z()));
+	}
+
+	/// The index of the cell containing the given point, which may be outside of the box
+	fn cell_of(&self, point: &Point3) -> usize {
+		let (x, y, z) = self.cell_coordinates(point);
+		return self.cell_index(x, y, z);
+	}
+
+	/// Call the given function on each pair of particles in the same or in neighbouring cells, exactly once per pair.
+	/// The pairs still have to be filtered according to their distance.
+	pub fn for_each_pair(&self, mut f: impl FnMut(usize, usize)) {
+		let n = self.nb_cells_per_side as isize;
+		for cell_z in 0..n {
+			for cell_y in 0..n {
+				for cell_x in 0..n {
+					let cell = &self.cells[self.cell_index(cell_x, cell_y, cell_z)];
+
+					// Pairs inside the cell
+					for (a, &i) in cell.iter().enumerate() {
+						for &j in &cell[(a + 1)..] {
+							f(i, j);
+						}
+					}
+					if n == 1 {
+						continue;
+					}
+
+					// Pairs with half of the neighbouring cells, the other half sees this cell as its neighbour
+					for dz in -1..=1isize {
+						for dy in -1..=1isize {
+							for dx in -1..=1isize {
+								if (dz, dy, dx) <= (0, 0, 0) {
+									continue;
+								}
+								let neighbour =
+									&self.cells[self.cell_index(cell_x + dx, cell_y + dy, cell_z + dz)];
+								for &i in cell {
+									for &j in neighbour {
+										f(i, j);
+									}
+								}
+							}
+						}
+					}
+				}
+			}
+		}
+	}
+
+	/// Call the given function on each particle in the cell containing the given point or in its neighbouring cells,
+	/// including the particle at this point if there is one.
+	/// The particles still have to be filtered according to their distance.
+	///
+	/// # Arguments
+	///
+	/// * `point` - The point whose neighbours are visited, which may be outside of the box
+	/// * `f` - The function called on each particle
+	pub fn for_each_neighbour(&self, point: &Point3, mut f: impl FnMut(usize)) {
+		if self.nb_cells_per_side == 1 {
+			self.cells[0].iter().for_each(|&j| f(j));
+			return;
+		}
+
+		let (cell_x, cell_y, cell_z) = self.cell_coordinates(point);
+		for dz in -1..=1isize {
+			for dy in -1..=1isize {
+				for dx in -1..=1isize {
+					for &j in &self.cells[self.cell_index(cell_x + dx, cell_y + dy, cell_z + dz)] {
+						f(j);
+					}
+				}
+			}
+		}
+	}
+}
+
+impl System {
+	/// Call the given function on each pair of particles closer than the cut, with the displacement from the nearest
+	/// image of the second particle to the first one, found with a [cell list](CellList).
+	pub(crate) fn for_each_pair_in_cut(&self, cell_list: &CellList, f: impl FnMut(usize, usize, Vector3)) {
+		self.for_each_pair_within(cell_list, self.parameters.r_cut, f);
+	}
+
+	/// Call the given function on each pair of particles closer than the given radius, with the displacement from the
+	/// nearest image of the second particle to the first one, found with a [cell list](CellList) built for this radius.
+	pub(crate) fn for_each_pair_within(&self, cell_list: &CellList, radius: f64, mut f: impl FnMut(usize, usize, Vector3)) {
+		let box_side = self.parameters.box_side;
+		let radius_cut_squared = radius.powi(2);
+		cell_list.for_each_pair(|i, j| {
+			let displacement = minimum_image(self.particles[i].coordinates - self.particles[j].coordinates, box_side);
+			if displacement.norm_squared() <= radius_cut_squared {
+				f(i, j, displacement);
+			}
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn each_close_pair_is_visited_once() {
+		let system = System::from_file(std::path::Path::new("dataset/particles.xyz"), 0).unwrap();
+		let box_side = system.parameters.box_side;
+		for radius_cut in [4.0, 10.0, 15.0] {
+			let cell_list = CellList::new(&system.particles, box_side, radius_cut);
+
+			let mut visited = vec![0; system.nb_particles_total().pow(2)];
+			cell_list.for_each_pair(|i, j| {
+				visited[i.min(j) * system.nb_particles_total() + i.max(j)] += 1;
+			});
+
+			for i in 0..system.nb_particles_total() {
+				for j in (i + 1)..system.nb_particles_total() {
+					let displacement =
+						minimum_image(system.particles[i].coordinates - system.particles[j].coordinates, box_side);
+					let count = visited[i * system.nb_particles_total() + j];
+					assert!(count <= 1);
+					if displacement.norm() <= radius_cut {
+						assert_eq!(count, 1);
+					}
+				}
+			}
+		}
+	}
+}
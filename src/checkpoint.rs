@@ -0,0 +1,293 @@
+//! Binary checkpoints of the full state of a [system](System), to resume a run exactly where it stopped
+//!
+//! All numbers are stored in little endian, and floating point numbers are stored bit for bit.
+//! The parameters and the topology are stored as TOML text, so that the format doesn't change when fields are added.
+
+use std::{
+	fmt::Display,
+	fs::{self, File},
+	io::{BufReader, BufWriter, Read, Write},
+	path::{Path, PathBuf},
+};
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+	algebra::{Point3, Vector3},
+	parameters::{ParameterError, SimulationParameters},
+	system::{Particle, System},
+	topology::{Exclusions, Topology},
+};
+
+/// The first bytes of a checkpoint file
+const MAGIC: &[u8; 8] = b"MLOMCKPT";
+/// The version of the format of the checkpoints
+const VERSION: u32 = 4;
+
+/// An error that occurred while reading a checkpoint
+#[derive(Debug)]
+pub enum CheckpointError {
+	/// The checkpoint could not be read
+	Io(std::io::Error),
+	/// The input is not a checkpoint
+	NotACheckpoint,
+	/// The checkpoint was written by an incompatible version
+	UnsupportedVersion(u32),
+	/// The parameters stored in the checkpoint are invalid
+	InvalidParameters(ParameterError),
+	/// The checkpoint is inconsistent
+	Corrupted(&'static str),
+}
+
+impl Display for CheckpointError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Io(error) => write!(f, "could not read the checkpoint: {error}"),
+			Self::NotACheckpoint => write!(f, "not a checkpoint"),
+			Self::UnsupportedVersion(version) => write!(f, "unsupported checkpoint version {version}, expected {VERSION}"),
+			Self::InvalidParameters(error) => write!(f, "{error}"),
+			Self::Corrupted(reason) => write!(f, "corrupted checkpoint: {reason}"),
+		}
+	}
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<std::io::Error> for CheckpointError {
+	fn from(error: std::io::Error) -> Self {
+		Self::Io(error)
+	}
+}
+
+/// Write a length or an index
+fn write_usize(writer: &mut impl Write, value: usize) -> std::io::Result<()> {
+	writer.write_all(&(value as u64).to_le_bytes())
+}
+
+/// Write a floating point number, bit for bit
+fn write_f64(writer: &mut impl Write, value: f64) -> std::io::Result<()> {
+	writer.write_all(&value.to_le_bytes())
+}
+
+/// Write a string, preceded by its length
+fn write_str(writer: &mut impl Write, value: &str) -> std::io::Result<()> {
+	write_usize(writer, value.len())?;
+	writer.write_all(value.as_bytes())
+}
+
+/// Read a fixed number of bytes
+fn read_array<const N: usize>(reader: &mut impl Read) -> std::io::Result<[u8; N]> {
+	let mut bytes = [0; N];
+	reader.read_exact(&mut bytes)?;
+	return Ok(bytes);
+}
+
+/// Read a length or an index
+fn read_usize(reader: &mut impl Read) -> Result<usize, CheckpointError> {
+	usize::try_from(u64::from_le_bytes(read_array(reader)?)).map_err(|_| CheckpointError::Corrupted("length too large"))
+}
+
+/// Read a floating point number
+fn read_f64(reader: &mut impl Read) -> Result<f64, CheckpointError> {
+	Ok(f64::from_le_bytes(read_array(reader)?))
+}
+
+/// Read a string, preceded by its length
+fn read_string(reader: &mut impl Read) -> Result<String, CheckpointError> {
+	let length = read_usize(reader)?;
+	let mut bytes = Vec::new();
+	reader.take(length as u64).read_to_end(&mut bytes)?;
+	if bytes.len() != length {
+		return Err(CheckpointError::Corrupted("truncated string"));
+	}
+	return String::from_utf8(bytes).map_err(|_| CheckpointError::Corrupted("invalid UTF-8 string"));
+}
+
+impl System {
+	/// Write the full state of the system, so that the run can be resumed with [`System::read_checkpoint`]
+	///
+	/// # Arguments
+	///
+	/// * `writer` - Where the checkpoint is written
+	pub fn write_checkpoint(&self, writer: &mut impl Write) -> std::io::Result<()> {
+		writer.write_all(MAGIC)?;
+		writer.write_all(&VERSION.to_le_bytes())?;
+
+		let parameters = toml::to_string(&self.parameters).map_err(std::io::Error::other)?;
+		write_str(writer, &parameters)?;
+		let topology = toml::to_string(&self.topology).map_err(std::io::Error::other)?;
+		write_str(writer, &topology)?;
+
+		write_usize(writer, self.nb_particles_local)?;
+		write_usize(writer, self.step_count)?;
+		writer.write_all(&[u8::from(self.half_step_momenta)])?;
+
+		writer.write_all(&self.rng.get_seed())?;
+		writer.write_all(&self.rng.get_stream().to_le_bytes())?;
+		writer.write_all(&self.rng.get_word_pos().to_le_bytes())?;
+
+		write_usize(writer, self.species.len())?;
+		for name in &self.species {
+			write_str(writer, name)?;
+		}
+
+		write_usize(writer, self.particles.len())?;
+		for particle in &self.particles {
+			let (x, y, z) = particle.xyz();
+			let p = particle.momentum;
+			for value in [x, y, z, p.x(), p.y(), p.z(), particle.charge] {
+				write_f64(writer, value)?;
+			}
+			write_usize(writer, particle.species)?;
+		}
+
+		return Ok(());
+	}
+
+	/// Read the full state of a system written by [`System::write_checkpoint`]
+	///
+	/// # Arguments
+	///
+	/// * `reader` - Where the checkpoint is read from
+	pub fn read_checkpoint(reader: &mut impl Read) -> Result<Self, CheckpointError> {
+		if &read_array::<8>(reader).map_err(|_| CheckpointError::NotACheckpoint)? != MAGIC {
+			return Err(CheckpointError::NotACheckpoint);
+		}
+		let version = u32::from_le_bytes(read_array(reader)?);
+		if version != VERSION {
+			return Err(CheckpointError::UnsupportedVersion(version));
+		}
+
+		let parameters = SimulationParameters::from_toml(&read_string(reader)?).map_err(CheckpointError::InvalidParameters)?;
+		let topology: Topology =
+			toml::from_str(&read_string(reader)?).map_err(|_| CheckpointError::Corrupted("invalid topology"))?;
+
+		let nb_particles_local = read_usize(reader)?;
+		let step_count = read_usize(reader)?;
+		let half_step_momenta = match read_array::<1>(reader)? {
+			[0] => false,
+			[1] => true,
+			_ => return Err(CheckpointError::Corrupted("invalid timing of the momenta")),
+		};
+
+		let mut rng = ChaCha8Rng::from_seed(read_array(reader)?);
+		rng.set_stream(u64::from_le_bytes(read_array(reader)?));
+		rng.set_word_pos(u128::from_le_bytes(read_array(reader)?));
+
+		let nb_species = read_usize(reader)?;
+		let mut species = Vec::new();
+		for _ in 0..nb_species {
+			species.push(read_string(reader)?);
+		}
+
+		let nb_particles = read_usize(reader)?;
+		let mut particles = Vec::new();
+		for _ in 0..nb_particles {
+			let mut values = [0.0; 7];
+			for value in values.iter_mut() {
+				*value = read_f64(reader)?;
+			}
+			let species_index = read_usize(reader)?;
+			if species_index >= species.len() {
+				return Err(CheckpointError::Corrupted("unknown species"));
+			}
+
+			particles.push(Particle {
+				coordinates: Point3::from(values[0], values[1], values[2]),
+				momentum: Vector3::from(values[3], values[4], values[5]),
+				species: species_index,
+				charge: values[6],
+			});
+		}
+		if nb_particles_local >= particles.len() {
+			return Err(CheckpointError::Corrupted("more local particles than particles"));
+		}
+
+		if reader.read(&mut [0])? != 0 {
+			return Err(CheckpointError::Corrupted("unexpected data after the end"));
+		}
+
+		let mut system = Self {
+			particles,
+			nb_particles_local,
+			parameters,
+			species,
+			step_count,
+			rng,
+			half_step_momenta,
+			// The neighbours don't depend on when the list was built, so it is just built again
+			verlet_list: None,
+			particle_arrays: None,
+			topology: Topology::default(),
+			exclusions: Exclusions::default(),
+		};
+		system.update_particle_arrays();
+		system.set_topology(topology)
+			.map_err(|_| CheckpointError::Corrupted("invalid topology"))?;
+		return Ok(system);
+	}
+
+	/// Save the full state of the system to a file.
+	/// The checkpoint is first written next to the file then renamed, so that a crash never leaves a partial checkpoint.
+	///
+	/// # Arguments
+	///
+	/// * `path` - The path of the checkpoint
+	pub fn save_checkpoint(&self, path: &Path) -> std::io::Result<()> {
+		let mut temporary = path.as_os_str().to_owned();
+		temporary.push(".tmp");
+		let temporary = PathBuf::from(temporary);
+
+		let mut writer = BufWriter::new(File::create(&temporary)?);
+		self.write_checkpoint(&mut writer)?;
+		writer.into_inner().map_err(|error| error.into_error())?.sync_all()?;
+
+		return fs::rename(&temporary, path);
+	}
+
+	/// Restore a system from a checkpoint file written by [`System::save_checkpoint`]
+	///
+	/// # Arguments
+	///
+	/// * `path` - The path of the checkpoint
+	pub fn restore(path: &Path) -> Result<Self, CheckpointError> {
+		Self::read_checkpoint(&mut BufReader::new(File::open(path)?))
+	}
+}
+
+/// Saves checkpoints of a [system](System) every given number of steps
+pub struct Checkpointer {
+	/// The path of the checkpoint, overwritten each time
+	path: PathBuf,
+	/// The number of steps between 2 checkpoints
+	every: usize,
+}
+
+impl Checkpointer {
+	/// Create a checkpointer saving to the given file every given number of steps
+	///
+	/// # Arguments
+	///
+	/// * `path` - The path of the checkpoint, overwritten each time
+	/// * `every` - The number of steps between 2 checkpoints
+	pub fn new(path: &Path, every: usize) -> Self {
+		assert!(every > 0);
+		Self {
+			path: path.to_path_buf(),
+			every,
+		}
+	}
+
+	/// Save a checkpoint if the step count of the system is a multiple of the number of steps between 2 checkpoints
+	///
+	/// # Arguments
+	///
+	/// * `system` - The system to save
+	pub fn record(&self, system: &System) -> std::io::Result<()> {
+		if system.step_count().is_multiple_of(self.every) {
+			system.save_checkpoint(&self.path)?;
+		}
+		return Ok(());
+	}
+}
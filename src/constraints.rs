@@ -0,0 +1,366 @@
+//! Holonomic constraints on the distances between particles, for rigid molecules such as SPC/E or TIP3P water
+//!
+//! After the positions are moved by the integrator, they are brought back onto the constraints by forces along the
+//! constrained bonds of the previous positions: with the iterative SHAKE algorithm for independent distances, and with
+//! the analytic SETTLE algorithm for rigid 3-site water molecules. After the second half kick of the momenta, the
+//! relative velocities along the constrained bonds are removed with the iterative RATTLE algorithm.
+//! A particle must not be constrained both by SHAKE and by SETTLE.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	algebra::{Point3, Vector3},
+	periodic_conditions::minimum_image,
+	system::System,
+};
+
+/// The maximum number of iterations of SHAKE and RATTLE, above which the constraints are considered as broken
+const MAX_ITERATIONS: usize = 1000;
+
+/// A fixed distance between 2 particles, kept by SHAKE and RATTLE
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Constraint {
+	/// The indices of the particles
+	pub particles: [usize; 2],
+	/// The distance between the particles, in Å
+	pub length: f64,
+}
+
+/// A rigid 3-site water molecule, kept by SETTLE.
+/// Both hydrogens must have the same mass.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RigidWater {
+	/// The indices of the oxygen and of both hydrogens
+	pub particles: [usize; 3],
+	/// The distance between the oxygen and each hydrogen, in Å
+	pub oh_length: f64,
+	/// The distance between the hydrogens, in Å
+	pub hh_length: f64,
+}
+
+impl RigidWater {
+	/// The SPC/E water model, or SPC since they share the geometry
+	///
+	/// # Arguments
+	///
+	/// * `particles` - The indices of the oxygen and of both hydrogens
+	pub fn spc_e(particles: [usize; 3]) -> Self {
+		Self::with_angle(particles, 1.0, 109.47)
+	}
+
+	/// The TIP3P water model
+	///
+	/// # Arguments
+	///
+	/// * `particles` - The indices of the oxygen and of both hydrogens
+	pub fn tip3p(particles: [usize; 3]) -> Self {
+		Self::with_angle(particles, 0.9572, 104.52)
+	}
+
+	/// A rigid water molecule of the given bond length and H-O-H angle
+	///
+	/// # Arguments
+	///
+	/// * `particles` - The indices of the oxygen and of both hydrogens
+	/// * `oh_length` - The distance between the oxygen and each hydrogen, in Å
+	/// * `angle` - The H-O-H angle, in degrees
+	pub fn with_angle(particles: [usize; 3], oh_length: f64, angle: f64) -> Self {
+		Self {
+			particles,
+			oh_length,
+			hh_length: 2.0 * oh_length * (angle.to_radians() / 2.0).sin(),
+		}
+	}
+
+	/// The 3 constrained pairs of the molecule, with their distance
+	pub fn pairs(&self) -> [Constraint; 3] {
+		let [oxygen, first, second] = self.particles;
+		[
+			Constraint {
+				particles: [oxygen, first],
+				length: self.oh_length,
+			},
+			Constraint {
+				particles: [oxygen, second],
+				length: self.oh_length,
+			},
+			Constraint {
+				particles: [first, second],
+				length: self.hh_length,
+			},
+		]
+	}
+}
+
+/// Move the sites of a rigid water molecule onto the constraints, with the SETTLE algorithm of
+/// Miyamoto and Kollman, J. Comput. Chem. 13 (1992).
+/// The molecule is rotated around its center of mass, which is kept, as the constraint forces along the bonds of the
+/// previous positions would do.
+///
+/// # Arguments
+///
+/// * `old` - The previous positions of the oxygen and of both hydrogens, which satisfy the constraints
+/// * `new` - The positions after the unconstrained move
+/// * `masses` - The masses of the oxygen and of a hydrogen
+/// * `water` - The geometry of the molecule
+fn settle(old: [Vector3; 3], new: [Vector3; 3], (mass_oxygen, mass_hydrogen): (f64, f64), water: &RigidWater) -> [Vector3; 3] {
+	// The canonical triangle, with the center of mass at the origin and the oxygen on the y axis
+	let total_mass = mass_oxygen + 2.0 * mass_hydrogen;
+	let rc = water.hh_length / 2.0;
+	let height = (water.oh_length.powi(2) - rc * rc).sqrt();
+	let ra = 2.0 * mass_hydrogen * height / total_mass;
+	let rb = height - ra;
+
+	let (b0, c0) = (old[1] - old[0], old[2] - old[0]);
+	let center_of_mass = (new[0] * mass_oxygen + (new[1] + new[2]) * mass_hydrogen) / total_mass;
+	let (a1, b1, c1) = (new[0] - center_of_mass, new[1] - center_of_mass, new[2] - center_of_mass);
+
+	// A frame whose z axis is normal to the previous plane of the molecule, and whose y axis is along the oxygen
+	let axis_z = b0.cross(&c0);
+	let axis_x = a1.cross(&axis_z);
+	let axis_y = axis_z.cross(&axis_x);
+	let (axis_x, axis_y, axis_z) = (axis_x / axis_x.norm(), axis_y / axis_y.norm(), axis_z / axis_z.norm());
+	let in_frame = |v: &Vector3| (axis_x.dot(v), axis_y.dot(v), axis_z.dot(v));
+	let (xb0, yb0, _) = in_frame(&b0);
+	let (xc0, yc0, _) = in_frame(&c0);
+	let (_, _, za1) = in_frame(&a1);
+	let (xb1, yb1, zb1) = in_frame(&b1);
+	let (xc1, yc1, zc1) = in_frame(&c1);
+
+	// The canonical triangle tilted out of the plane like the new positions
+	let sin_phi = za1 / ra;
+	let cos_phi = (1.0 - sin_phi * sin_phi).sqrt();
+	let sin_psi = (zb1 - zc1) / (2.0 * rc * cos_phi);
+	let cos_psi = (1.0 - sin_psi * sin_psi).sqrt();
+	let ya2 = ra * cos_phi;
+	let xb2 = -rc * cos_psi;
+	let yb2 = -rb * cos_phi - rc * sin_psi * sin_phi;
+	let yc2 = -rb * cos_phi + rc * sin_psi * sin_phi;
+
+	// Then rotated in the plane, so that the displacements are along the previous bonds
+	let alpha = xb2 * (xb0 - xc0) + yb0 * yb2 + yc0 * yc2;
+	let beta = xb2 * (yc0 - yb0) + xb0 * yb2 + xc0 * yc2;
+	let gamma = xb0 * yb1 - xb1 * yb0 + xc0 * yc1 - xc1 * yc0;
+	let alpha_beta = alpha * alpha + beta * beta;
+	let sin_theta = (alpha * gamma - beta * (alpha_beta - gamma * gamma).sqrt()) / alpha_beta;
+	let cos_theta = (1.0 - sin_theta * sin_theta).sqrt();
+
+	let from_frame = |x: f64, y: f64, z: f64| center_of_mass + axis_x * x + axis_y * y + axis_z * z;
+	return [
+		from_frame(-ya2 * sin_theta, ya2 * cos_theta, za1),
+		from_frame(xb2 * cos_theta - yb2 * sin_theta, xb2 * sin_theta + yb2 * cos_theta, zb1),
+		from_frame(-xb2 * cos_theta - yc2 * sin_theta, -xb2 * sin_theta + yc2 * cos_theta, zc1),
+	];
+}
+
+impl System {
+	/// Whether the topology has constraints to keep
+	pub fn has_constraints(&self) -> bool {
+		!self.topology.constraints.is_empty() || !self.topology.rigid_waters.is_empty()
+	}
+
+	/// Bring the particles back onto the constraints after an unconstrained move of their positions,
+	/// with SETTLE for the rigid water molecules and SHAKE for the other constraints.
+	/// The momenta are corrected by the displacements, so that the positions stay the previous ones plus the momenta
+	/// times the duration of the move.
+	///
+	/// # Arguments
+	///
+	/// * `previous` - The positions before the move, which satisfy the constraints
+	/// * `duration` - The time over which the particles moved, in femtoseconds
+	///
+	/// # Panics
+	///
+	/// If SHAKE does not converge, i.e. the particles moved too much during the time step
+	pub fn constrain_positions(&mut self, previous: &[Point3], duration: f64) {
+		let box_side = self.parameters.box_side;
+		let tolerance = self.parameters.constraint_tolerance;
+		let masses = self.masses();
+		let mass = |particle: &crate::system::Particle| masses[particle.species];
+		let particles = &mut self.particles;
+
+		for water in &self.topology.rigid_waters {
+			let [oxygen, first, second] = water.particles;
+			// The positions relative to the previous position of the oxygen, with the nearest images
+			let origin = previous[oxygen];
+			let old = water.particles.map(|i| minimum_image(previous[i] - origin, box_side));
+			let new_oxygen = minimum_image(particles[oxygen].coordinates - origin, box_side);
+			let new = water
+				.particles
+				.map(|i| new_oxygen + minimum_image(particles[i].coordinates - particles[oxygen].coordinates, box_side));
+
+			let water_masses = water.particles.map(|i| mass(&particles[i]));
+			let settled = settle(old, new, (water_masses[0], water_masses[1]), water);
+			for (a, i) in [oxygen, first, second].into_iter().enumerate() {
+				let displacement = settled[a] - new[a];
+				particles[i].coordinates += displacement;
+				particles[i].momentum += displacement * (water_masses[a] / duration);
+			}
+		}
+
+		for _ in 0..MAX_ITERATIONS {
+			let mut converged = true;
+			for constraint in &self.topology.constraints {
+				let [i, j] = constraint.particles;
+				let bond = minimum_image(particles[i].coordinates - particles[j].coordinates, box_side);
+				let length_squared = constraint.length.powi(2);
+				let difference = length_squared - bond.norm_squared();
+				if difference.abs() <= 2.0 * tolerance * length_squared {
+					continue;
+				}
+				converged = false;
+
+				// Move both particles along the previous bond, inversely to their mass
+				let previous_bond = minimum_image(previous[i] - previous[j], box_side);
+				let (inverse_i, inverse_j) = (1.0 / mass(&particles[i]), 1.0 / mass(&particles[j]));
+				let g = difference / (2.0 * bond.dot(&previous_bond) * (inverse_i + inverse_j));
+				particles[i].coordinates += previous_bond * (g * inverse_i);
+				particles[j].coordinates -= previous_bond * (g * inverse_j);
+				particles[i].momentum += previous_bond * (g / duration);
+				particles[j].momentum -= previous_bond * (g / duration);
+			}
+			if converged {
+				return;
+			}
+		}
+		panic!("SHAKE did not converge in {MAX_ITERATIONS} iterations, the time step may be too large");
+	}
+
+	/// Remove the relative velocities along the constrained bonds with RATTLE, including the ones of the rigid water
+	/// molecules, so that the constrained distances don't change
+	///
+	/// # Panics
+	///
+	/// If RATTLE does not converge
+	pub fn constrain_momenta(&mut self) {
+		let box_side = self.parameters.box_side;
+		let tolerance = self.parameters.constraint_tolerance / self.parameters.delta_time;
+		let masses = self.masses();
+		let constraints: Vec<Constraint> = (self.topology.constraints.iter().copied())
+			.chain(self.topology.rigid_waters.iter().flat_map(RigidWater::pairs))
+			.collect();
+
+		for _ in 0..MAX_ITERATIONS {
+			let mut converged = true;
+			for constraint in &constraints {
+				let [i, j] = constraint.particles;
+				let (mass_i, mass_j) = (masses[self.particles[i].species], masses[self.particles[j].species]);
+				let bond = minimum_image(self.particles[i].coordinates - self.particles[j].coordinates, box_side);
+				let relative_velocity = self.particles[i].momentum / mass_i - self.particles[j].momentum / mass_j;
+				let stretching = bond.dot(&relative_velocity);
+				if stretching.abs() <= tolerance * constraint.length.powi(2) {
+					continue;
+				}
+				converged = false;
+
+				let k = stretching / (bond.norm_squared() * (1.0 / mass_i + 1.0 / mass_j));
+				self.particles[i].momentum -= bond * k;
+				self.particles[j].momentum += bond * k;
+			}
+			if converged {
+				return;
+			}
+		}
+		panic!("RATTLE did not converge in {MAX_ITERATIONS} iterations");
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{parameters::SimulationParameters, topology::Topology};
+
+	/// A rigid water molecule in the plane z = 0, and the same molecule moved and deformed
+	fn water_before_and_after() -> (RigidWater, [Vector3; 3], [Vector3; 3]) {
+		let water = RigidWater::tip3p([0, 1, 2]);
+		let half_angle = (104.52f64 / 2.0).to_radians();
+		let (x, y) = (water.oh_length * half_angle.sin(), water.oh_length * half_angle.cos());
+		let old = [Vector3::zero(), Vector3::from(-x, -y, 0.0), Vector3::from(x, -y, 0.0)];
+		let new = [
+			Vector3::from(0.02, 0.01, -0.03),
+			Vector3::from(-x - 0.05, -y + 0.04, 0.06),
+			Vector3::from(x + 0.03, -y - 0.02, -0.01),
+		];
+		return (water, old, new);
+	}
+
+	/// A system of a single water molecule, whose particles are at the given positions
+	fn water_system(water: RigidWater, positions: [Vector3; 3], topology: Topology) -> System {
+		let parameters = SimulationParameters::from_toml("[species.O]\nmass = 16.0\n[species.H]\nmass = 1.008").unwrap();
+		let mut xyz = "3\nProperties=species:S:1:pos:R:3:velo:R:3\n".to_string();
+		for (name, position) in ["O", "H", "H"].iter().zip(positions) {
+			xyz += &format!("{name} {} {} {} 0 0 0\n", position.x(), position.y(), position.z());
+		}
+		let mut system = System::from_str_with_parameters(&xyz, 0, parameters).unwrap();
+		assert_eq!(water.particles, [0, 1, 2]);
+		system.set_topology(topology).unwrap();
+		return system;
+	}
+
+	#[test]
+	fn settle_keeps_the_geometry_and_the_center_of_mass() {
+		let (water, old, new) = water_before_and_after();
+		let masses = (16.0, 1.008);
+		let settled = settle(old, new, masses, &water);
+
+		assert!(((settled[1] - settled[0]).norm() - water.oh_length).abs() < 1e-12);
+		assert!(((settled[2] - settled[0]).norm() - water.oh_length).abs() < 1e-12);
+		assert!(((settled[2] - settled[1]).norm() - water.hh_length).abs() < 1e-12);
+		let center_of_mass = |positions: [Vector3; 3]| positions[0] * masses.0 + (positions[1] + positions[2]) * masses.1;
+		assert!((center_of_mass(settled) - center_of_mass(new)).norm() < 1e-12);
+	}
+
+	#[test]
+	fn settle_matches_shake() {
+		let (water, old, new) = water_before_and_after();
+		let settled = settle(old, new, (16.0, 1.008), &water);
+
+		// The same molecule, with its 3 distances kept by SHAKE
+		let topology = Topology {
+			constraints: water.pairs().to_vec(),
+			..Default::default()
+		};
+		let mut system = water_system(water, new, topology);
+		system.parameters.constraint_tolerance = 1e-14;
+		system.constrain_positions(&old.map(Vector3::as_point), system.parameters.delta_time);
+		for (particle, expected) in system.particles().iter().zip(settled) {
+			assert!(
+				(particle.coordinates - expected).norm() < 1e-9,
+				"{:?} != {expected:?}",
+				particle.coordinates
+			);
+		}
+	}
+
+	#[test]
+	fn rattle_removes_the_velocities_along_the_bonds() {
+		let (water, old, _) = water_before_and_after();
+		let topology = Topology {
+			rigid_waters: vec![water],
+			..Default::default()
+		};
+		let mut system = water_system(water, old, topology);
+		system.particles[0].momentum = Vector3::from(1.0, -2.0, 0.5);
+		system.particles[1].momentum = Vector3::from(-0.3, 0.1, 0.2);
+		let total_momentum = system
+			.particles()
+			.iter()
+			.fold(Vector3::zero(), |sum, particle| sum + particle.momentum);
+		system.constrain_momenta();
+
+		let masses = system.masses();
+		let velocity = |i: usize| system.particles[i].momentum / masses[system.particles[i].species];
+		for constraint in water.pairs() {
+			let [i, j] = constraint.particles;
+			let bond = system.particles[i].coordinates - system.particles[j].coordinates;
+			assert!(bond.dot(&(velocity(i) - velocity(j))).abs() < 1e-9);
+		}
+		let constrained_momentum = system
+			.particles()
+			.iter()
+			.fold(Vector3::zero(), |sum, particle| sum + particle.momentum);
+		assert!((constrained_momentum - total_momentum).norm() < 1e-12);
+	}
+}
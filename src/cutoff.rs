@@ -0,0 +1,207 @@
+//! Treatments of the cut of the interactions, so that the energy and the force go to 0 continuously at the cut
+//!
+//! Truncating the potential at the cut makes the energy jump each time a pair crosses it, which breaks the
+//! conservation of the energy. The other treatments modify the potential so that it reaches 0 at the cut.
+
+use serde::{Deserialize, Serialize};
+
+use crate::potential::PairPotential;
+
+/// How the potential is modified close to the cut
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Cutoff {
+	/// The potential is used as is, so the energy jumps at the cut
+	#[default]
+	Truncated,
+	/// The potential is shifted by its value at the cut, so the energy is continuous but not the force
+	Shifted,
+	/// The potential is shifted and tilted so that both the energy and the force are 0 at the cut
+	ShiftedForce,
+	/// The potential is multiplied by the CHARMM switching function between `r_switch` and the cut,
+	/// so that both the energy and the force go smoothly to 0, without changing the potential below `r_switch`
+	Switched {
+		/// The distance at which the switching starts, smaller than the cut
+		r_switch: f64,
+	},
+}
+
+/// A pair potential with a [treatment](Cutoff) of its cut.
+/// Beyond the cut there is no interaction, except for [`Cutoff::Truncated`] which leaves the potential unchanged
+/// and lets the pair searches ignore the pairs beyond the cut, like before the treatments existed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CutPotential<P> {
+	/// The potential without treatment
+	pub potential: P,
+	/// The treatment of the cut
+	pub cutoff: Cutoff,
+	/// The distance above which interactions are ignored
+	pub r_cut: f64,
+	/// The energy of the potential at the cut
+	energy_at_cut: f64,
+	/// The force of the potential at the cut, -dU/dr
+	force_at_cut: f64,
+}
+
+impl<P: PairPotential> CutPotential<P> {
+	/// Apply a treatment of the cut to a potential
+	///
+	/// # Arguments
+	///
+	/// * `potential` - The potential without treatment
+	/// * `cutoff` - The treatment of the cut
+	/// * `r_cut` - The distance above which interactions are ignored
+	pub fn new(potential: P, cutoff: Cutoff, r_cut: f64) -> Self {
+		let (energy_at_cut, force_at_cut) = match cutoff {
+			Cutoff::Truncated | Cutoff::Switched { .. } => (0.0, 0.0),
+			Cutoff::Shifted | Cutoff::ShiftedForce => {
+				let (energy, force) = potential.energy_and_force(r_cut * r_cut);
+				(energy, force * r_cut)
+			}
+		};
+		Self {
+			potential,
+			cutoff,
+			r_cut,
+			energy_at_cut,
+			force_at_cut,
+		}
+	}
+
+	/// Apply the same treatment of the cut to another potential
+	///
+	/// # Arguments
+	///
+	/// * `potential` - The potential without treatment
+	pub fn with<Q: PairPotential>(&self, potential: Q) -> CutPotential<Q> {
+		CutPotential::new(potential, self.cutoff, self.r_cut)
+	}
+}
+
+impl<P: PairPotential> PairPotential for CutPotential<P> {
+	fn energy(&self, distance_squared: f64) -> f64 {
+		self.energy_and_force(distance_squared).0
+	}
+
+	fn force(&self, distance_squared: f64) -> f64 {
+		self.energy_and_force(distance_squared).1
+	}
+
+	fn energy_and_force(&self, distance_squared: f64) -> (f64, f64) {
+		let r_cut_squared = self.r_cut * self.r_cut;
+		if self.cutoff != Cutoff::Truncated && distance_squared > r_cut_squared {
+			return (0.0, 0.0);
+		}
+
+		let (energy, force) = self.potential.energy_and_force(distance_squared);
+		match self.cutoff {
+			Cutoff::Truncated => (energy, force),
+			Cutoff::Shifted => (energy - self.energy_at_cut, force),
+			Cutoff::ShiftedForce => {
+				let distance = distance_squared.sqrt();
+				let energy = energy - self.energy_at_cut + (distance - self.r_cut) * self.force_at_cut;
+				(energy, force - self.force_at_cut / distance)
+			}
+			Cutoff::Switched { r_switch } => {
+				let (switch, switch_derivative) = switching(distance_squared, r_switch, self.r_cut);
+				(energy * switch, force * switch - energy * switch_derivative)
+			}
+		}
+	}
+}
+
+/// The CHARMM switching function, 1 below `r_switch` and 0 beyond `r_cut`, with a continuous derivative,
+/// and its derivative with respect to r divided by r
+///
+/// # Arguments
+///
+/// * `distance_squared` - The distance between the particles, squared
+/// * `r_switch` - The distance at which the switching starts
+/// * `r_cut` - The distance at which the switching ends, larger than `r_switch`
+pub(crate) fn switching(distance_squared: f64, r_switch: f64, r_cut: f64) -> (f64, f64) {
+	let (r_switch_squared, r_cut_squared) = (r_switch * r_switch, r_cut * r_cut);
+	if distance_squared <= r_switch_squared {
+		return (1.0, 0.0);
+	}
+	if distance_squared >= r_cut_squared {
+		return (0.0, 0.0);
+	}
+	let denominator = (r_cut_squared - r_switch_squared).powi(3);
+	let to_cut = r_cut_squared - distance_squared;
+	let switch = to_cut * to_cut * (r_cut_squared + 2.0 * distance_squared - 3.0 * r_switch_squared) / denominator;
+	let switch_derivative = 12.0 * to_cut * (r_switch_squared - distance_squared) / denominator;
+	return (switch, switch_derivative);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{assert_approx_eq, potential::LennardJones};
+
+	const R_CUT: f64 = 10.0;
+
+	fn cut_lennard_jones(cutoff: Cutoff) -> CutPotential<LennardJones> {
+		CutPotential::new(LennardJones::new(3.0, 0.2), cutoff, R_CUT)
+	}
+
+	/// Far below the jump of the truncated potential at the cut, which is about 1e-3
+	const CONTINUITY_TOLERANCE: f64 = 1e-10;
+
+	/// The energy and the force (-dU/dr) just below and just above the given distance
+	fn around(potential: &impl PairPotential, r: f64) -> [(f64, f64); 2] {
+		[r - 1e-9, r + 1e-9].map(|r| (potential.energy(r * r), potential.force(r * r) * r))
+	}
+
+	#[test]
+	fn truncated_potential_is_unchanged() {
+		let potential = cut_lennard_jones(Cutoff::Truncated);
+		for r in [3.0, 9.0, 11.0] {
+			assert_eq!(potential.energy(r * r), potential.potential.energy(r * r));
+			assert_eq!(potential.force(r * r), potential.potential.force(r * r));
+		}
+		let [(below, _), (above, _)] = around(&potential, R_CUT);
+		assert!(below.abs() > CONTINUITY_TOLERANCE && above.abs() > CONTINUITY_TOLERANCE);
+	}
+
+	#[test]
+	fn energy_is_continuous_at_the_cut() {
+		for cutoff in [Cutoff::Shifted, Cutoff::ShiftedForce, Cutoff::Switched { r_switch: 8.0 }] {
+			let [(below, _), (above, _)] = around(&cut_lennard_jones(cutoff), R_CUT);
+			assert!(below.abs() < CONTINUITY_TOLERANCE);
+			assert_eq!(above, 0.0);
+		}
+	}
+
+	#[test]
+	fn force_is_continuous_at_the_cut() {
+		for cutoff in [Cutoff::ShiftedForce, Cutoff::Switched { r_switch: 8.0 }] {
+			let [(_, below), (_, above)] = around(&cut_lennard_jones(cutoff), R_CUT);
+			assert!(below.abs() < CONTINUITY_TOLERANCE);
+			assert_eq!(above, 0.0);
+		}
+		// Only the energy is shifted
+		let [(_, below), _] = around(&cut_lennard_jones(Cutoff::Shifted), R_CUT);
+		assert!(below.abs() > CONTINUITY_TOLERANCE);
+	}
+
+	#[test]
+	fn switching_is_continuous_where_it_starts() {
+		let potential = cut_lennard_jones(Cutoff::Switched { r_switch: 8.0 });
+		let [(energy_below, force_below), (energy_above, force_above)] = around(&potential, 8.0);
+		assert!((energy_below - energy_above).abs() < CONTINUITY_TOLERANCE);
+		assert!((force_below - force_above).abs() < CONTINUITY_TOLERANCE);
+		assert_eq!(potential.energy(49.0), potential.potential.energy(49.0));
+	}
+
+	#[test]
+	fn forces_are_the_gradients_of_the_energies() {
+		let h = 1e-6;
+		for cutoff in [Cutoff::Shifted, Cutoff::ShiftedForce, Cutoff::Switched { r_switch: 8.0 }] {
+			let potential = cut_lennard_jones(cutoff);
+			for r in [3.5f64, 7.9, 8.5, 9.9] {
+				let derivative = (potential.energy((r + h).powi(2)) - potential.energy((r - h).powi(2))) / (2.0 * h);
+				assert_approx_eq!(potential.force(r * r), -derivative / r);
+			}
+		}
+	}
+}
@@ -0,0 +1,430 @@
+//! Electrostatics with the Ewald summation, for charged particles in the periodic box
+//!
+//! The Coulomb interaction decays too slowly to be cut, so the sum over all the images is split in 2 sums which
+//! converge quickly: a real space sum of the interactions screened by `erfc(αr)`, cut like the other interactions,
+//! and a reciprocal space sum over the wave vectors of the box of the smooth remainder. The splitting parameter α
+//! trades the cost of one sum for the other, without changing the result.
+//!
+//! The pairs [excluded](crate::topology::Exclusions) by the topology are skipped in real space, but the reciprocal
+//! space sum includes every pair, so the `erf(αr)/r` interaction of the nearest image of each excluded pair is
+//! subtracted from it.
+
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	algebra::{Point3, Vector3},
+	cell_list::CellList,
+	forces::ForceEvaluation,
+	particle_mesh_ewald::ParticleMeshEwald,
+	periodic_conditions::minimum_image,
+	system::System,
+};
+
+/// Coulomb's constant, in kcal.Å/(mol.e²), so that the energy of 2 charges in elementary charges is in kcal/mol
+pub const COULOMB_CONSTANT: f64 = 332.0637;
+/// Relative accuracy of the Ewald sums, unless given
+pub const EWALD_ACCURACY: f64 = 1e-6;
+
+/// How the electrostatic interactions between the charged particles are computed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Electrostatics {
+	/// Ewald summation, in real space and in reciprocal space
+	Ewald(Ewald),
+	/// Smooth Particle Mesh Ewald summation, with the reciprocal space sum evaluated on a grid
+	ParticleMeshEwald(ParticleMeshEwald),
+}
+
+/// The parameters of the Ewald summation
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ewald {
+	/// Relative accuracy of both sums, i.e. the value of the neglected terms: `erfc(α r_cut)` in real space and
+	/// `exp(-k²/4α²)` in reciprocal space
+	pub accuracy: f64,
+	/// Splitting parameter α, in 1/Å, chosen from the accuracy and the cut if absent
+	pub alpha: Option<f64>,
+	/// Number of wave vectors along each direction, on each side of 0, chosen from the accuracy if absent
+	pub k_max: Option<usize>,
+}
+
+impl Default for Ewald {
+	fn default() -> Self {
+		Self {
+			accuracy: EWALD_ACCURACY,
+			alpha: None,
+			k_max: None,
+		}
+	}
+}
+
+impl Ewald {
+	/// The splitting parameter α, so that the real space sum reaches the accuracy at the cut
+	///
+	/// # Arguments
+	///
+	/// * `r_cut` - The distance above which the real space interactions are ignored
+	pub fn alpha(&self, r_cut: f64) -> f64 {
+		self.alpha.unwrap_or_else(|| (-self.accuracy.ln()).sqrt() / r_cut)
+	}
+
+	/// The number of wave vectors along each direction, so that the reciprocal space sum reaches the accuracy
+	///
+	/// # Arguments
+	///
+	/// * `alpha` - The splitting parameter
+	/// * `box_side` - The side of the cubic box
+	pub fn k_max(&self, alpha: f64, box_side: f64) -> usize {
+		// The wave vectors are 2πn/L, and the terms are neglected above k = 2α sqrt(-ln ε)
+		self.k_max
+			.unwrap_or_else(|| (alpha * box_side * (-self.accuracy.ln()).sqrt() / PI).ceil() as usize)
+	}
+}
+
+/// The complementary error function, `1 - erf(x)`, accurate to a few ulps
+///
+/// # Arguments
+///
+/// * `x` - Where the function is evaluated
+pub(crate) fn erfc(x: f64) -> f64 {
+	if x < 0.0 {
+		return 2.0 - erfc(-x);
+	}
+
+	if x < 2.0 {
+		// Series of erf(x) = 2/√π exp(-x²) Σ 2ⁿ x²ⁿ⁺¹ / (1.3...(2n+1)), whose terms are all positive
+		let (mut term, mut sum) = (x, x);
+		let mut n = 0.0;
+		while term > 1e-17 * sum {
+			term *= 2.0 * x * x / (2.0 * n + 3.0);
+			sum += term;
+			n += 1.0;
+		}
+		return 1.0 - 2.0 / PI.sqrt() * (-x * x).exp() * sum;
+	}
+
+	// Continued fraction erfc(x) = exp(-x²)/√π / (x + (1/2)/(x + 1/(x + (3/2)/(x + ...)))), evaluated from its end
+	let mut fraction = x;
+	for n in (1..=60).rev() {
+		fraction = x + (n as f64 / 2.0) / fraction;
+	}
+	return (-x * x).exp() / (PI.sqrt() * fraction);
+}
+
+impl System {
+	/// Evaluate the electrostatic forces, energy and virial with the configured [method](Electrostatics),
+	/// or `None` if the electrostatics are disabled
+	pub fn evaluate_electrostatics(&self) -> Option<ForceEvaluation> {
+		let electrostatics = self.parameters.electrostatics.as_ref()?;
+		return Some(match electrostatics {
+			Electrostatics::Ewald(ewald) => self.evaluate_ewald(ewald),
+			Electrostatics::ParticleMeshEwald(pme) => self.evaluate_particle_mesh_ewald(pme),
+		});
+	}
+
+	/// Evaluate the electrostatic forces, energy and virial with the Ewald summation.
+	/// Like [`System::energy_gradient`], the evaluation holds the [gradients](ForceEvaluation::gradients) of the energy.
+	///
+	/// # Arguments
+	///
+	/// * `ewald` - The parameters of the summation
+	pub fn evaluate_ewald(&self, ewald: &Ewald) -> ForceEvaluation {
+		let alpha = ewald.alpha(self.parameters.r_cut);
+		let mut evaluation = self.ewald_real_space(alpha);
+		evaluation += &self.ewald_reciprocal_space(alpha, ewald.k_max(alpha, self.parameters.box_side));
+		evaluation += &self.ewald_corrections(alpha);
+		evaluation += &self.ewald_exclusions(alpha);
+		return evaluation;
+	}
+
+	/// Evaluate the real space part of the Ewald summation, i.e. the Coulomb interactions screened by `erfc(αr)`
+	/// between the pairs closer than the cut, except the pairs [excluded](crate::topology::Exclusions) by the topology
+	///
+	/// # Arguments
+	///
+	/// * `alpha` - The splitting parameter
+	pub fn ewald_real_space(&self, alpha: f64) -> ForceEvaluation {
+		let mut evaluation = ForceEvaluation::zero(self.nb_particles_total());
+		let cell_list = CellList::new(&self.particles, self.parameters.box_side, self.parameters.r_cut);
+		self.for_each_pair_in_cut(&cell_list, |i, j, displacement| {
+			if self.exclusions.contains(i, j) {
+				return;
+			}
+			let charges = COULOMB_CONSTANT * self.particles[i].charge * self.particles[j].charge;
+			let distance_squared = displacement.norm_squared();
+			let distance = distance_squared.sqrt();
+			let energy = charges * erfc(alpha * distance) / distance;
+			// -dU/dr divided by r
+			let force =
+				(energy + charges * 2.0 * alpha / PI.sqrt() * (-alpha * alpha * distance_squared).exp()) / distance_squared;
+
+			let gradient = displacement * -force;
+			evaluation.gradients[i] += gradient;
+			evaluation.gradients[j] -= gradient;
+			evaluation.energy += energy;
+			evaluation.virial += force * distance_squared;
+		});
+		return evaluation;
+	}
+
+	/// Evaluate the reciprocal space part of the Ewald summation, over the wave vectors `2πn/L` with each component of
+	/// `n` between `-k_max` and `k_max`. Since the terms of `k` and `-k` are the same, only half of them are computed.
+	///
+	/// # Arguments
+	///
+	/// * `alpha` - The splitting parameter
+	/// * `k_max` - The number of wave vectors along each direction, on each side of 0
+	pub fn ewald_reciprocal_space(&self, alpha: f64, k_max: usize) -> ForceEvaluation {
+		let nb_particles = self.nb_particles_total();
+		let box_side = self.parameters.box_side;
+		let volume = box_side.powi(3);
+		let mut evaluation = ForceEvaluation::zero(nb_particles);
+
+		// exp(2iπn x/L) for each particle, each direction and each n in [0, k_max], as (cos, sin)
+		let k_max = k_max as isize;
+		let phases = |coordinate: fn(&Point3) -> f64| -> Vec<Vec<(f64, f64)>> {
+			self.particles
+				.iter()
+				.map(|particle| {
+					let angle = 2.0 * PI * coordinate(&particle.coordinates) / box_side;
+					(0..=k_max).map(|n| ((n as f64 * angle).cos(), (n as f64 * angle).sin())).collect()
+				})
+				.collect()
+		};
+		let (phases_x, phases_y, phases_z) = (phases(Point3::x), phases(Point3::y), phases(Point3::z));
+		let phase = |phases: &Vec<(f64, f64)>, n: isize| {
+			let (cos, sin) = phases[n.unsigned_abs()];
+			(cos, sin * n.signum() as f64)
+		};
+
+		let k_cut_squared = (2.0 * PI * k_max as f64 / box_side).powi(2);
+		let mut terms = vec![(0.0, 0.0); nb_particles];
+		for nx in 0..=k_max {
+			for ny in -k_max..=k_max {
+				for nz in -k_max..=k_max {
+					// Half of the wave vectors, without 0
+					if nx == 0 && (ny < 0 || (ny == 0 && nz <= 0)) {
+						continue;
+					}
+					let k = Vector3::from(nx as f64, ny as f64, nz as f64) * (2.0 * PI / box_side);
+					let k_squared = k.norm_squared();
+					if k_squared > k_cut_squared {
+						continue;
+					}
+
+					// The structure factor S(k) = Σ q exp(ik.r)
+					let (mut s_cos, mut s_sin) = (0.0, 0.0);
+					for (i, particle) in self.particles.iter().enumerate() {
+						let (cos_x, sin_x) = phase(&phases_x[i], nx);
+						let (cos_y, sin_y) = phase(&phases_y[i], ny);
+						let (cos_z, sin_z) = phase(&phases_z[i], nz);
+						let (cos_xy, sin_xy) = (cos_x * cos_y - sin_x * sin_y, sin_x * cos_y + cos_x * sin_y);
+						let (cos, sin) = (cos_xy * cos_z - sin_xy * sin_z, sin_xy * cos_z + cos_xy * sin_z);
+						terms[i] = (cos, sin);
+						s_cos += particle.charge * cos;
+						s_sin += particle.charge * sin;
+					}
+
+					// Both k and -k
+					let factor = 4.0 * PI * COULOMB_CONSTANT / volume * (-k_squared / (4.0 * alpha * alpha)).exp()
+						/ k_squared;
+					let energy = factor * (s_cos * s_cos + s_sin * s_sin);
+					evaluation.energy += energy;
+					// The derivative of the energy with respect to a scaling of the box
+					evaluation.virial += energy * (1.0 - k_squared / (2.0 * alpha * alpha));
+					for (i, particle) in self.particles.iter().enumerate() {
+						let (cos, sin) = terms[i];
+						evaluation.gradients[i] +=
+							k * (2.0 * factor * particle.charge * (cos * s_sin - sin * s_cos));
+					}
+				}
+			}
+		}
+		return evaluation;
+	}
+
+	/// Evaluate the constant terms of the Ewald summation: the interaction of each charge with its own screening
+	/// charge, and the interaction with a uniform background which neutralizes the box if it is charged
+	///
+	/// # Arguments
+	///
+	/// * `alpha` - The splitting parameter
+	pub fn ewald_corrections(&self, alpha: f64) -> ForceEvaluation {
+		let mut evaluation = ForceEvaluation::zero(self.nb_particles_total());
+		let sum_of_squares: f64 = self.particles.iter().map(|particle| particle.charge.powi(2)).sum();
+		let net_charge: f64 = self.particles.iter().map(|particle| particle.charge).sum();
+
+		let self_energy = -COULOMB_CONSTANT * alpha / PI.sqrt() * sum_of_squares;
+		let background_energy =
+			-COULOMB_CONSTANT * PI * net_charge.powi(2) / (2.0 * self.parameters.box_side.powi(3) * alpha * alpha);
+		evaluation.energy = self_energy + background_energy;
+		// The background energy scales as the inverse of the volume
+		evaluation.virial = 3.0 * background_energy;
+		return evaluation;
+	}
+
+	/// Evaluate the opposite of the interactions of the pairs [excluded](crate::topology::Exclusions) by the topology
+	/// in the reciprocal space sum, i.e. `-q q' erf(αr)/r` for the nearest image of each excluded pair
+	///
+	/// # Arguments
+	///
+	/// * `alpha` - The splitting parameter
+	pub fn ewald_exclusions(&self, alpha: f64) -> ForceEvaluation {
+		let mut evaluation = ForceEvaluation::zero(self.nb_particles_total());
+		let box_side = self.parameters.box_side;
+		for i in 0..self.nb_particles_total() {
+			for &j in self.exclusions.of(i).iter().filter(|&&j| j > i) {
+				let displacement = minimum_image(self.particles[i].coordinates - self.particles[j].coordinates, box_side);
+				let charges = COULOMB_CONSTANT * self.particles[i].charge * self.particles[j].charge;
+				let distance_squared = displacement.norm_squared();
+				let distance = distance_squared.sqrt();
+				let energy = -charges * (1.0 - erfc(alpha * distance)) / distance;
+				// -dU/dr divided by r
+				let force = (energy + charges * 2.0 * alpha / PI.sqrt() * (-alpha * alpha * distance_squared).exp())
+					/ distance_squared;
+
+				let gradient = displacement * -force;
+				evaluation.gradients[i] += gradient;
+				evaluation.gradients[j] -= gradient;
+				evaluation.energy += energy;
+				evaluation.virial += force * distance_squared;
+			}
+		}
+		return evaluation;
+	}
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use super::*;
+	use crate::{parameters::SimulationParameters, system::Particle, topology::Topology};
+
+	#[test]
+	fn erfc_matches_known_values() {
+		for (x, expected) in [
+			(0.0, 1.0),
+			(0.5, 0.4795001221869535),
+			(1.0, 0.15729920705028513),
+			(2.0, 0.004677734981047266),
+			(3.0, 2.209049699858544e-05),
+			(-1.0, 1.8427007929497148),
+		] {
+			assert!((erfc(x) - expected).abs() <= 1e-14 * expected, "erfc({x}) = {}", erfc(x));
+		}
+	}
+
+	/// A few charges in a small box, neutral unless the charges say otherwise
+	fn charged_system(charges: &[f64]) -> System {
+		let mut parameters = SimulationParameters {
+			box_side: 12.0,
+			r_cut: 5.5,
+			..Default::default()
+		};
+		parameters.electrostatics = Some(Electrostatics::Ewald(Ewald::default()));
+		let coordinates = [
+			(0.3, 0.1, -0.4),
+			(2.5, -1.7, 0.9),
+			(-3.1, 4.2, 2.2),
+			(5.1, -5.2, -4.8),
+			(-0.9, -3.3, 3.7),
+		];
+		let mut xyz = format!("{}\nProperties=species:S:1:pos:R:3:charge:R:1\n", charges.len());
+		for (charge, (x, y, z)) in charges.iter().zip(coordinates) {
+			xyz.push_str(&format!("Q {x} {y} {z} {charge}\n"));
+		}
+		return System::from_str_with_parameters(&xyz, 0, parameters).unwrap();
+	}
+
+	/// Check the electrostatic forces computed with the given method against finite differences of the energy
+	pub(crate) fn assert_forces_are_the_gradients(system: &System, electrostatics: Electrostatics) {
+		let mut system = system.clone();
+		system.parameters.electrostatics = Some(electrostatics);
+		let evaluation = system.evaluate_electrostatics().unwrap();
+
+		let h = 1e-5;
+		for i in 0..system.nb_particles_total() {
+			let energy_moved = |displacement: Vector3| {
+				let mut moved = system.clone();
+				moved.particles[i] = Particle {
+					coordinates: (moved.particles[i].coordinates + displacement).as_point(),
+					..moved.particles[i]
+				};
+				moved.evaluate_electrostatics().unwrap().energy
+			};
+			let numerical = Vector3::from(
+				energy_moved(Vector3::from(h, 0.0, 0.0)) - energy_moved(Vector3::from(-h, 0.0, 0.0)),
+				energy_moved(Vector3::from(0.0, h, 0.0)) - energy_moved(Vector3::from(0.0, -h, 0.0)),
+				energy_moved(Vector3::from(0.0, 0.0, h)) - energy_moved(Vector3::from(0.0, 0.0, -h)),
+			) / (2.0 * h);
+			assert!(
+				(evaluation.gradients[i] - numerical).norm() < 1e-4,
+				"particle {i}: {:?} != {numerical:?}",
+				evaluation.gradients[i]
+			);
+		}
+	}
+
+	#[test]
+	fn forces_are_the_gradients_of_the_energy() {
+		for charges in [[1.0, -1.0, 0.5, -0.5, 0.0], [1.0, 1.0, -0.5, 0.8, 0.2]] {
+			assert_forces_are_the_gradients(&charged_system(&charges), Electrostatics::Ewald(Ewald::default()));
+		}
+
+		// The corrections of the excluded pairs too
+		let mut system = charged_system(&[1.0, -1.0, 0.5, -0.5, 0.0]);
+		let topology = Topology {
+			extra_exclusions: vec![[0, 1], [1, 4]],
+			..Default::default()
+		};
+		system.set_topology(topology).unwrap();
+		assert_forces_are_the_gradients(&system, Electrostatics::Ewald(Ewald::default()));
+	}
+
+	#[test]
+	fn energy_does_not_depend_on_the_splitting() {
+		let system = charged_system(&[1.0, -1.0, 0.5, -0.5, 0.3]);
+		let energies = [0.8, 0.9, 1.0].map(|alpha| {
+			let ewald = Ewald {
+				alpha: Some(alpha),
+				accuracy: 1e-8,
+				..Default::default()
+			};
+			system.evaluate_ewald(&ewald).energy
+		});
+		assert!((energies[0] - energies[1]).abs() < 1e-6 * energies[0].abs());
+		assert!((energies[0] - energies[2]).abs() < 1e-6 * energies[0].abs());
+	}
+
+	#[test]
+	fn virial_is_the_derivative_of_the_energy_under_scaling() {
+		for extra_exclusions in [vec![], vec![[0, 1], [1, 4]]] {
+			let mut system = charged_system(&[1.0, -1.0, 0.5, -0.5, 0.3]);
+			system.set_topology(Topology {
+				extra_exclusions,
+				..Default::default()
+			})
+			.unwrap();
+			let ewald = Ewald {
+				alpha: Some(0.6),
+				k_max: Some(8),
+				..Default::default()
+			};
+			let virial = system.evaluate_ewald(&ewald).virial;
+
+			let scaled_energy = |factor: f64| {
+				let mut scaled = system.clone();
+				scaled.parameters.box_side *= factor;
+				for particle in scaled.particles.iter_mut() {
+					let (x, y, z) = particle.xyz();
+					particle.coordinates = Point3::from(x * factor, y * factor, z * factor);
+				}
+				scaled.evaluate_ewald(&ewald).energy
+			};
+			let h = 1e-6;
+			let derivative = (scaled_energy(1.0 + h) - scaled_energy(1.0 - h)) / (2.0 * h);
+			assert!((virial + derivative).abs() <= 1e-5 * virial.abs().max(1.0));
+		}
+	}
+}
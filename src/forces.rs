@@ -0,0 +1,215 @@
+//! Evaluation of the forces, the energy and the virial in a single pass over the pairs of particles
+//!
+//! Each pair is visited once, and its force is added to the first particle and subtracted from the second one
+//! (Newton's third law), so that the memory is linear in the number of particles.
+
+use std::ops::AddAssign;
+
+use crate::{
+	algebra::Vector3,
+	cell_list::CellList,
+	cutoff::{CutPotential, Cutoff},
+	parameters::PairSearch,
+	periodic_conditions::neighboring_3d_translations,
+	potential::{PairPotential, Potential},
+	species::Interactions,
+	system::System,
+	verlet_list::VerletList,
+};
+
+/// The result of the evaluation of the interactions between the particles of a [system](System)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForceEvaluation {
+	/// The gradient of the energy with respect to the position of each particle, like [`System::energy_gradient`].
+	/// The force applied to the particle is its opposite, given by [`ForceEvaluation::forces`].
+	pub gradients: Vec<Vector3>,
+	/// The microscopic (potential) energy of the system
+	pub energy: f64,
+	/// The virial of the interactions, i.e. the sum over the pairs of the displacement between the particles
+	/// times the physical force between them, used to compute the pressure
+	pub virial: f64,
+}
+
+impl ForceEvaluation {
+	/// An evaluation without any interaction
+	///
+	/// # Arguments
+	///
+	/// * `nb_particles` - The number of particles of the system
+	pub fn zero(nb_particles: usize) -> Self {
+		Self {
+			gradients: vec![Vector3::zero(); nb_particles],
+			energy: 0.0,
+			virial: 0.0,
+		}
+	}
+
+	/// The force applied to each particle, F = -∇U, opposite to its [gradient](ForceEvaluation::gradients)
+	pub fn forces(&self) -> Vec<Vector3> {
+		self.gradients.iter().map(|gradient| *gradient * -1.0).collect()
+	}
+}
+
+/// Add the interactions of another evaluation of the same system, e.g. the electrostatic ones
+impl AddAssign<&ForceEvaluation> for ForceEvaluation {
+	fn add_assign(&mut self, rhs: &ForceEvaluation) {
+		for (gradient, other) in self.gradients.iter_mut().zip(&rhs.gradients) {
+			*gradient += *other;
+		}
+		self.energy += rhs.energy;
+		self.virial += rhs.virial;
+	}
+}
+
+impl System {
+	/// Evaluate the interactions of the pairs visited by the given function, which must visit each pair once.
+	/// The pairs [excluded](crate::topology::Exclusions) by the topology are skipped.
+	///
+	/// # Arguments
+	///
+	/// * `potential` - The interaction between the particles of a pair, which may depend on their species
+	/// * `for_each_pair` - Calls its argument on each pair of particles, with the displacement from the second to the first
+	pub(crate) fn accumulate_forces(
+		&self, potential: &impl Interactions, for_each_pair: impl FnOnce(&mut dyn FnMut(usize, usize, Vector3)),
+	) -> ForceEvaluation {
+		let mut evaluation = ForceEvaluation::zero(self.nb_particles_total());
+		for_each_pair(&mut |i, j, displacement| {
+			if self.exclusions.contains(i, j) {
+				return;
+			}
+			let pair_potential = potential.between(self.particles[i].species, self.particles[j].species);
+			let (energy, force) = pair_potential.energy_and_force(displacement.norm_squared());
+			let gradient = displacement * -force;
+			evaluation.gradients[i] += gradient;
+			evaluation.gradients[j] -= gradient;
+			evaluation.energy += energy;
+			// The physical force applied on the first particle is the opposite of the gradient
+			evaluation.virial -= displacement.dot(&gradient);
+		});
+		return evaluation;
+	}
+
+	/// Evaluate the forces, the energy and the virial with periodic conditions, using the configured
+	/// [pair search](PairSearch) and [potential](System::potential), plus the
+	/// [electrostatic interactions](System::evaluate_electrostatics) if enabled and the
+	/// [bonded interactions](System::evaluate_bonded) of the topology.
+	/// With the `parallel` feature, the particles are split between threads.
+	pub fn evaluate_forces(&self) -> ForceEvaluation {
+		let mut evaluation = self.evaluate_pair_forces();
+		if let Some(electrostatics) = self.evaluate_electrostatics() {
+			evaluation += &electrostatics;
+		}
+		if let Some(bonded) = self.evaluate_bonded() {
+			evaluation += &bonded;
+		}
+		return evaluation;
+	}
+
+	/// Evaluate the forces, the energy and the virial of the [potential](System::potential) between the pairs
+	fn evaluate_pair_forces(&self) -> ForceEvaluation {
+		if let Some(lennard_jones) = self.lennard_jones_pairs() {
+			return match self.parameters.cutoff {
+				Cutoff::Truncated => self.evaluate_forces_with(&lennard_jones),
+				cutoff => self.evaluate_forces_with(
+					&lennard_jones.map(|pair| CutPotential::new(*pair, cutoff, self.parameters.r_cut)),
+				),
+			};
+		}
+
+		// Dispatch once here, so that the pair loops are specialized for each potential
+		match self.potential().potential {
+			Potential::LennardJones(potential) => self.evaluate_forces_with_cutoff(potential),
+			Potential::Morse(potential) => self.evaluate_forces_with_cutoff(potential),
+			Potential::Buckingham(potential) => self.evaluate_forces_with_cutoff(potential),
+			Potential::Mie(potential) => self.evaluate_forces_with_cutoff(potential),
+			Potential::WeeksChandlerAndersen(potential) => self.evaluate_forces_with_cutoff(potential),
+			Potential::Yukawa(potential) => self.evaluate_forces_with_cutoff(potential),
+			Potential::Tabulated(potential) => self.evaluate_forces_with_cutoff(potential),
+		}
+	}
+
+	/// Evaluate the forces, the energy and the virial of the given potential with the configured treatment of the cut.
+	/// A truncated potential is used as is, so that its pair loops are not slowed down.
+	///
+	/// # Arguments
+	///
+	/// * `potential` - The interaction between the particles of a pair, without treatment of the cut
+	fn evaluate_forces_with_cutoff<P: PairPotential + Sync>(&self, potential: P) -> ForceEvaluation {
+		match self.parameters.cutoff {
+			Cutoff::Truncated => self.evaluate_forces_with(&potential),
+			cutoff => self.evaluate_forces_with(&CutPotential::new(potential, cutoff, self.parameters.r_cut)),
+		}
+	}
+
+	/// Evaluate the forces, the energy and the virial with periodic conditions, using the configured
+	/// [pair search](PairSearch) and the given potential.
+	/// With the `parallel` feature, the particles are split between threads.
+	///
+	/// # Arguments
+	///
+	/// * `potential` - The interaction between the particles of a pair, which may depend on their species
+	pub fn evaluate_forces_with<I: Interactions + Sync>(&self, potential: &I) -> ForceEvaluation {
+		#[cfg(feature = "parallel")]
+		return self.evaluate_forces_parallel(potential);
+		#[cfg(not(feature = "parallel"))]
+		return self.evaluate_forces_serial(potential);
+	}
+
+	/// Evaluate the forces, the energy and the virial with periodic conditions in a single pass over the pairs,
+	/// using the configured [pair search](PairSearch), on the current thread.
+	///
+	/// # Arguments
+	///
+	/// * `potential` - The interaction between the particles of a pair, which may depend on their species
+	pub fn evaluate_forces_serial(&self, potential: &impl Interactions) -> ForceEvaluation {
+		let box_side = self.parameters.box_side;
+		let radius_cut = self.parameters.r_cut;
+		match self.parameters.pair_search {
+			PairSearch::Images => {
+				let translations = neighboring_3d_translations(box_side);
+				self.accumulate_forces(potential, |f| self.for_each_pair_in_images(&translations, radius_cut, f))
+			}
+			PairSearch::MinimumImage => self.accumulate_forces(potential, |f| self.for_each_pair_minimum_image(radius_cut, f)),
+			PairSearch::Vectorized => self.evaluate_forces_vectorized(potential),
+			PairSearch::CellList => {
+				let cell_list = CellList::new(&self.particles, box_side, radius_cut);
+				self.accumulate_forces(potential, |f| self.for_each_pair_in_cut(&cell_list, f))
+			}
+			PairSearch::VerletList => match &self.verlet_list {
+				Some(verlet_list) if verlet_list.is_valid_for(self) => {
+					self.accumulate_forces(potential, |f| self.for_each_pair_in_verlet_list(verlet_list, f))
+				}
+				_ => {
+					let verlet_list = VerletList::new(self, self.parameters.verlet_skin);
+					self.accumulate_forces(potential, |f| self.for_each_pair_in_verlet_list(&verlet_list, f))
+				}
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::algebra::Point3;
+
+	#[test]
+	fn virial_is_the_derivative_of_the_energy_under_scaling() {
+		let system = System::from_file(std::path::Path::new("dataset/particles.xyz"), 0).unwrap();
+		let evaluation = system.evaluate_forces();
+
+		// Scaling the whole system by a factor λ, the virial is -dU/dλ at λ = 1
+		let scaled_energy = |factor: f64| {
+			let mut scaled = system.clone();
+			scaled.parameters.box_side *= factor;
+			for particle in scaled.particles.iter_mut() {
+				let (x, y, z) = particle.xyz();
+				particle.coordinates = Point3::from(x * factor, y * factor, z * factor);
+			}
+			scaled.evaluate_forces().energy
+		};
+		let h = 1e-6;
+		let derivative = (scaled_energy(1.0 + h) - scaled_energy(1.0 - h)) / (2.0 * h);
+		assert!((evaluation.virial + derivative).abs() <= 1e-4 * evaluation.virial.abs());
+	}
+}
@@ -0,0 +1,125 @@
+//! Integration of Newton's equations of motion over a time step
+//!
+//! The [integrators](Integrator) move the particles under the [forces](crate::forces::ForceEvaluation::forces)
+//! F = -∇U, opposite to the gradients of the energy given by [`System::evaluate_forces`]. The momenta are in amu.Å/fs
+//! and the forces in kcal/(mol.Å), so a kick of the momenta during dt is `F dt conversion_force`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	algebra::{Point3, Vector3},
+	respa::Respa,
+	system::System,
+};
+
+/// A scheme which advances the particles of a [system](System) by a time step of `delta_time`
+pub trait Integrator {
+	/// Move the particles and update their momenta over a time step, keeping the constraints of the topology
+	fn integrate(&self, system: &mut System);
+}
+
+/// Velocity Verlet: a half kick of the momenta, a drift of the positions, and a half kick with the new forces.
+/// The momenta and the positions are known at the same time, and the constrained momenta are kept by RATTLE.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct VelocityVerlet;
+
+impl Integrator for VelocityVerlet {
+	fn integrate(&self, system: &mut System) {
+		let delta_time = system.parameters.delta_time;
+
+		// p(t + dt/2) = p(t) + F(t) dt/2
+		let forces = system.forces_for_step();
+		system.kick(&forces, 0.5 * delta_time);
+		// r(t + dt) = r(t) + p(t + dt/2) / m dt, then SHAKE and SETTLE back onto the constraints
+		system.drift(delta_time);
+		// p(t + dt) = p(t + dt/2) + F(t + dt) dt/2, then RATTLE
+		let forces = system.forces_for_step();
+		system.kick(&forces, 0.5 * delta_time);
+		if system.has_constraints() {
+			system.constrain_momenta();
+		}
+	}
+}
+
+/// Leapfrog: a full kick of the momenta followed by a drift of the positions, with a single evaluation of the forces.
+/// The momenta are kept half a time step behind the positions: when they are at the time of the positions, e.g. after
+/// their initialization, the first kick only lasts half a time step.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Leapfrog;
+
+impl Integrator for Leapfrog {
+	fn integrate(&self, system: &mut System) {
+		let delta_time = system.parameters.delta_time;
+
+		// p(t + dt/2) = p(t - dt/2) + F(t) dt, or p(t) + F(t) dt/2 at the first step
+		let forces = system.forces_for_step();
+		let duration = if system.half_step_momenta { delta_time } else { 0.5 * delta_time };
+		system.kick(&forces, duration);
+		system.half_step_momenta = true;
+		// r(t + dt) = r(t) + p(t + dt/2) / m dt, then SHAKE and SETTLE back onto the constraints
+		system.drift(delta_time);
+	}
+}
+
+/// Which [integrator](Integrator) advances the system at each step
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Integration {
+	/// [Velocity Verlet](VelocityVerlet)
+	#[default]
+	VelocityVerlet,
+	/// [Leapfrog]
+	Leapfrog,
+	/// [Multiple time steps](Respa), with the fast forces integrated with a smaller time step
+	Respa(Respa),
+}
+
+impl Integrator for Integration {
+	fn integrate(&self, system: &mut System) {
+		match self {
+			Integration::VelocityVerlet => VelocityVerlet.integrate(system),
+			Integration::Leapfrog => Leapfrog.integrate(system),
+			Integration::Respa(respa) => respa.integrate(system),
+		}
+	}
+}
+
+impl System {
+	/// Update the Verlet list if needed, then compute the force applied to each particle
+	pub fn forces_for_step(&mut self) -> Vec<Vector3> {
+		self.update_verlet_list();
+		return self.forces_per_particle();
+	}
+
+	/// Update the momenta of the particles under constant forces during a time
+	///
+	/// # Arguments
+	///
+	/// * `forces` - The force applied to each particle, in kcal/(mol.Å)
+	/// * `duration` - The duration of the kick, in femtoseconds
+	pub fn kick(&mut self, forces: &[Vector3], duration: f64) {
+		let conversion_force = self.parameters.conversion_force;
+		for (particle, force) in self.particles.iter_mut().zip(forces) {
+			particle.momentum += *force * duration * conversion_force;
+		}
+	}
+
+	/// Move the particles at constant momenta during a time, then bring them back onto the constraints.
+	/// The [structure of arrays](crate::particle_arrays::ParticleArrays) of the system follows the new positions.
+	///
+	/// # Arguments
+	///
+	/// * `duration` - The duration of the drift, in femtoseconds
+	pub fn drift(&mut self, duration: f64) {
+		let masses = self.masses();
+		let previous_positions: Vec<Point3> = self.particles.iter().map(|p| p.coordinates).collect();
+		for p in self.particles.iter_mut() {
+			let velocity = p.momentum / masses[p.species];
+			p.coordinates = (p.coordinates + velocity * duration).as_point();
+		}
+		if self.has_constraints() {
+			self.constrain_positions(&previous_positions, duration);
+		}
+		self.update_particle_arrays();
+	}
+}
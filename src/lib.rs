@@ -1,5 +1,25 @@
 pub mod algebra;
+pub mod cell_list;
+pub mod checkpoint;
+pub mod constraints;
+pub mod cutoff;
+pub mod ewald;
+pub mod forces;
+pub mod integrator;
 pub mod movement;
+#[cfg(feature = "parallel")]
+pub mod parallel;
 pub mod parameters;
+pub mod particle_arrays;
+pub mod particle_mesh_ewald;
 pub mod periodic_conditions;
+pub mod potential;
+pub mod respa;
+pub mod species;
 pub mod system;
+pub mod tabulated;
+pub mod tail_corrections;
+pub mod topology;
+pub mod trajectory;
+pub mod verlet_list;
+pub mod xyz;
@@ -1,7 +1,17 @@
-use mlom::system::System;
+use mlom::{checkpoint::Checkpointer, parameters::SimulationParameters, system::System, trajectory::TrajectoryWriter};
 use std::path::Path;
 
 fn main() {
-	let mut system = System::from_file(Path::new("dataset/particles.xyz"), 0);
-	system.energy_evolution(1000, "plots/jvaismetuer.png");
+	// An optional TOML or JSON file can be given to override the default parameters
+	let parameters = match std::env::args().nth(1) {
+		Some(path) => SimulationParameters::from_file(Path::new(&path)).unwrap_or_else(|error| panic!("{error}")),
+		None => SimulationParameters::default(),
+	};
+
+	let mut system = System::from_file_with_parameters(Path::new("dataset/particles.xyz"), 0, parameters)
+		.unwrap_or_else(|error| panic!("{error}"));
+	let mut trajectory = TrajectoryWriter::create(Path::new("plots/trajectory.xyz"), 10).unwrap();
+	let checkpointer = (system.parameters().checkpoint_every).map(|every| Checkpointer::new(Path::new("plots/checkpoint.bin"), every));
+	system.energy_evolution(1000, "plots/jvaismetuer.png", Some(&mut trajectory), checkpointer.as_ref())
+		.unwrap();
 }
@@ -1,11 +1,53 @@
-use crate::{algebra::Vector3, parameters::*, periodic_conditions::neighboring_3d_translations, system::System};
+use std::io::Write;
+
+use rand::Rng;
+
+use crate::{
+	algebra::Vector3,
+	cell_list::CellList,
+	checkpoint::Checkpointer,
+	integrator::Integrator,
+	parameters::{MomentumDistribution, PairSearch},
+	periodic_conditions::{minimum_image, neighboring_3d_translations},
+	system::System,
+	trajectory::TrajectoryWriter,
+};
 use plotters::prelude::*;
 use plotters::prelude::{RED, WHITE};
 
+/// The contributions to the energy of a [system](System), and its pressure, in kcal/mol and kcal/(mol.Å³)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnergyBreakdown {
+	/// The kinetic energy of the particles
+	pub kinetic_energy: f64,
+	/// The temperature, in Kelvin
+	pub temperature: f64,
+	/// The microscopic energy of the pairs closer than the cut
+	pub potential_energy: f64,
+	/// The correction of the energy for the pairs beyond the cut, 0 without tail corrections
+	pub tail_energy: f64,
+	/// The pressure from the virial of the pairs closer than the cut
+	pub pressure: f64,
+	/// The correction of the pressure for the pairs beyond the cut, 0 without tail corrections
+	pub tail_pressure: f64,
+}
+
+impl EnergyBreakdown {
+	/// The total energy, including the tail correction
+	pub fn total_energy(&self) -> f64 {
+		self.kinetic_energy + self.potential_energy + self.tail_energy
+	}
+
+	/// The total pressure, including the tail correction
+	pub fn total_pressure(&self) -> f64 {
+		self.pressure + self.tail_pressure
+	}
+}
+
 impl System {
-	/// Compute the degrees of liberty of the system. Noted N_dl
+	/// Compute the degrees of liberty of the system, without the ones removed by the constraints. Noted N_dl
 	pub fn degrees_of_liberty(&self) -> f64 {
-		(3 * self.nb_particles_total() - 3) as f64
+		(3 * self.nb_particles_total() - 3 - self.topology.nb_constraints()) as f64
 	}
 
 	/// Calibrate the kinetic momentum of the particles to have the desired temperature
@@ -16,7 +58,7 @@ impl System {
 		}
 
 		// Desired kinetic energy: K_desired = (N_dl / 2) * k_B * T0
-		let desired_ke = 0.5 * self.degrees_of_liberty() * R_CONSTANT * T_0;
+		let desired_ke = 0.5 * self.degrees_of_liberty() * self.parameters.r_constant * self.parameters.t_0;
 		let scale = (desired_ke / initial_ke).sqrt();
 
 		for p in self.particles.iter_mut() {
@@ -39,35 +81,70 @@ impl System {
 		}
 	}
 
+	/// Initialize the momentums of the particles randomly, with the random number generator of the system
 	pub fn init_particles_momentums(&mut self) {
-		// Step 1: Random vectors in unit cube
-		for particle in self.particles.iter_mut() {
-			particle.momentum = Vector3::random_in_unit_cube();
+		let mut rng = self.rng.clone();
+		self.init_particles_momentums_with_rng(&mut rng);
+		self.rng = rng;
+	}
+
+	/// Initialize the momentums of the particles randomly, with the given random number generator.
+	/// The momenta are at the same time as the positions.
+	pub fn init_particles_momentums_with_rng(&mut self, rng: &mut impl Rng) {
+		self.half_step_momenta = false;
+
+		// Step 1: Random vectors, according to the chosen distribution
+		match self.parameters.momentum_distribution {
+			MomentumDistribution::Uniform => {
+				for particle in self.particles.iter_mut() {
+					particle.momentum = Vector3::random_in_unit_cube(rng);
+				}
+			}
+			MomentumDistribution::MaxwellBoltzmann => {
+				// Each component follows a Gaussian of variance m k_B T, with k_B T converted from kcal/mol to amu.Å²/fs²
+				// as the momenta are in amu.Å/fs
+				let masses = self.masses();
+				let k_b_t = self.parameters.r_constant * self.parameters.t_0 * self.parameters.conversion_force;
+				for particle in self.particles.iter_mut() {
+					particle.momentum = Vector3::random_gaussian(rng) * (masses[particle.species] * k_b_t).sqrt();
+				}
+			}
 		}
 
-		// Step 2: Recalibrate
+		// Step 2: Recalibrate, without relative velocities along the constrained bonds
 		self.recalibrate_according_to_temperature();
 		self.recalibrate_according_to_center_of_mass();
+		if self.has_constraints() {
+			self.constrain_momenta();
+		}
 		self.recalibrate_according_to_temperature();
 	}
 
+	/// Compute the kinetic energy of the particles, in kcal/mol, and the temperature it corresponds to, in Kelvin.
+	/// The momenta are in amu.Å/fs, so p²/(2m) is divided by `conversion_force` to give kcal/mol, the unit of the
+	/// potential energy: the sum of both is the energy conserved by the [integrators](crate::integrator).
+	/// The momenta are taken as they are stored, i.e. half a time step behind the positions with
+	/// [leapfrog](crate::integrator::Leapfrog): [`System::energy_breakdown`] brings them to the time of the positions.
 	pub fn kinetic_energy_and_temperature(&self) -> (f64, f64) {
-		let mut sum_p2 = 0.0;
+		let masses = self.masses();
+		let mut sum_p2 = vec![0.0; masses.len()];
 		for particle in self.particles() {
 			let p = particle.kinetic_moment();
-			sum_p2 += p.x().powi(2) + p.y().powi(2) + p.z().powi(2);
+			sum_p2[particle.species] += p.x().powi(2) + p.y().powi(2) + p.z().powi(2);
 		}
 
-		// Physical kinetic energy: K = sum(p^2) / (2 m)
-		let kinetic_energy = sum_p2 / (2.0 * PARTICLE_MASS);
+		// Physical kinetic energy: K = sum(p^2) / (2 m), for the particles of each species.
+		// The momenta are in amu.Å/fs, so the energy is converted from amu.Å²/fs² to kcal/mol.
+		let kinetic_energy: f64 = sum_p2.iter().zip(&masses).map(|(sum_p2, mass)| sum_p2 / (2.0 * mass)).sum::<f64>()
+			/ self.parameters.conversion_force;
 
 		// Temperature: K = (N_dl / 2) * k_B * T  =>  T = 2K / (N_dl * k_B)
-		let temperature = 2.0 * kinetic_energy / (self.degrees_of_liberty() * R_CONSTANT);
+		let temperature = 2.0 * kinetic_energy / (self.degrees_of_liberty() * self.parameters.r_constant);
 
 		return (kinetic_energy, temperature);
 	}
 
-	pub fn forces_applied_to_particles(forces: &Vec<Vec<Vec<Vector3>>>) -> Vec<Vector3> {
+	pub fn forces_applied_to_particles(forces: &[Vec<Vec<Vector3>>]) -> Vec<Vector3> {
 		let nb_particles = forces[0].len();
 		let mut flattened_forces = vec![Vector3::zero(); nb_particles];
 		for sym_idx in 0..forces.len() {
@@ -80,65 +157,96 @@ impl System {
 		return flattened_forces;
 	}
 
-	pub fn step(&mut self) {
-		// Compute forces applied to each particle
-		let forces = self.compute_forces_periodic(&neighboring_3d_translations(BOX_SIDE), R_CUT);
-		let forces = Self::forces_applied_to_particles(&forces);
+	/// Compute the forces applied to each particle, F = -∇U, using the configured [pair search](PairSearch)
+	pub fn forces_per_particle(&self) -> Vec<Vector3> {
+		self.evaluate_forces().forces()
+	}
 
-		// INFO: max force magnitude and max particle momentum before update
-		let max_force = forces.iter().map(|f| f.norm()).fold(0.0, f64::max);
-		let max_momentum_before = self.particles.iter().map(|p| p.momentum.norm()).fold(0.0, f64::max);
-		println!("INFO: max_force = {}, max_momentum_before = {}", max_force, max_momentum_before);
+	/// Compute the microscopic energy in the system with periodic conditions, using the configured [pair search](PairSearch)
+	pub fn potential_energy(&self) -> f64 {
+		self.evaluate_forces().energy
+	}
 
-		// INFO: minimal pair distance (considering periodic images)
-		let mut min_pair_dist2 = std::f64::INFINITY;
+	/// Find the closest pair of particles, considering periodic images.
+	/// With a [cell list](PairSearch::CellList) or a [Verlet list](PairSearch::VerletList), only the pairs closer than
+	/// the cut are considered.
+	/// With the `parallel` feature, the particles are split between threads.
+	pub fn minimum_pair_distance(&self) -> (f64, (usize, usize)) {
+		#[cfg(feature = "parallel")]
+		return self.minimum_pair_distance_parallel();
+		#[cfg(not(feature = "parallel"))]
+		return self.minimum_pair_distance_serial();
+	}
+
+	/// Find the closest pair of particles, considering periodic images, on the current thread.
+	/// With a [cell list](PairSearch::CellList) or a [Verlet list](PairSearch::VerletList), only the pairs closer than
+	/// the cut are considered.
+	pub fn minimum_pair_distance_serial(&self) -> (f64, (usize, usize)) {
+		let mut min_pair_dist2 = f64::INFINITY;
 		let mut min_pair = (0usize, 0usize);
-		for sym in neighboring_3d_translations(BOX_SIDE) {
-			for i in 0..self.nb_particles_total() {
-				for j in 0..self.nb_particles_total() {
-					if i == j {
-						continue;
+		match self.parameters.pair_search {
+			PairSearch::Images => {
+				for sym in neighboring_3d_translations(self.parameters.box_side) {
+					for i in 0..self.nb_particles_total() {
+						for j in 0..self.nb_particles_total() {
+							if i == j {
+								continue;
+							}
+							let particle_j_with_symmetry = (self.particles[j].coordinates + sym).as_point();
+							let dist2 = self.particles[i]
+								.coordinates
+								.distance_to_squared(&particle_j_with_symmetry);
+							if dist2 < min_pair_dist2 {
+								min_pair_dist2 = dist2;
+								min_pair = (i, j);
+							}
+						}
 					}
-					let particle_j_with_symmetry = (self.particles[j].coordinates + sym).as_point();
-					let dist2 = self.particles[i].coordinates.distance_to_squared(&particle_j_with_symmetry);
+				}
+			}
+			PairSearch::MinimumImage | PairSearch::Vectorized => {
+				for i in 0..self.nb_particles_total() {
+					for j in (i + 1)..self.nb_particles_total() {
+						let displacement = minimum_image(
+							self.particles[i].coordinates - self.particles[j].coordinates,
+							self.parameters.box_side,
+						);
+						let dist2 = displacement.norm_squared();
+						if dist2 < min_pair_dist2 {
+							min_pair_dist2 = dist2;
+							min_pair = (i, j);
+						}
+					}
+				}
+			}
+			PairSearch::CellList | PairSearch::VerletList => {
+				let cell_list = CellList::new(&self.particles, self.parameters.box_side, self.parameters.r_cut);
+				self.for_each_pair_in_cut(&cell_list, |i, j, displacement| {
+					let dist2 = displacement.norm_squared();
 					if dist2 < min_pair_dist2 {
 						min_pair_dist2 = dist2;
 						min_pair = (i, j);
 					}
-				}
+				});
 			}
 		}
-		let min_pair_distance = min_pair_dist2.sqrt();
-		println!("INFO: min_pair_distance = {}, min_pair = {:?}", min_pair_distance, min_pair);
-
-		// 1st equation: half time step update of the kinetic momentum
-		// d(r_i) / d_t is momentum
-		// F_i = -Nabla U => momentum(t) - 1/2 Nabla U(t) dt = momentum(t) + 1/2 F_i dt
-		// TODO: Verify why it explodes when it's += but stays stable with -=
-		for (i, p) in self.particles.iter_mut().enumerate() {
-			p.momentum = p.momentum - 0.5 * forces[i] * DELTA_TIME * CONVERSION_FORCE;
-		}
+		return (min_pair_dist2.sqrt(), min_pair);
+	}
 
-		// 2nd equation: full time step update of the positions
-		// According to Newton's equations: m_i * momentum = F_i
-		for p in self.particles.iter_mut() {
-			let velocity = p.momentum / PARTICLE_MASS;
-			p.coordinates = (p.coordinates + velocity * DELTA_TIME).as_point();
-		}
+	/// Advance the system by a time step, with the configured [integrator](crate::integrator::Integration)
+	pub fn step(&mut self) {
+		let box_side = self.parameters.box_side;
 
-		// 3rd equation: full time step update of the kinetic momentum
-		// Before, compute the energy at the next time step and forces applied to each particle
-		// TODO: Same as 1st equation
-		let forces = self.compute_forces_periodic(&neighboring_3d_translations(BOX_SIDE), R_CUT);
-		let forces = Self::forces_applied_to_particles(&forces);
+		// INFO: max particle momentum before update
+		let max_momentum_before = self.particles.iter().map(|p| p.momentum.norm()).fold(0.0, f64::max);
+		println!("INFO: max_momentum_before = {}", max_momentum_before);
 
-		// INFO: max force (after position update)
-		let max_force_after = forces.iter().map(|f| f.norm()).fold(0.0, f64::max);
-		println!("INFO: max_force_after = {}", max_force_after);
+		// INFO: minimal pair distance (considering periodic images)
+		let (min_pair_distance, min_pair) = self.minimum_pair_distance();
+		println!("INFO: min_pair_distance = {}, min_pair = {:?}", min_pair_distance, min_pair);
 
-		for (i, p) in self.particles.iter_mut().enumerate() {
-			p.momentum = p.momentum - 0.5 * forces[i] * DELTA_TIME * CONVERSION_FORCE;
-		}
+		let integrator = self.parameters.integrator;
+		integrator.integrate(self);
 
 		// INFO: max particle momentum after full update
 		let max_momentum_after = self.particles.iter().map(|p| p.momentum.norm()).fold(0.0, f64::max);
@@ -146,30 +254,113 @@ impl System {
 
 		// Periodic conditions: put the particles in the box
 		for p in self.particles.iter_mut() {
-			p.put_back_in_box();
+			p.put_back_in_box(box_side);
+		}
+		self.update_particle_arrays();
+
+		// INFO: how often the Verlet list was rebuilt
+		if let Some(statistics) = self.verlet_list_statistics() {
+			println!(
+				"INFO: verlet_list_builds = {}, verlet_list_updates = {}, max_displacement = {}",
+				statistics.nb_builds, statistics.nb_updates, statistics.max_displacement
+			);
 		}
+
+		self.step_count += 1;
 	}
 
-	pub fn total_energy(&self) -> f64 {
-		let (kinetic_energy, _temp) = self.kinetic_energy_and_temperature();
+	/// Compute the contributions to the energy of the system and its pressure, with the
+	/// [tail corrections](System::tail_corrections) reported apart.
+	/// All of them are at the time of the positions: the momenta half a time step behind of
+	/// [leapfrog](crate::integrator::Leapfrog) are first kicked by the forces over half a time step.
+	pub fn energy_breakdown(&self) -> EnergyBreakdown {
+		// Calculate potential energy and virial using the periodic conditions
+		let evaluation = self.evaluate_forces();
+		let (kinetic_energy, temperature) = if self.half_step_momenta {
+			// p(t) = p(t - dt/2) + F(t) dt/2, then RATTLE
+			let mut synchronized = self.clone();
+			synchronized.kick(&evaluation.forces(), 0.5 * self.parameters.delta_time);
+			if synchronized.has_constraints() {
+				synchronized.constrain_momenta();
+			}
+			synchronized.kinetic_energy_and_temperature()
+		} else {
+			self.kinetic_energy_and_temperature()
+		};
+		let tail_corrections = self.tail_corrections().unwrap_or_default();
 
-		// Calculate potential energy using the periodic conditions
-		let potential_energy = self.microscopic_energy_periodic(&neighboring_3d_translations(BOX_SIDE), R_CUT);
+		// Virial theorem: P V = (2 K + W) / 3
+		let volume = self.parameters.box_side.powi(3);
+		return EnergyBreakdown {
+			kinetic_energy,
+			temperature,
+			potential_energy: evaluation.energy,
+			tail_energy: tail_corrections.energy,
+			pressure: (2.0 * kinetic_energy + evaluation.virial) / (3.0 * volume),
+			tail_pressure: tail_corrections.pressure,
+		};
+	}
+
+	pub fn total_energy(&self) -> f64 {
+		let breakdown = self.energy_breakdown();
+		let (kinetic_energy, _temp, potential_energy) =
+			(breakdown.kinetic_energy, breakdown.temperature, breakdown.potential_energy);
 
 		println!("k: {kinetic_energy}, t: {_temp}, p: {potential_energy}");
+		if self.parameters.tail_corrections {
+			println!(
+				"INFO: tail_energy = {}, pressure = {}, tail_pressure = {}",
+				breakdown.tail_energy, breakdown.pressure, breakdown.tail_pressure
+			);
+		}
 
-		return kinetic_energy + potential_energy;
+		return breakdown.total_energy();
 	}
 
-	pub fn energy_evolution(&mut self, nb_steps: usize, save_to: &str) {
+	/// Run the simulation for a number of steps, and plot the total energy at each step.
+	/// With a trajectory, the frames of the particles are also dumped, numbered with the [step count](System::step_count)
+	/// of the system, so that a resumed run continues the numbering of the checkpoints. The initial state is dumped too
+	/// when its step is one of the frames, and the trajectory is flushed once at the end of the run.
+	/// With a checkpointer, the state of the system is saved periodically so that the run can be resumed.
+	///
+	/// # Arguments
+	///
+	/// * `nb_steps` - The number of steps to run
+	/// * `save_to` - The path of the plot of the energy
+	/// * `trajectory` - Where the frames of the particles are written, if any
+	/// * `checkpointer` - Where the checkpoints are saved, if any
+	pub fn energy_evolution(
+		&mut self, nb_steps: usize, save_to: &str, mut trajectory: Option<&mut TrajectoryWriter<dyn Write + '_>>,
+		checkpointer: Option<&Checkpointer>,
+	) -> std::io::Result<()> {
+		if let Some(trajectory) = trajectory.as_mut() {
+			trajectory.record(self, self.step_count())?;
+		}
+
 		let mut energies = vec![];
 		for step in 0..nb_steps {
 			self.step();
-			println!("Step {}: Total energy = {}", step, self.total_energy());
+			if let Some(trajectory) = trajectory.as_mut() {
+				trajectory.record(self, self.step_count())?;
+			}
+			if let Some(checkpointer) = checkpointer {
+				checkpointer.record(self)?;
+			}
 			let total_energy = self.total_energy();
+			println!("Step {}: Total energy = {}", step, total_energy);
 			energies.push(total_energy);
 		}
+		if let Some(trajectory) = trajectory.as_mut() {
+			trajectory.flush()?;
+		}
+
+		Self::plot_energies(&energies, save_to);
+		return Ok(());
+	}
 
+	/// Plot the total energy at each step
+	fn plot_energies(energies: &[f64], save_to: &str) {
+		let nb_steps = energies.len();
 		let root = BitMapBackend::new(save_to, (800, 600)).into_drawing_area();
 		root.fill(&WHITE).unwrap();
 		let mut chart = ChartBuilder::on(&root)
@@ -188,11 +379,11 @@ impl System {
 
 		chart.draw_series(plotters::series::LineSeries::new(
 			energies.iter().enumerate().map(|(x, y)| (x, *y)),
-			&RED,
+			RED,
 		))
 		.unwrap()
 		.label("Total Energy")
-		.legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &RED));
-		chart.configure_series_labels().background_style(&WHITE.mix(0.8)).draw().unwrap();
+		.legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
+		chart.configure_series_labels().background_style(WHITE.mix(0.8)).draw().unwrap();
 	}
 }
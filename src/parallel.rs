@@ -0,0 +1,239 @@
+//! Evaluation of the interactions on all cores, behind the `parallel` feature
+//!
+//! Each particle sums the interactions with all its neighbours on its own, in a fixed order, then the sums of the
+//! particles are added in the order of the particles. The results are thus the same whatever the number of threads,
+//! at the cost of computing each pair twice. The [vectorized](PairSearch::Vectorized) pair search keeps its
+//! [structure of arrays](crate::particle_arrays::ParticleArrays) and its kernel without branches on each thread.
+
+use rayon::prelude::*;
+
+use crate::{
+	algebra::Vector3,
+	cell_list::CellList,
+	forces::ForceEvaluation,
+	parameters::PairSearch,
+	particle_arrays::{sum_in_lanes, wrap},
+	periodic_conditions::{minimum_image, neighboring_3d_translations},
+	potential::PairPotential,
+	species::Interactions,
+	system::System,
+	verlet_list::VerletList,
+};
+
+/// How the neighbours of a particle are found, prepared once for all the particles
+enum Neighbours<'a> {
+	/// Every particle, in the box and its neighbouring images
+	Images(Vec<Vector3>),
+	/// Every other particle, with the minimum image convention
+	MinimumImage,
+	/// The particles in the neighbouring cells
+	CellList(CellList),
+	/// The particles listed as neighbours
+	VerletList(std::borrow::Cow<'a, VerletList>),
+}
+
+impl System {
+	/// Prepare the search of the neighbours of the particles, according to the configured [pair search](PairSearch)
+	fn neighbours(&self) -> Neighbours<'_> {
+		let box_side = self.parameters.box_side;
+		match self.parameters.pair_search {
+			PairSearch::Images => Neighbours::Images(neighboring_3d_translations(box_side)),
+			// Above half the box, several images can be in the cut
+			PairSearch::MinimumImage | PairSearch::Vectorized if self.parameters.r_cut > box_side / 2.0 => {
+				Neighbours::Images(neighboring_3d_translations(box_side))
+			}
+			PairSearch::MinimumImage | PairSearch::Vectorized => Neighbours::MinimumImage,
+			PairSearch::CellList => Neighbours::CellList(CellList::new(&self.particles, box_side, self.parameters.r_cut)),
+			PairSearch::VerletList => match &self.verlet_list {
+				Some(verlet_list) if verlet_list.is_valid_for(self) => {
+					Neighbours::VerletList(std::borrow::Cow::Borrowed(verlet_list))
+				}
+				_ => Neighbours::VerletList(std::borrow::Cow::Owned(VerletList::new(self, self.parameters.verlet_skin))),
+			},
+		}
+	}
+
+	/// Call the given function on each particle or image of particle closer than the given radius to the given particle,
+	/// with the displacement from the neighbour to the particle.
+	/// The images of the particle itself are included, but not the particle.
+	///
+	/// # Arguments
+	///
+	/// * `neighbours` - How the neighbours are found
+	/// * `i` - The index of the particle
+	/// * `radius_cut_squared` - The square of the distance above which neighbours are ignored
+	/// * `f` - The function called on each neighbour
+	fn for_each_neighbour(&self, neighbours: &Neighbours, i: usize, radius_cut_squared: f64, mut f: impl FnMut(usize, Vector3)) {
+		let box_side = self.parameters.box_side;
+		let coordinates = self.particles[i].coordinates;
+		let mut visit = |j: usize, displacement: Vector3| {
+			if displacement.norm_squared() <= radius_cut_squared {
+				f(j, displacement);
+			}
+		};
+
+		match neighbours {
+			Neighbours::Images(translations) => {
+				for sym in translations {
+					for j in 0..self.nb_particles_total() {
+						if i != j || *sym != Vector3::zero() {
+							visit(j, coordinates - (self.particles[j].coordinates + sym));
+						}
+					}
+				}
+			}
+			Neighbours::MinimumImage => {
+				for j in (0..self.nb_particles_total()).filter(|&j| j != i) {
+					visit(j, minimum_image(coordinates - self.particles[j].coordinates, box_side));
+				}
+			}
+			Neighbours::CellList(cell_list) => cell_list.for_each_neighbour(&coordinates, |j| {
+				if j != i {
+					visit(j, minimum_image(coordinates - self.particles[j].coordinates, box_side));
+				}
+			}),
+			Neighbours::VerletList(verlet_list) => {
+				for &j in verlet_list.neighbours(i) {
+					visit(j, minimum_image(coordinates - self.particles[j].coordinates, box_side));
+				}
+			}
+		}
+	}
+
+	/// Evaluate the forces, the energy and the virial with periodic conditions, using the configured
+	/// [pair search](PairSearch), on all the threads of the rayon pool.
+	/// The results don't depend on the number of threads, but can differ slightly from [`System::evaluate_forces_serial`]
+	/// since the sums are done in another order.
+	///
+	/// # Arguments
+	///
+	/// * `potential` - The interaction between the particles of a pair, which may depend on their species
+	pub fn evaluate_forces_parallel<I: Interactions + Sync>(&self, potential: &I) -> ForceEvaluation {
+		if self.parameters.pair_search == PairSearch::Vectorized && self.parameters.r_cut <= self.parameters.box_side / 2.0 {
+			return self.evaluate_forces_vectorized_parallel(potential);
+		}
+		let neighbours = self.neighbours();
+		let radius_cut_squared = self.parameters.r_cut.powi(2);
+		let per_particle: Vec<(Vector3, f64, f64)> = (0..self.nb_particles_total())
+			.into_par_iter()
+			.map(|i| {
+				let mut force = Vector3::zero();
+				let mut energy = 0.0;
+				let mut virial = 0.0;
+				let species = self.particles[i].species;
+				self.for_each_neighbour(&neighbours, i, radius_cut_squared, |j, displacement| {
+					if self.exclusions.contains(i, j) {
+						return;
+					}
+					let pair_potential = potential.between(species, self.particles[j].species);
+					let (pair_energy, pair_force) = pair_potential.energy_and_force(displacement.norm_squared());
+					let gradient = displacement * -pair_force;
+					force += gradient;
+					// Each pair is seen from both particles
+					energy += 0.5 * pair_energy;
+					virial -= 0.5 * displacement.dot(&gradient);
+				});
+				(force, energy, virial)
+			})
+			.collect();
+
+		let mut evaluation = ForceEvaluation::zero(self.nb_particles_total());
+		for (i, (force, energy, virial)) in per_particle.into_iter().enumerate() {
+			evaluation.gradients[i] = force;
+			evaluation.energy += energy;
+			evaluation.virial += virial;
+		}
+		return evaluation;
+	}
+
+	/// Evaluate the forces, the energy and the virial like [`System::evaluate_forces_vectorized`], from the
+	/// [structure of arrays](crate::particle_arrays::ParticleArrays) of the system and with a kernel without branches,
+	/// on all the threads of the rayon pool.
+	/// Like [`System::evaluate_forces_parallel`], each particle sums the interactions with all the other particles on
+	/// its own, so the results don't depend on the number of threads.
+	/// The cut must be smaller than half the box, so that only the nearest image of each particle is in the cut.
+	///
+	/// # Arguments
+	///
+	/// * `potential` - The interaction between the particles of a pair, which may depend on their species
+	fn evaluate_forces_vectorized_parallel<I: Interactions + Sync>(&self, potential: &I) -> ForceEvaluation {
+		let box_side = self.parameters.box_side;
+		let radius_cut_squared = self.parameters.r_cut.powi(2);
+		let arrays = self.particle_arrays_or_converted();
+		let n = arrays.len();
+		let species: Vec<usize> = self.particles.iter().map(|particle| particle.species).collect();
+
+		// The contributions of the pairs of a particle, computed in a first loop which vectorizes, then summed.
+		// Each thread reuses its buffers for all the particles it is given.
+		let buffers = || (vec![0.0; n], vec![0.0; n], vec![0.0; n], vec![0.0; n], vec![0.0; n], vec![false; n]);
+		let per_particle: Vec<(Vector3, f64, f64)> = (0..n)
+			.into_par_iter()
+			.map_init(buffers, |(gx, gy, gz, energies, virials, excluded), i| {
+				let (xi, yi, zi) = (arrays.x[i], arrays.y[i], arrays.z[i]);
+				let species_i = species[i];
+				// The particle itself is masked like the excluded pairs, so that the kernel has no branches
+				excluded.fill(false);
+				excluded[i] = true;
+				for &j in self.exclusions.of(i) {
+					excluded[j] = true;
+				}
+				for j in 0..n {
+					let dx = wrap(xi - arrays.x[j], box_side);
+					let dy = wrap(yi - arrays.y[j], box_side);
+					let dz = wrap(zi - arrays.z[j], box_side);
+					let distance_squared = dx * dx + dy * dy + dz * dz;
+
+					// Pairs beyond the cut contribute 0, instead of being skipped
+					let (energy, force) = potential.between(species_i, species[j]).energy_and_force(distance_squared);
+					let inside = distance_squared <= radius_cut_squared && !excluded[j];
+					let factor = if inside { -force } else { 0.0 };
+
+					gx[j] = dx * factor;
+					gy[j] = dy * factor;
+					gz[j] = dz * factor;
+					// Each pair is seen from both particles
+					energies[j] = if inside { 0.5 * energy } else { 0.0 };
+					virials[j] = -0.5 * (dx * gx[j] + dy * gy[j] + dz * gz[j]);
+				}
+				let gradient = Vector3::from(sum_in_lanes(gx), sum_in_lanes(gy), sum_in_lanes(gz));
+				(gradient, sum_in_lanes(energies), sum_in_lanes(virials))
+			})
+			.collect();
+
+		let mut evaluation = ForceEvaluation::zero(n);
+		for (i, (gradient, energy, virial)) in per_particle.into_iter().enumerate() {
+			evaluation.gradients[i] = gradient;
+			evaluation.energy += energy;
+			evaluation.virial += virial;
+		}
+		return evaluation;
+	}
+
+	/// Find the closest pair of particles, considering periodic images, on all the threads of the rayon pool.
+	/// Same as [`System::minimum_pair_distance`] on the current thread, but ties are broken by the lowest indices.
+	pub fn minimum_pair_distance_parallel(&self) -> (f64, (usize, usize)) {
+		// Like on the current thread, only the lists are limited to the pairs closer than the cut
+		let (neighbours, radius_cut_squared) = match self.parameters.pair_search {
+			PairSearch::Images => (
+				Neighbours::Images(neighboring_3d_translations(self.parameters.box_side)),
+				f64::INFINITY,
+			),
+			PairSearch::MinimumImage | PairSearch::Vectorized => (Neighbours::MinimumImage, f64::INFINITY),
+			PairSearch::CellList | PairSearch::VerletList => (self.neighbours(), self.parameters.r_cut.powi(2)),
+		};
+		let (min_pair_dist2, min_pair) = (0..self.nb_particles_total())
+			.into_par_iter()
+			.map(|i| {
+				let mut closest = (f64::INFINITY, (0usize, 0usize));
+				self.for_each_neighbour(&neighbours, i, radius_cut_squared, |j, displacement| {
+					let dist2 = displacement.norm_squared();
+					if j != i && (dist2, (i, j)) < closest {
+						closest = (dist2, (i, j));
+					}
+				});
+				closest
+			})
+			.reduce(|| (f64::INFINITY, (0, 0)), |a, b| if b < a { b } else { a });
+		return (min_pair_dist2.sqrt(), min_pair);
+	}
+}
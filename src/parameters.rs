@@ -1,3 +1,20 @@
+//! Parameters of the simulation
+//!
+//! The constants are the values used in the course, and the defaults of [`SimulationParameters`].
+
+use std::{collections::BTreeMap, fmt::Display, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	cutoff::Cutoff,
+	ewald::Electrostatics,
+	integrator::Integration,
+	potential::Potential,
+	species::{MixingRule, PairParameters, SpeciesParameters},
+	tail_corrections::power_law_terms,
+};
+
 pub const R_STAR: f64 = 3.0; // ISM2
 pub const EPSILON_STAR: f64 = 0.2; // ISM2
 pub const R_CUT: f64 = 10.0; // ISM3
@@ -8,3 +25,546 @@ pub const CONVERSION_FORCE: f64 = 0.0001 * 4.186; // ISM4
 pub const PARTICLE_MASS: f64 = 18.0; // ISM4
 pub const R_CONSTANT: f64 = 0.00199; // ISM4
 pub const T_0: f64 = 300.0; // ISM4, initial temperature in Kelvin
+pub const VERLET_SKIN: f64 = 1.0; // Distance added to the cut for the Verlet lists
+pub const CONSTRAINT_TOLERANCE: f64 = 1e-10; // Relative tolerance on the constrained distances
+
+/// How the initial momentums of the particles are drawn, before being recalibrated to the initial temperature
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MomentumDistribution {
+	/// Each component is drawn uniformly in [-1, 1]
+	#[default]
+	Uniform,
+	/// Each component is drawn from a Gaussian of variance m k_B T, as at thermal equilibrium
+	MaxwellBoltzmann,
+}
+
+/// How the pairs of interacting particles are found, with periodic conditions
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PairSearch {
+	/// Every pair between the box and its 26 neighbouring images, in quadratic time
+	Images,
+	/// Every pair once, with the nearest image of the second particle, in quadratic time but a single pass
+	MinimumImage,
+	/// Same as [`PairSearch::MinimumImage`], with the particles stored as a
+	/// [structure of arrays](crate::particle_arrays::ParticleArrays) so that the kernel is vectorized
+	Vectorized,
+	/// Only the pairs in neighbouring cells of a [cell list](crate::cell_list::CellList), in linear time
+	#[default]
+	CellList,
+	/// The pairs of a [Verlet list](crate::verlet_list::VerletList) built with a skin, reused across steps
+	VerletList,
+}
+
+/// The parameters of a simulation, which can be loaded at runtime from a TOML or JSON file.
+/// Missing fields take the value of the corresponding constant.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationParameters {
+	/// Distance at which the Lennard-Jones potential reaches its minimum
+	pub r_star: f64,
+	/// Depth of the Lennard-Jones potential well
+	pub epsilon_star: f64,
+	/// Distance above which interactions are ignored
+	pub r_cut: f64,
+	/// Side of the cubic simulation box
+	pub box_side: f64,
+	/// Time step of the integration, in femtoseconds
+	pub delta_time: f64,
+	/// Conversion factor from the force unit to the momentum unit per femtosecond
+	pub conversion_force: f64,
+	/// Mass of a particle, unless given for its species
+	pub particle_mass: f64,
+	/// Gas constant, in kcal/(mol.K)
+	pub r_constant: f64,
+	/// Initial temperature, in Kelvin
+	pub t_0: f64,
+	/// Seed of the random number generator, drawn from the system entropy if absent
+	pub seed: Option<u64>,
+	/// Number of steps between 2 checkpoints of a run, without checkpoints if absent
+	pub checkpoint_every: Option<usize>,
+	/// Distribution of the initial momentums
+	pub momentum_distribution: MomentumDistribution,
+	/// How the pairs of interacting particles are found
+	pub pair_search: PairSearch,
+	/// Distance added to the cut when building a Verlet list
+	pub verlet_skin: f64,
+	/// Interaction between the particles, the Lennard-Jones potential of `r_star` and `epsilon_star` if absent
+	pub potential: Option<Potential>,
+	/// How the potential is modified close to the cut
+	pub cutoff: Cutoff,
+	/// Whether the energy and the pressure are corrected for the interactions beyond the cut
+	pub tail_corrections: bool,
+	/// The parameters of the species, by name, replacing `particle_mass`, `r_star` and `epsilon_star`.
+	/// The Lennard-Jones parameters are only used when no other `potential` is chosen.
+	pub species: BTreeMap<String, SpeciesParameters>,
+	/// How the Lennard-Jones parameters of different species are combined
+	pub mixing_rule: MixingRule,
+	/// The Lennard-Jones parameters of pairs of species, replacing the mixing rule
+	pub pair_overrides: Vec<PairParameters>,
+	/// How the electrostatic interactions between charged particles are computed, ignored if absent
+	pub electrostatics: Option<Electrostatics>,
+	/// Relative tolerance of SHAKE and RATTLE on the constrained distances
+	pub constraint_tolerance: f64,
+	/// How the equations of motion are integrated at each step
+	pub integrator: Integration,
+}
+
+impl Default for SimulationParameters {
+	fn default() -> Self {
+		Self {
+			r_star: R_STAR,
+			epsilon_star: EPSILON_STAR,
+			r_cut: R_CUT,
+			box_side: BOX_SIDE,
+			delta_time: DELTA_TIME,
+			conversion_force: CONVERSION_FORCE,
+			particle_mass: PARTICLE_MASS,
+			r_constant: R_CONSTANT,
+			t_0: T_0,
+			seed: None,
+			checkpoint_every: None,
+			momentum_distribution: MomentumDistribution::default(),
+			pair_search: PairSearch::default(),
+			verlet_skin: VERLET_SKIN,
+			potential: None,
+			cutoff: Cutoff::default(),
+			tail_corrections: false,
+			species: BTreeMap::new(),
+			mixing_rule: MixingRule::default(),
+			pair_overrides: Vec::new(),
+			electrostatics: None,
+			constraint_tolerance: CONSTRAINT_TOLERANCE,
+			integrator: Integration::VelocityVerlet,
+		}
+	}
+}
+
+/// An error that occurred while loading or validating [parameters](SimulationParameters)
+#[derive(Debug)]
+pub enum ParameterError {
+	/// The file could not be read
+	Io(std::io::Error),
+	/// The file is not valid TOML
+	Toml(toml::de::Error),
+	/// The file is not valid JSON
+	Json(serde_json::Error),
+	/// The file extension is neither `.toml` nor `.json`
+	UnknownFormat(String),
+	/// A parameter has a nonsensical value
+	Invalid {
+		/// The name of the parameter
+		name: &'static str,
+		/// Its value
+		value: f64,
+		/// Why the value is rejected
+		reason: &'static str,
+	},
+}
+
+impl Display for ParameterError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Io(error) => write!(f, "could not read the parameters: {error}"),
+			Self::Toml(error) => write!(f, "invalid TOML parameters: {error}"),
+			Self::Json(error) => write!(f, "invalid JSON parameters: {error}"),
+			Self::UnknownFormat(path) => write!(f, "unknown parameters format for {path}, expected .toml or .json"),
+			Self::Invalid { name, value, reason } => write!(f, "invalid parameter {name} = {value}: {reason}"),
+		}
+	}
+}
+
+impl std::error::Error for ParameterError {}
+
+impl SimulationParameters {
+	/// Parse parameters from a TOML string, and validate them
+	pub fn from_toml(s: &str) -> Result<Self, ParameterError> {
+		let parameters: Self = toml::from_str(s).map_err(ParameterError::Toml)?;
+		parameters.validate()?;
+		return Ok(parameters);
+	}
+
+	/// Parse parameters from a JSON string, and validate them
+	pub fn from_json(s: &str) -> Result<Self, ParameterError> {
+		let parameters: Self = serde_json::from_str(s).map_err(ParameterError::Json)?;
+		parameters.validate()?;
+		return Ok(parameters);
+	}
+
+	/// Load parameters from a file, and validate them.
+	/// The format is deduced from the extension of the file, either `.toml` or `.json`.
+	/// A relative `file` of a tabulated potential is resolved against the directory of the parameters file.
+	pub fn from_file(path: &Path) -> Result<Self, ParameterError> {
+		let contents = fs::read_to_string(path).map_err(ParameterError::Io)?;
+		let directory = path.parent().unwrap_or(Path::new(""));
+		let parameters: Self = match path.extension().and_then(|extension| extension.to_str()) {
+			Some("toml") => {
+				let mut table: toml::Table = toml::from_str(&contents).map_err(ParameterError::Toml)?;
+				if let Some(toml::Value::Table(potential)) = table.get_mut("potential")
+					&& potential.get("kind").and_then(toml::Value::as_str) == Some("tabulated")
+					&& let Some(toml::Value::String(file)) = potential.get_mut("file")
+				{
+					*file = directory.join(&*file).display().to_string();
+				}
+				toml::Value::Table(table).try_into().map_err(ParameterError::Toml)?
+			}
+			Some("json") => {
+				let mut value: serde_json::Value = serde_json::from_str(&contents).map_err(ParameterError::Json)?;
+				if let Some(potential) = value.get_mut("potential")
+					&& potential.get("kind").and_then(serde_json::Value::as_str) == Some("tabulated")
+					&& let Some(serde_json::Value::String(file)) = potential.get_mut("file")
+				{
+					*file = directory.join(&*file).display().to_string();
+				}
+				serde_json::from_value(value).map_err(ParameterError::Json)?
+			}
+			_ => return Err(ParameterError::UnknownFormat(path.display().to_string())),
+		};
+		parameters.validate()?;
+		return Ok(parameters);
+	}
+
+	/// Check that the parameters make sense physically
+	pub fn validate(&self) -> Result<(), ParameterError> {
+		let invalid = |name, value, reason| Err(ParameterError::Invalid { name, value, reason });
+
+		let (species_strictly_positive, species_positive) = self.species_values();
+		let strictly_positive = [
+			("r_star", self.r_star),
+			("r_cut", self.r_cut),
+			("box_side", self.box_side),
+			("delta_time", self.delta_time),
+			("conversion_force", self.conversion_force),
+			("particle_mass", self.particle_mass),
+			("r_constant", self.r_constant),
+			("constraint_tolerance", self.constraint_tolerance),
+		];
+		for (name, value) in strictly_positive.into_iter().chain(species_strictly_positive) {
+			if !value.is_finite() || value <= 0.0 {
+				return invalid(name, value, "must be finite and strictly positive");
+			}
+		}
+
+		let positive = [
+			("epsilon_star", self.epsilon_star),
+			("t_0", self.t_0),
+			("verlet_skin", self.verlet_skin),
+		];
+		for (name, value) in positive.into_iter().chain(species_positive) {
+			if !value.is_finite() || value < 0.0 {
+				return invalid(name, value, "must be finite and positive");
+			}
+		}
+
+		let potential_strictly_positive = match &self.potential {
+			None => vec![],
+			Some(Potential::LennardJones(potential)) => vec![("potential.r_star", potential.r_star)],
+			Some(Potential::Morse(potential)) => vec![
+				("potential.stiffness", potential.stiffness),
+				("potential.equilibrium_distance", potential.equilibrium_distance),
+			],
+			Some(Potential::Buckingham(potential)) => vec![("potential.rho", potential.rho)],
+			Some(Potential::Mie(potential)) => vec![
+				("potential.m", potential.m),
+				("potential.n", potential.n - potential.m),
+				("potential.sigma", potential.sigma),
+			],
+			Some(Potential::WeeksChandlerAndersen(potential)) => vec![("potential.r_star", potential.r_star)],
+			Some(Potential::Yukawa(_)) | Some(Potential::Tabulated(_)) => vec![],
+		};
+		for (name, value) in potential_strictly_positive {
+			if !value.is_finite() || value <= 0.0 {
+				// The exponent n of the Mie potential is checked through n - m
+				let reason = if name == "potential.n" {
+					"must be larger than m"
+				} else {
+					"must be finite and strictly positive"
+				};
+				return invalid(name, value, reason);
+			}
+		}
+		let potential_finite = match &self.potential {
+			None => vec![],
+			Some(Potential::LennardJones(potential)) => vec![("potential.epsilon_star", potential.epsilon_star)],
+			Some(Potential::Morse(potential)) => vec![("potential.depth", potential.depth)],
+			Some(Potential::Buckingham(potential)) => vec![("potential.a", potential.a), ("potential.c", potential.c)],
+			Some(Potential::Mie(potential)) => vec![("potential.epsilon", potential.epsilon)],
+			Some(Potential::WeeksChandlerAndersen(potential)) => vec![("potential.epsilon_star", potential.epsilon_star)],
+			Some(Potential::Yukawa(potential)) => {
+				vec![("potential.strength", potential.strength), ("potential.kappa", potential.kappa)]
+			}
+			// Checked while loading the table
+			Some(Potential::Tabulated(_)) => vec![],
+		};
+		for (name, value) in potential_finite {
+			if !value.is_finite() {
+				return invalid(name, value, "must be finite");
+			}
+		}
+
+		// Only the potentials which are sums of powers of the distance have analytic corrections
+		if self.tail_corrections
+			&& let Some(potential) = &self.potential
+			&& power_law_terms(potential, self.r_cut).is_none()
+		{
+			return invalid(
+				"tail_corrections",
+				1.0,
+				"needs a potential which is a sum of powers of the distance beyond the cut",
+			);
+		}
+		if self.checkpoint_every == Some(0) {
+			return invalid("checkpoint_every", 0.0, "must be strictly positive");
+		}
+		// With a bigger cut, a particle could interact with several images of the same particle
+		if self.r_cut > self.box_side / 2.0 {
+			return invalid("r_cut", self.r_cut, "must not be larger than half the box side");
+		}
+		if let Cutoff::Switched { r_switch } = self.cutoff
+			&& (!r_switch.is_finite() || r_switch <= 0.0 || r_switch >= self.r_cut)
+		{
+			return invalid("cutoff.r_switch", r_switch, "must be strictly positive and smaller than the cut");
+		}
+		for parameters in self.species.values() {
+			if let Some(charge) = parameters.charge
+				&& !charge.is_finite()
+			{
+				return invalid("species.charge", charge, "must be finite");
+			}
+		}
+		if let Some(electrostatics) = &self.electrostatics {
+			let ewald = match electrostatics {
+				Electrostatics::Ewald(ewald) => *ewald,
+				Electrostatics::ParticleMeshEwald(pme) => pme.ewald(),
+			};
+			if !ewald.accuracy.is_finite() || ewald.accuracy <= 0.0 || ewald.accuracy >= 1.0 {
+				return invalid("electrostatics.accuracy", ewald.accuracy, "must be strictly between 0 and 1");
+			}
+			if let Some(alpha) = ewald.alpha
+				&& (!alpha.is_finite() || alpha <= 0.0)
+			{
+				return invalid("electrostatics.alpha", alpha, "must be finite and strictly positive");
+			}
+			if ewald.k_max == Some(0) {
+				return invalid("electrostatics.k_max", 0.0, "must be strictly positive");
+			}
+		}
+		if let Some(Electrostatics::ParticleMeshEwald(pme)) = &self.electrostatics {
+			// Below order 3, the forces interpolated from the grid are not continuous
+			if pme.order < 3 {
+				return invalid("electrostatics.order", pme.order as f64, "must be at least 3");
+			}
+			if let Some(grid_size) = pme.grid_size
+				&& grid_size < pme.order
+			{
+				return invalid("electrostatics.grid_size", grid_size as f64, "must not be smaller than the order");
+			}
+		}
+		if let Integration::Respa(respa) = self.integrator {
+			if !respa.inner_cut.is_finite() || respa.inner_cut <= 0.0 || respa.inner_cut >= self.r_cut {
+				return invalid(
+					"integrator.inner_cut",
+					respa.inner_cut,
+					"must be strictly positive and smaller than the cut",
+				);
+			}
+			if !respa.switch_width.is_finite() || respa.switch_width <= 0.0 || respa.switch_width >= respa.inner_cut {
+				return invalid(
+					"integrator.switch_width",
+					respa.switch_width,
+					"must be strictly positive and smaller than the inner cut",
+				);
+			}
+			if respa.nb_inner_steps == 0 {
+				return invalid("integrator.nb_inner_steps", 0.0, "must be strictly positive");
+			}
+		}
+		if self.pair_search == PairSearch::VerletList && self.r_cut + self.verlet_skin > self.box_side / 2.0 {
+			return invalid(
+				"verlet_skin",
+				self.verlet_skin,
+				"must not make the cut larger than half the box side",
+			);
+		}
+
+		return Ok(());
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::respa::Respa;
+
+	#[test]
+	fn defaults_are_the_constants_and_valid() {
+		let parameters = SimulationParameters::default();
+		assert_eq!(parameters.r_cut, R_CUT);
+		assert_eq!(parameters.box_side, BOX_SIDE);
+		assert!(parameters.validate().is_ok());
+	}
+
+	#[test]
+	fn missing_fields_are_defaulted() {
+		let parameters =
+			SimulationParameters::from_toml("box_side = 30.0\nt_0 = 150.0\nmomentum_distribution = \"maxwell_boltzmann\"")
+				.unwrap();
+		assert_eq!(parameters.box_side, 30.0);
+		assert_eq!(parameters.t_0, 150.0);
+		assert_eq!(parameters.momentum_distribution, MomentumDistribution::MaxwellBoltzmann);
+		assert_eq!(parameters.r_star, R_STAR);
+
+		let parameters = SimulationParameters::from_json(r#"{ "delta_time": 0.5, "seed": 42 }"#).unwrap();
+		assert_eq!(parameters.delta_time, 0.5);
+		assert_eq!(parameters.seed, Some(42));
+		assert_eq!(parameters.particle_mass, PARTICLE_MASS);
+	}
+
+	#[test]
+	fn species_are_configured_by_name() {
+		let parameters = SimulationParameters::from_toml(concat!(
+			"mixing_rule = \"geometric\"\n",
+			"[species.A]\nmass = 40.0\nr_star = 4.0\n",
+			"[species.B]\nepsilon_star = 0.5\n",
+			"[[pair_overrides]]\nspecies = [\"A\", \"B\"]\nr_star = 3.0\nepsilon_star = 0.6\n",
+		))
+		.unwrap();
+		assert_eq!(parameters.mixing_rule, MixingRule::Geometric);
+		assert_eq!(parameters.species["A"].mass, Some(40.0));
+		assert_eq!(parameters.species["B"].mass, None);
+		assert_eq!(parameters.pair_overrides[0].species, ["A", "B"]);
+
+		assert!(matches!(
+			SimulationParameters::from_toml("[species.A]\nmass = 0.0"),
+			Err(ParameterError::Invalid { name: "species.mass", .. })
+		));
+	}
+
+	#[test]
+	fn potential_is_chosen_by_kind() {
+		let parameters = SimulationParameters::from_toml(
+			"[potential]\nkind = \"morse\"\ndepth = 0.2\nstiffness = 1.5\nequilibrium_distance = 3.0",
+		)
+		.unwrap();
+		assert_eq!(
+			parameters.potential,
+			Some(Potential::Morse(crate::potential::Morse::new(0.2, 1.5, 3.0)))
+		);
+
+		let parameters = SimulationParameters::from_json(
+			r#"{ "potential": { "kind": "weeks_chandler_andersen", "r_star": 3.0, "epsilon_star": 0.2 } }"#,
+		)
+		.unwrap();
+		assert!(matches!(parameters.potential, Some(Potential::WeeksChandlerAndersen(_))));
+		assert_eq!(SimulationParameters::default().potential, None);
+
+		assert!(matches!(
+			SimulationParameters::from_toml("[potential]\nkind = \"yukawa\"\nstrength = 1.0\nkappa = 0.5\nsigma = 3.0"),
+			Err(ParameterError::Toml(_))
+		));
+	}
+
+	#[test]
+	fn integrator_is_chosen_by_kind() {
+		let parameters = SimulationParameters::from_toml("[integrator]\nkind = \"leapfrog\"").unwrap();
+		assert_eq!(parameters.integrator, Integration::Leapfrog);
+		assert_eq!(SimulationParameters::default().integrator, Integration::VelocityVerlet);
+
+		let round_trip = SimulationParameters::from_toml(&toml::to_string(&parameters).unwrap()).unwrap();
+		assert_eq!(round_trip, parameters);
+
+		let parameters = SimulationParameters::from_toml("[integrator]\nkind = \"respa\"\nnb_inner_steps = 8").unwrap();
+		assert_eq!(
+			parameters.integrator,
+			Integration::Respa(Respa {
+				nb_inner_steps: 8,
+				..Default::default()
+			})
+		);
+	}
+
+	#[test]
+	fn nonsensical_values_are_rejected() {
+		assert!(matches!(
+			SimulationParameters::from_toml("checkpoint_every = 0"),
+			Err(ParameterError::Invalid {
+				name: "checkpoint_every",
+				..
+			})
+		));
+		assert!(matches!(
+			SimulationParameters::from_toml("r_cut = -1.0"),
+			Err(ParameterError::Invalid { name: "r_cut", .. })
+		));
+		assert!(matches!(
+			SimulationParameters::from_toml("r_cut = 15.0\nbox_side = 20.0"),
+			Err(ParameterError::Invalid { name: "r_cut", .. })
+		));
+		assert!(matches!(
+			SimulationParameters::from_toml("pair_search = \"verlet_list\"\nr_cut = 10.0\nbox_side = 21.0\nverlet_skin = 1.0"),
+			Err(ParameterError::Invalid { name: "verlet_skin", .. })
+		));
+		assert!(matches!(
+			SimulationParameters::from_json(r#"{ "particle_mass": 0.0 }"#),
+			Err(ParameterError::Invalid { name: "particle_mass", .. })
+		));
+		assert!(matches!(
+			SimulationParameters::from_toml("[potential]\nkind = \"mie\"\nn = 6.0\nm = 12.0\nsigma = 3.0\nepsilon = 0.2"),
+			Err(ParameterError::Invalid { name: "potential.n", .. })
+		));
+		assert!(matches!(
+			SimulationParameters::from_toml("[cutoff]\nkind = \"switched\"\nr_switch = 12.0"),
+			Err(ParameterError::Invalid {
+				name: "cutoff.r_switch",
+				..
+			})
+		));
+		assert!(matches!(
+			SimulationParameters::from_toml("[electrostatics]\nkind = \"ewald\"\naccuracy = 2.0"),
+			Err(ParameterError::Invalid {
+				name: "electrostatics.accuracy",
+				..
+			})
+		));
+		assert!(matches!(
+			SimulationParameters::from_toml("[electrostatics]\nkind = \"particle_mesh_ewald\"\norder = 2"),
+			Err(ParameterError::Invalid {
+				name: "electrostatics.order",
+				..
+			})
+		));
+		assert!(matches!(
+			SimulationParameters::from_toml("[electrostatics]\nkind = \"particle_mesh_ewald\"\ngrid_size = 3"),
+			Err(ParameterError::Invalid {
+				name: "electrostatics.grid_size",
+				..
+			})
+		));
+		assert!(matches!(
+			SimulationParameters::from_toml("[integrator]\nkind = \"respa\"\ninner_cut = 12.0"),
+			Err(ParameterError::Invalid {
+				name: "integrator.inner_cut",
+				..
+			})
+		));
+		assert!(matches!(
+			SimulationParameters::from_toml("[integrator]\nkind = \"respa\"\nswitch_width = 7.0"),
+			Err(ParameterError::Invalid {
+				name: "integrator.switch_width",
+				..
+			})
+		));
+		assert!(matches!(
+			SimulationParameters::from_toml("[integrator]\nkind = \"respa\"\nnb_inner_steps = 0"),
+			Err(ParameterError::Invalid {
+				name: "integrator.nb_inner_steps",
+				..
+			})
+		));
+		assert!(matches!(
+			SimulationParameters::from_toml("unknown = 1.0"),
+			Err(ParameterError::Toml(_))
+		));
+	}
+}
@@ -0,0 +1,275 @@
+//! Structure of arrays storage of the particles, so that the compiler vectorizes the pair kernel
+//!
+//! [`System::particles`](System) interleaves the coordinates and the momentum of each particle, so a loop over the
+//! particles can't load the x coordinates of several particles at once. Here each component has its own array.
+//! The system keeps these arrays when the vectorized pair search is used, and updates them when the particles move.
+
+use std::borrow::Cow;
+
+use crate::{
+	algebra::{Point3, Vector3},
+	forces::ForceEvaluation,
+	parameters::PairSearch,
+	potential::PairPotential,
+	species::Interactions,
+	system::{Particle, System},
+};
+
+/// The coordinates of particles, with a separate array for each component, put back in the box
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ParticleArrays {
+	/// The x coordinate of each particle
+	pub x: Vec<f64>,
+	/// The y coordinate of each particle
+	pub y: Vec<f64>,
+	/// The z coordinate of each particle
+	pub z: Vec<f64>,
+}
+
+impl ParticleArrays {
+	/// Split the coordinates of the given particles into arrays
+	///
+	/// # Arguments
+	///
+	/// * `particles` - The particles to store
+	/// * `box_side` - The side of the cubic box
+	pub fn from_particles(particles: &[Particle], box_side: f64) -> Self {
+		let mut arrays = Self::default();
+		arrays.update(particles, box_side);
+		return arrays;
+	}
+
+	/// Copy the current coordinates of the given particles, without allocating if their number didn't change
+	///
+	/// # Arguments
+	///
+	/// * `particles` - The particles to store
+	/// * `box_side` - The side of the cubic box
+	pub fn update(&mut self, particles: &[Particle], box_side: f64) {
+		self.x.clear();
+		self.y.clear();
+		self.z.clear();
+		for particle in particles {
+			let (x, y, z) = particle.xyz();
+			self.x.push(x);
+			self.y.push(y);
+			self.z.push(z);
+		}
+		self.put_back_in_box(box_side);
+	}
+
+	/// The number of particles stored
+	pub fn len(&self) -> usize {
+		self.x.len()
+	}
+
+	/// Whether no particle is stored
+	pub fn is_empty(&self) -> bool {
+		self.x.is_empty()
+	}
+
+	/// The coordinates of a particle
+	///
+	/// # Arguments
+	///
+	/// * `i` - The index of the particle
+	pub fn coordinates(&self, i: usize) -> Point3 {
+		Point3::from(self.x[i], self.y[i], self.z[i])
+	}
+
+	/// Put the coordinates back in the box centered on the origin, so that every component of a displacement between
+	/// 2 particles is smaller than the box side
+	///
+	/// # Arguments
+	///
+	/// * `box_side` - The side of the cubic box
+	pub fn put_back_in_box(&mut self, box_side: f64) {
+		for coordinates in [&mut self.x, &mut self.y, &mut self.z] {
+			for c in coordinates.iter_mut() {
+				*c -= box_side * (*c / box_side).round();
+			}
+		}
+	}
+}
+
+/// The number of partial sums of [`sum_in_lanes`]
+const LANES: usize = 4;
+
+/// Sum the given values in several partial sums, since the compiler may not reorder floating point sums by itself
+pub(crate) fn sum_in_lanes(values: &[f64]) -> f64 {
+	let mut lanes = [0.0; LANES];
+	let chunks = values.chunks_exact(LANES);
+	let remainder = chunks.remainder();
+	for chunk in chunks {
+		for lane in 0..LANES {
+			lanes[lane] += chunk[lane];
+		}
+	}
+	return lanes.iter().sum::<f64>() + remainder.iter().sum::<f64>();
+}
+
+/// The nearest image of a component of a displacement, smaller than the box side, without branches
+#[inline(always)]
+pub(crate) fn wrap(c: f64, box_side: f64) -> f64 {
+	let half = box_side / 2.0;
+	let c = if c > half { c - box_side } else { c };
+	return if c < -half { c + box_side } else { c };
+}
+
+impl System {
+	/// Update the [structure of arrays](ParticleArrays) of the system for the current positions, if the vectorized
+	/// [pair search](PairSearch) is used, so that the pair kernel doesn't convert the particles at each evaluation
+	pub fn update_particle_arrays(&mut self) {
+		if self.parameters.pair_search != PairSearch::Vectorized {
+			self.particle_arrays = None;
+			return;
+		}
+		let box_side = self.parameters.box_side;
+		self.particle_arrays
+			.get_or_insert_with(ParticleArrays::default)
+			.update(&self.particles, box_side);
+	}
+
+	/// The [structure of arrays](ParticleArrays) kept by the system, or converted from the particles if the system
+	/// doesn't keep it
+	pub(crate) fn particle_arrays_or_converted(&self) -> Cow<'_, ParticleArrays> {
+		match &self.particle_arrays {
+			Some(arrays) if arrays.len() == self.nb_particles_total() => Cow::Borrowed(arrays),
+			_ => Cow::Owned(ParticleArrays::from_particles(&self.particles, self.parameters.box_side)),
+		}
+	}
+
+	/// Evaluate the forces, the energy and the virial with periodic conditions, visiting every pair once with the
+	/// nearest image of the second particle like [`PairSearch::MinimumImage`],
+	/// but from a [structure of arrays](ParticleArrays) and without branches so that the compiler vectorizes the kernel.
+	/// The kernel is only vectorized if the potential has no branches, like [`LennardJones`](crate::potential::LennardJones).
+	/// The pairs [excluded](crate::topology::Exclusions) by the topology are skipped.
+	/// When the cut is larger than half the box, several images can be in the cut, so all the images are considered.
+	/// The arrays kept by the system must be [up to date](System::update_particle_arrays) with the positions.
+	///
+	/// # Arguments
+	///
+	/// * `potential` - The interaction between the particles of a pair, which may depend on their species
+	pub fn evaluate_forces_vectorized(&self, potential: &impl Interactions) -> ForceEvaluation {
+		let box_side = self.parameters.box_side;
+		let radius_cut_squared = self.parameters.r_cut.powi(2);
+		if self.parameters.r_cut > box_side / 2.0 {
+			return self.accumulate_forces(potential, |f| self.for_each_pair_minimum_image(self.parameters.r_cut, f));
+		}
+
+		let arrays = self.particle_arrays_or_converted();
+		let n = arrays.len();
+		let species: Vec<usize> = self.particles.iter().map(|particle| particle.species).collect();
+
+		let (mut fx, mut fy, mut fz) = (vec![0.0; n], vec![0.0; n], vec![0.0; n]);
+		let mut energy = 0.0;
+		let mut virial = 0.0;
+
+		// The contributions of the pairs of a particle, computed in a first loop which vectorizes, then summed
+		let (mut gx, mut gy, mut gz) = (vec![0.0; n], vec![0.0; n], vec![0.0; n]);
+		let mut energies = vec![0.0; n];
+		let mut virials = vec![0.0; n];
+		// Whether each pair of a particle is excluded by the topology, as a mask so that the kernel has no branches
+		let mut excluded = vec![false; n];
+
+		for i in 0..n {
+			let (xi, yi, zi) = (arrays.x[i], arrays.y[i], arrays.z[i]);
+			let species_i = species[i];
+			let m = n - i - 1;
+			let others = (i + 1)..n;
+
+			let (gx, gy, gz) = (&mut gx[..m], &mut gy[..m], &mut gz[..m]);
+			let (energies, virials) = (&mut energies[..m], &mut virials[..m]);
+			let excluded = &mut excluded[..m];
+			excluded.fill(false);
+			for &j in self.exclusions.of(i).iter().filter(|&&j| j > i) {
+				excluded[j - i - 1] = true;
+			}
+			let (xj, yj, zj) = (&arrays.x[others.clone()], &arrays.y[others.clone()], &arrays.z[others.clone()]);
+			let species_j = &species[others.clone()];
+			let (fxj, fyj, fzj) = (&mut fx[others.clone()], &mut fy[others.clone()], &mut fz[others]);
+			for k in 0..m {
+				let dx = wrap(xi - xj[k], box_side);
+				let dy = wrap(yi - yj[k], box_side);
+				let dz = wrap(zi - zj[k], box_side);
+				let distance_squared = dx * dx + dy * dy + dz * dz;
+
+				// Pairs beyond the cut contribute 0, instead of being skipped
+				// A single potential ignores the species, so the kernel still vectorizes
+				let (energy, force) = potential.between(species_i, species_j[k]).energy_and_force(distance_squared);
+				let inside = distance_squared <= radius_cut_squared && !excluded[k];
+				let factor = if inside { -force } else { 0.0 };
+
+				gx[k] = dx * factor;
+				gy[k] = dy * factor;
+				gz[k] = dz * factor;
+				energies[k] = if inside { energy } else { 0.0 };
+				virials[k] = -(dx * gx[k] + dy * gy[k] + dz * gz[k]);
+
+				fxj[k] -= gx[k];
+				fyj[k] -= gy[k];
+				fzj[k] -= gz[k];
+			}
+
+			fx[i] += sum_in_lanes(gx);
+			fy[i] += sum_in_lanes(gy);
+			fz[i] += sum_in_lanes(gz);
+			energy += sum_in_lanes(energies);
+			virial += sum_in_lanes(virials);
+		}
+
+		return ForceEvaluation {
+			gradients: (0..n).map(|i| Vector3::from(fx[i], fy[i], fz[i])).collect(),
+			energy,
+			virial,
+		};
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::assert_approx_eq;
+
+	#[test]
+	fn particles_outside_of_the_box_are_wrapped() {
+		let mut system = System::from_file(std::path::Path::new("dataset/particles.xyz"), 0).unwrap();
+		system.parameters.r_cut = 20.0;
+		for particle in system.particles.iter_mut() {
+			particle.coordinates =
+				(particle.coordinates + Vector3::from(0.7, -1.6, 0.2) * system.parameters.box_side).as_point();
+		}
+
+		let expected = system.accumulate_forces(&system.potential(), |f| system.for_each_pair_minimum_image(20.0, f));
+		let actual = system.evaluate_forces_vectorized(&system.potential());
+		assert_approx_eq!(actual.energy, expected.energy);
+		assert_approx_eq!(actual.virial, expected.virial);
+		for (expected, actual) in expected.gradients.iter().zip(&actual.gradients) {
+			assert_approx_eq!(expected.x(), actual.x());
+			assert_approx_eq!(expected.y(), actual.y());
+			assert_approx_eq!(expected.z(), actual.z());
+		}
+	}
+
+	#[test]
+	fn arrays_follow_the_particles() {
+		let mut system = System::from_file(std::path::Path::new("dataset/particles.xyz"), 0).unwrap();
+		assert!(system.particle_arrays.is_none());
+
+		system.parameters.pair_search = PairSearch::Vectorized;
+		system.update_particle_arrays();
+		let box_side = system.parameters.box_side;
+		for _ in 0..3 {
+			system.step();
+			assert_eq!(
+				system.particle_arrays,
+				Some(ParticleArrays::from_particles(&system.particles, box_side))
+			);
+			system.drift(0.5);
+			assert_eq!(
+				system.particle_arrays,
+				Some(ParticleArrays::from_particles(&system.particles, box_side))
+			);
+		}
+	}
+}
@@ -0,0 +1,424 @@
+//! Electrostatics with the smooth Particle Mesh Ewald summation (PME), for many charged particles in the periodic box
+//!
+//! The real space sum, the constant terms and the corrections of the excluded pairs are the ones of the
+//! [Ewald summation](crate::ewald), but the reciprocal space sum is evaluated on a grid: the charges are spread on the
+//! grid with cardinal B-splines, the grid is convolved with the Ewald kernel through a 3D FFT, and the forces are
+//! interpolated back with the derivatives of the same B-splines. The cost is O(N log N) instead of O(N^{3/2}), and
+//! the forces are exactly opposite to the gradients of the energy.
+
+use std::{f64::consts::PI, sync::Arc};
+
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	algebra::Vector3,
+	ewald::{COULOMB_CONSTANT, EWALD_ACCURACY, Ewald},
+	forces::ForceEvaluation,
+	system::System,
+};
+
+/// Order of the B-splines which spread the charges on the grid, unless given: quintic splines, since the cubic ones
+/// need much finer grids to reach the accuracy of the Ewald summation
+pub const PME_ORDER: usize = 6;
+
+/// The parameters of the smooth Particle Mesh Ewald summation
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ParticleMeshEwald {
+	/// Relative accuracy of both sums, as for the [Ewald summation](Ewald::accuracy)
+	pub accuracy: f64,
+	/// Splitting parameter α, in 1/Å, chosen from the accuracy and the cut if absent
+	pub alpha: Option<f64>,
+	/// Number of grid points along each direction, chosen from the accuracy if absent
+	pub grid_size: Option<usize>,
+	/// Order of the B-splines, i.e. the number of grid points along each direction a charge is spread on
+	pub order: usize,
+}
+
+impl Default for ParticleMeshEwald {
+	fn default() -> Self {
+		Self {
+			accuracy: EWALD_ACCURACY,
+			alpha: None,
+			grid_size: None,
+			order: PME_ORDER,
+		}
+	}
+}
+
+impl ParticleMeshEwald {
+	/// The [Ewald summation](Ewald) of the same accuracy and splitting, whose real space sum and constant terms are used
+	pub fn ewald(&self) -> Ewald {
+		Ewald {
+			accuracy: self.accuracy,
+			alpha: self.alpha,
+			k_max: None,
+		}
+	}
+
+	/// The number of grid points along each direction, so that the grid holds twice the wave vectors the Ewald
+	/// summation would need, rounded up to a size whose FFT is fast
+	///
+	/// # Arguments
+	///
+	/// * `alpha` - The splitting parameter
+	/// * `box_side` - The side of the cubic box
+	pub fn grid_size(&self, alpha: f64, box_side: f64) -> usize {
+		self.grid_size.unwrap_or_else(|| {
+			let k_max = self.ewald().k_max(alpha, box_side);
+			fast_fft_size((4 * k_max + 1).max(self.order))
+		})
+	}
+}
+
+/// The smallest size not below the given one whose only prime factors are 2, 3 and 5
+///
+/// # Arguments
+///
+/// * `minimum` - The smallest acceptable size
+fn fast_fft_size(minimum: usize) -> usize {
+	let mut size = minimum.max(1);
+	loop {
+		let mut remainder = size;
+		for factor in [2, 3, 5] {
+			while remainder.is_multiple_of(factor) {
+				remainder /= factor;
+			}
+		}
+		if remainder == 1 {
+			return size;
+		}
+		size += 1;
+	}
+}
+
+/// The values `M_n(w + j)` of the cardinal B-spline of order `n`, and their derivatives, for `j` in `0..n`
+///
+/// # Arguments
+///
+/// * `w` - The offset, in [0, 1)
+/// * `order` - The order `n` of the B-spline, at least 2
+fn bspline(w: f64, order: usize) -> (Vec<f64>, Vec<f64>) {
+	// M_1 is 1 on [0, 1), and 0 elsewhere
+	let mut values = vec![0.0; order];
+	let mut derivatives = vec![0.0; order];
+	values[0] = 1.0;
+	for n in 2..=order {
+		if n == order {
+			// M_n'(x) = M_{n-1}(x) - M_{n-1}(x - 1)
+			for j in 0..order {
+				derivatives[j] = values[j] - if j > 0 { values[j - 1] } else { 0.0 };
+			}
+		}
+		// M_n(x) = (x M_{n-1}(x) + (n - x) M_{n-1}(x - 1)) / (n - 1), from the end so that M_{n-1}(x - 1) is still there
+		for j in (0..n).rev() {
+			let x = w + j as f64;
+			let previous = if j > 0 { values[j - 1] } else { 0.0 };
+			values[j] = (x * values[j] + (n as f64 - x) * previous) / (n - 1) as f64;
+		}
+	}
+	return (values, derivatives);
+}
+
+/// The squared modulus `|b(m)|²` of the Euler exponential spline factors, for each frequency `m` of the grid, which
+/// correct the interpolation of `exp(2iπmu/K)` by the B-splines
+///
+/// # Arguments
+///
+/// * `grid_size` - The number of grid points `K` along the direction
+/// * `order` - The order of the B-splines
+fn bspline_moduli(grid_size: usize, order: usize) -> Vec<f64> {
+	let (values, _) = bspline(0.0, order);
+	let mut moduli: Vec<f64> = (0..grid_size)
+		.map(|m| {
+			// |Σ M_n(k + 1) exp(2iπmk/K)|² for k in 0..n-1
+			let (mut real, mut imaginary) = (0.0, 0.0);
+			for k in 0..order - 1 {
+				let angle = 2.0 * PI * (m * k) as f64 / grid_size as f64;
+				real += values[k + 1] * angle.cos();
+				imaginary += values[k + 1] * angle.sin();
+			}
+			real * real + imaginary * imaginary
+		})
+		.collect();
+
+	// With an odd order, the sum vanishes at the Nyquist frequency: interpolate from its neighbours instead
+	for m in 0..grid_size {
+		if moduli[m] < 1e-7 {
+			moduli[m] = (moduli[(m + grid_size - 1) % grid_size] + moduli[(m + 1) % grid_size]) / 2.0;
+		}
+	}
+	return moduli.iter().map(|modulus| 1.0 / modulus).collect();
+}
+
+/// Transform a cubic grid in place, along each of its 3 directions
+///
+/// # Arguments
+///
+/// * `grid` - The values of the grid, with the index `(x K + y) K + z` for the point `(x, y, z)`
+/// * `fft` - The 1D transform of size `K` applied along each direction
+fn fft_3d(grid: &mut [Complex<f64>], fft: &Arc<dyn Fft<f64>>) {
+	let size = fft.len();
+	// The z lines are contiguous
+	fft.process(grid);
+
+	let mut line = vec![Complex::default(); size];
+	for stride in [size, size * size] {
+		for start in 0..grid.len() {
+			// The first point of each line along the direction of the stride
+			if (start / stride) % size != 0 {
+				continue;
+			}
+			for k in 0..size {
+				line[k] = grid[start + k * stride];
+			}
+			fft.process(&mut line);
+			for k in 0..size {
+				grid[start + k * stride] = line[k];
+			}
+		}
+	}
+}
+
+/// How a charge is spread on the grid along one direction
+struct Spreading {
+	/// The grid points the charge is spread on
+	indices: Vec<usize>,
+	/// The weight of the charge on each of these grid points
+	weights: Vec<f64>,
+	/// The derivative of each weight with respect to the coordinate, in 1/Å
+	derivatives: Vec<f64>,
+}
+
+impl Spreading {
+	/// Spread a coordinate on the grid along one direction
+	///
+	/// # Arguments
+	///
+	/// * `coordinate` - The coordinate of the particle, in the box centered on the origin
+	/// * `box_side` - The side of the cubic box
+	/// * `grid_size` - The number of grid points along the direction
+	/// * `order` - The order of the B-splines
+	fn new(coordinate: f64, box_side: f64, grid_size: usize, order: usize) -> Self {
+		// The scaled fractional coordinate u, in [0, K)
+		let u = ((coordinate / box_side + 0.5).rem_euclid(1.0) * grid_size as f64).min(grid_size as f64 - 1e-12);
+		let first = u.floor();
+		let (weights, mut derivatives) = bspline(u - first, order);
+		for derivative in derivatives.iter_mut() {
+			*derivative *= grid_size as f64 / box_side;
+		}
+		// The weight M_n(u - k) of the grid point k = floor(u) - j is the j-th value
+		let indices = (0..order)
+			.map(|j| (first as isize - j as isize).rem_euclid(grid_size as isize) as usize)
+			.collect();
+		return Self {
+			indices,
+			weights,
+			derivatives,
+		};
+	}
+}
+
+impl System {
+	/// Evaluate the electrostatic forces, energy and virial with the smooth Particle Mesh Ewald summation.
+	/// Like [`System::energy_gradient`], the evaluation holds the [gradients](ForceEvaluation::gradients) of the energy.
+	///
+	/// # Arguments
+	///
+	/// * `pme` - The parameters of the summation
+	pub fn evaluate_particle_mesh_ewald(&self, pme: &ParticleMeshEwald) -> ForceEvaluation {
+		let alpha = pme.ewald().alpha(self.parameters.r_cut);
+		let grid_size = pme.grid_size(alpha, self.parameters.box_side);
+		let mut evaluation = self.ewald_real_space(alpha);
+		evaluation += &self.particle_mesh_ewald_reciprocal_space(alpha, grid_size, pme.order);
+		evaluation += &self.ewald_corrections(alpha);
+		evaluation += &self.ewald_exclusions(alpha);
+		return evaluation;
+	}
+
+	/// Evaluate the reciprocal space part of the Ewald summation on a grid, with the structure factor interpolated
+	/// by B-splines
+	///
+	/// # Arguments
+	///
+	/// * `alpha` - The splitting parameter
+	/// * `grid_size` - The number of grid points along each direction, at least the order
+	/// * `order` - The order of the B-splines, at least 3 so that the forces are continuous
+	pub fn particle_mesh_ewald_reciprocal_space(&self, alpha: f64, grid_size: usize, order: usize) -> ForceEvaluation {
+		let box_side = self.parameters.box_side;
+		let volume = box_side.powi(3);
+		let mut evaluation = ForceEvaluation::zero(self.nb_particles_total());
+		let grid_index = |x: usize, y: usize, z: usize| (x * grid_size + y) * grid_size + z;
+
+		// Spread the charges on the grid
+		let spreadings: Vec<[Spreading; 3]> = self
+			.particles
+			.iter()
+			.map(|particle| {
+				let (x, y, z) = particle.xyz();
+				[x, y, z].map(|coordinate| Spreading::new(coordinate, box_side, grid_size, order))
+			})
+			.collect();
+		let mut grid = vec![Complex::default(); grid_size.pow(3)];
+		for (particle, [x, y, z]) in self.particles.iter().zip(&spreadings) {
+			for (&i, &weight_x) in x.indices.iter().zip(&x.weights) {
+				for (&j, &weight_y) in y.indices.iter().zip(&y.weights) {
+					let charge_xy = particle.charge * weight_x * weight_y;
+					for (&k, &weight_z) in z.indices.iter().zip(&z.weights) {
+						grid[grid_index(i, j, k)].re += charge_xy * weight_z;
+					}
+				}
+			}
+		}
+
+		// Multiply the structure factor by the Ewald kernel in reciprocal space
+		let mut planner = FftPlanner::new();
+		fft_3d(&mut grid, &planner.plan_fft_forward(grid_size));
+		let moduli = bspline_moduli(grid_size, order);
+		let frequency = |m: usize| {
+			if m <= grid_size / 2 {
+				m as f64
+			} else {
+				m as f64 - grid_size as f64
+			}
+		};
+		for mx in 0..grid_size {
+			for my in 0..grid_size {
+				for mz in 0..grid_size {
+					let index = grid_index(mx, my, mz);
+					if index == 0 {
+						grid[index] = Complex::default();
+						continue;
+					}
+					let k = Vector3::from(frequency(mx), frequency(my), frequency(mz)) * (2.0 * PI / box_side);
+					let k_squared = k.norm_squared();
+					let kernel = 4.0 * PI * COULOMB_CONSTANT / volume * (-k_squared / (4.0 * alpha * alpha)).exp()
+						/ k_squared * moduli[mx] * moduli[my] * moduli[mz];
+
+					// Each wave vector is in the sum once, unlike in the Ewald summation which pairs k and -k
+					let energy = 0.5 * kernel * grid[index].norm_sqr();
+					evaluation.energy += energy;
+					evaluation.virial += energy * (1.0 - k_squared / (2.0 * alpha * alpha));
+					grid[index] *= kernel;
+				}
+			}
+		}
+
+		// The convolved grid is the derivative of the energy with respect to the charge on each grid point
+		fft_3d(&mut grid, &planner.plan_fft_inverse(grid_size));
+		for (i, (particle, [x, y, z])) in self.particles.iter().zip(&spreadings).enumerate() {
+			let mut gradient = Vector3::zero();
+			for a in 0..order {
+				for b in 0..order {
+					for c in 0..order {
+						let potential = grid[grid_index(x.indices[a], y.indices[b], z.indices[c])].re;
+						gradient += Vector3::from(
+							x.derivatives[a] * y.weights[b] * z.weights[c],
+							x.weights[a] * y.derivatives[b] * z.weights[c],
+							x.weights[a] * y.weights[b] * z.derivatives[c],
+						) * potential;
+					}
+				}
+			}
+			evaluation.gradients[i] = gradient * particle.charge;
+		}
+		return evaluation;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{ewald::Electrostatics, ewald::tests::assert_forces_are_the_gradients, parameters::SimulationParameters};
+
+	#[test]
+	fn bsplines_are_a_partition_of_unity() {
+		for order in 3..=8 {
+			for w in [0.0, 0.25, 0.5, 0.9] {
+				let (values, derivatives) = bspline(w, order);
+				assert!((values.iter().sum::<f64>() - 1.0).abs() < 1e-14);
+				assert!(derivatives.iter().sum::<f64>().abs() < 1e-14);
+			}
+		}
+		// The cubic B-spline at the grid points
+		let (values, _) = bspline(0.0, 4);
+		for (value, expected) in values.iter().zip([0.0, 1.0 / 6.0, 2.0 / 3.0, 1.0 / 6.0]) {
+			assert!((value - expected).abs() < 1e-15);
+		}
+	}
+
+	#[test]
+	fn fft_sizes_only_have_small_prime_factors() {
+		assert_eq!(fast_fft_size(7), 8);
+		assert_eq!(fast_fft_size(31), 32);
+		assert_eq!(fast_fft_size(61), 64);
+		assert_eq!(fast_fft_size(45), 45);
+	}
+
+	/// Random charges in a box, neutral overall
+	fn charged_system(nb_particles: usize) -> System {
+		let mut parameters = SimulationParameters {
+			box_side: 15.0,
+			r_cut: 6.0,
+			..Default::default()
+		};
+		parameters.electrostatics = Some(Electrostatics::ParticleMeshEwald(ParticleMeshEwald::default()));
+		let mut xyz = format!("{nb_particles}\nProperties=species:S:1:pos:R:3:charge:R:1\n");
+		for i in 0..nb_particles {
+			// A deterministic scattering of the particles in the box
+			let coordinate = |n: usize| ((i * n + 7) % 97) as f64 / 97.0 * 15.0 - 7.5 + 0.01 * n as f64;
+			let charge = if i % 2 == 0 { 0.8 } else { -0.8 };
+			xyz.push_str(&format!("Q {} {} {} {charge}\n", coordinate(13), coordinate(31), coordinate(59)));
+		}
+		return System::from_str_with_parameters(&xyz, 0, parameters).unwrap();
+	}
+
+	#[test]
+	fn particle_mesh_matches_the_ewald_summation() {
+		let system = charged_system(40);
+		let pme = ParticleMeshEwald::default();
+		let mesh = system.evaluate_particle_mesh_ewald(&pme);
+		let direct = system.evaluate_ewald(&pme.ewald());
+
+		assert!(
+			(mesh.energy - direct.energy).abs() < 1e-5 * direct.energy.abs(),
+			"{} != {}",
+			mesh.energy,
+			direct.energy
+		);
+		assert!((mesh.virial - direct.virial).abs() < 1e-4 * direct.virial.abs());
+		let largest_force = direct.gradients.iter().map(|force| force.norm()).fold(0.0, f64::max);
+		for (mesh_force, direct_force) in mesh.gradients.iter().zip(&direct.gradients) {
+			assert!((*mesh_force - *direct_force).norm() < 1e-4 * largest_force);
+		}
+	}
+
+	#[test]
+	fn accuracy_improves_with_the_grid_and_the_order() {
+		let system = charged_system(40);
+		let direct = system.evaluate_ewald(&ParticleMeshEwald::default().ewald()).energy;
+		let error = |grid_size, order| {
+			let pme = ParticleMeshEwald {
+				grid_size: Some(grid_size),
+				order,
+				..Default::default()
+			};
+			(system.evaluate_particle_mesh_ewald(&pme).energy - direct).abs()
+		};
+		assert!(error(32, 4) < error(16, 4));
+		assert!(error(16, 6) < error(16, 4));
+	}
+
+	#[test]
+	fn forces_are_the_gradients_of_the_energy() {
+		for order in [3, 4, 5] {
+			let pme = ParticleMeshEwald {
+				grid_size: Some(12),
+				order,
+				..Default::default()
+			};
+			assert_forces_are_the_gradients(&charged_system(10), Electrostatics::ParticleMeshEwald(pme));
+		}
+	}
+}
@@ -1,8 +1,4 @@
-use crate::{
-	algebra::Vector3,
-	parameters::{EPSILON_STAR, R_STAR},
-	system::{Particle, System},
-};
+use crate::{algebra::Vector3, potential::PairPotential, species::Interactions, system::System};
 
 /// Computes the neighbors in 3D of the simulation box.
 pub fn neighboring_3d_translations(box_side: f64) -> Vec<Vector3> {
@@ -23,14 +19,37 @@ pub fn neighboring_3d_translations(box_side: f64) -> Vec<Vector3> {
 	return symmetries;
 }
 
+/// Computes the displacement to the nearest image of a particle, i.e. the minimum image convention.
+///
+/// # Arguments
+///
+/// * `displacement` - The displacement between 2 particles
+/// * `box_side` - The side of the cubic box
+pub fn minimum_image(displacement: Vector3, box_side: f64) -> Vector3 {
+	let wrap = |c: f64| c - box_side * (c / box_side).round();
+	return Vector3::from(wrap(displacement.x()), wrap(displacement.y()), wrap(displacement.z()));
+}
+
 impl System {
-	/// Compute the microscopic energy in the system, according to the Lennard-Jones potential, with periodic conditions.
+	/// Compute the microscopic energy in the system with periodic conditions, according to its [potential](System::potential).
 	pub fn microscopic_energy_periodic(&self, translations: &[Vector3], radius_cut: f64) -> f64 {
+		self.microscopic_energy_periodic_with(&self.interactions(), translations, radius_cut)
+	}
+
+	/// Compute the microscopic energy in the system with periodic conditions, according to the given potential.
+	/// The pairs [excluded](crate::topology::Exclusions) by the topology are skipped, with all their images.
+	///
+	/// # Arguments
+	///
+	/// * `potential` - The interaction between the particles of a pair, which may depend on their species
+	/// * `translations` - The translations of the images of the box
+	/// * `radius_cut` - The distance above which interactions are ignored
+	pub fn microscopic_energy_periodic_with(&self, potential: &impl Interactions, translations: &[Vector3], radius_cut: f64) -> f64 {
 		let mut total = 0.0;
 		for sym in translations {
 			for i in 0..self.nb_particles_total() {
 				for j in 0..self.nb_particles_total() {
-					if i == j && *sym == Vector3::zero() {
+					if (i == j && *sym == Vector3::zero()) || self.exclusions.contains(i, j) {
 						continue;
 					}
 
@@ -59,27 +78,42 @@ impl System {
 						continue;
 					}
 
-					// The usual energy term (ISM 3)
-					let r_star_over_r_ij = R_STAR / dist_ij_squared.sqrt();
-					let u_ij = r_star_over_r_ij.powi(12) - 2.0 * r_star_over_r_ij.powi(6);
-					total += u_ij;
+					total += potential
+						.between(self.particles[i].species, self.particles[j].species)
+						.energy(dist_ij_squared);
 				}
 			}
 		}
 
-		return (4.0 * EPSILON_STAR * total) / 2.0;
+		// Each pair is counted twice
+		return total / 2.0;
 	}
 
 	/// Compute the forces between pairs of particles, with periodic conditions.
 	/// The new force that a particle j applies on particle i is the sum of forces of all its symmetries.
 	pub fn compute_forces_periodic(&self, translations: &[Vector3], radius_cut: f64) -> Vec<Vec<Vec<Vector3>>> {
+		self.compute_forces_periodic_with(&self.interactions(), translations, radius_cut)
+	}
+
+	/// Compute the forces between pairs of particles, with periodic conditions, according to the given potential.
+	/// The new force that a particle j applies on particle i is the sum of forces of all its symmetries.
+	/// The pairs [excluded](crate::topology::Exclusions) by the topology have no force, with all their images.
+	///
+	/// # Arguments
+	///
+	/// * `potential` - The interaction between the particles of a pair, which may depend on their species
+	/// * `translations` - The translations of the images of the box
+	/// * `radius_cut` - The distance above which interactions are ignored
+	pub fn compute_forces_periodic_with(
+		&self, potential: &impl Interactions, translations: &[Vector3], radius_cut: f64,
+	) -> Vec<Vec<Vec<Vector3>>> {
 		let mut forces =
 			vec![vec![vec![Vector3::zero(); self.nb_particles_total()]; self.nb_particles_total()]; translations.len()];
 
 		for (sym_idx, sym) in translations.iter().enumerate() {
 			for i in 0..self.nb_particles_total() {
 				for j in 0..self.nb_particles_total() {
-					if i == j && *sym == Vector3::zero() {
+					if (i == j && *sym == Vector3::zero()) || self.exclusions.contains(i, j) {
 						continue;
 					}
 
@@ -103,13 +137,9 @@ impl System {
 					}
 
 					// Gradient function for any coordinate
-					forces[sym_idx][i][j] += self.energy_gradient(
-						&self.particles[i],
-						&Particle {
-							coordinates: particle_j_with_symmetry,
-							momentum: self.particles[j].momentum,
-						},
-					);
+					forces[sym_idx][i][j] += potential
+						.between(self.particles[i].species, self.particles[j].species)
+						.gradient(self.particles[i].coordinates - particle_j_with_symmetry);
 				}
 			}
 		}
@@ -117,8 +147,59 @@ impl System {
 		return forces;
 	}
 
+	/// Call the given function on each pair of a particle and an image of another particle (or of itself) closer than
+	/// the cut, exactly once per pair, with the displacement from the image to the first particle.
+	///
+	/// # Arguments
+	///
+	/// * `translations` - The translations of the images, which must be symmetric, e.g. [`neighboring_3d_translations`]
+	/// * `radius_cut` - The distance above which interactions are ignored
+	/// * `f` - The function called on each pair
+	pub(crate) fn for_each_pair_in_images(&self, translations: &[Vector3], radius_cut: f64, mut f: impl FnMut(usize, usize, Vector3)) {
+		let radius_cut_squared = radius_cut.powi(2);
+		for sym in translations {
+			// The pair of a particle and its image by the opposite translation is the same pair
+			let with_itself = (sym.x(), sym.y(), sym.z()) > (0.0, 0.0, 0.0);
+			for i in 0..self.nb_particles_total() {
+				let first = if with_itself { i } else { i + 1 };
+				for j in first..self.nb_particles_total() {
+					let displacement = self.particles[i].coordinates - (self.particles[j].coordinates + sym);
+					if displacement.norm_squared() <= radius_cut_squared {
+						f(i, j, displacement);
+					}
+				}
+			}
+		}
+	}
+
+	/// Call the given function on each pair of particles closer than the cut, exactly once per pair, with the
+	/// displacement from the nearest image of the second particle to the first one.
+	/// When the cut is larger than half the box, several images can be in the cut,
+	/// so [`System::for_each_pair_in_images`] is used instead.
+	///
+	/// # Arguments
+	///
+	/// * `radius_cut` - The distance above which interactions are ignored
+	/// * `f` - The function called on each pair
+	pub(crate) fn for_each_pair_minimum_image(&self, radius_cut: f64, mut f: impl FnMut(usize, usize, Vector3)) {
+		let box_side = self.parameters.box_side;
+		if radius_cut > box_side / 2.0 {
+			return self.for_each_pair_in_images(&neighboring_3d_translations(box_side), radius_cut, f);
+		}
+
+		let radius_cut_squared = radius_cut.powi(2);
+		for i in 0..self.nb_particles_total() {
+			for j in (i + 1)..self.nb_particles_total() {
+				let displacement = minimum_image(self.particles[i].coordinates - self.particles[j].coordinates, box_side);
+				if displacement.norm_squared() <= radius_cut_squared {
+					f(i, j, displacement);
+				}
+			}
+		}
+	}
+
 	/// Compute the sum of all the forces between pairs of particles in the system with periodic conditions
-	pub fn sum_of_forces_periodic(forces: &Vec<Vec<Vec<Vector3>>>) -> Vector3 {
+	pub fn sum_of_forces_periodic(forces: &[Vec<Vec<Vector3>>]) -> Vector3 {
 		let mut sx = 0.0;
 		let mut sy = 0.0;
 		let mut sz = 0.0;
@@ -0,0 +1,423 @@
+//! Pair potentials, giving the energy and the force between 2 particles from their distance
+//!
+//! The energy and force routines of the [system](crate::system::System) are generic over [`PairPotential`],
+//! so that another potential can be used without rewriting the loops over the pairs.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{algebra::Vector3, tabulated::TabulatedPotential};
+
+/// An interaction between 2 particles which only depends on their distance
+pub trait PairPotential {
+	/// The energy of a pair of particles
+	///
+	/// # Arguments
+	///
+	/// * `distance_squared` - The distance between the particles, squared
+	fn energy(&self, distance_squared: f64) -> f64;
+
+	/// The intensity of the force between a pair of particles divided by their distance, i.e. -U'(r)/r,
+	/// positive when the particles repel each other.
+	/// The force applied on the first particle is this times the displacement from the second particle to the first one.
+	///
+	/// # Arguments
+	///
+	/// * `distance_squared` - The distance between the particles, squared
+	fn force(&self, distance_squared: f64) -> f64;
+
+	/// Both the [energy](PairPotential::energy) and the [force](PairPotential::force) of a pair of particles,
+	/// which potentials can override to share their intermediate results
+	///
+	/// # Arguments
+	///
+	/// * `distance_squared` - The distance between the particles, squared
+	fn energy_and_force(&self, distance_squared: f64) -> (f64, f64) {
+		(self.energy(distance_squared), self.force(distance_squared))
+	}
+
+	/// The gradient of the energy of a pair of particles, with respect to the first particle
+	///
+	/// # Arguments
+	///
+	/// * `displacement` - The displacement from the second particle to the first one
+	fn gradient(&self, displacement: Vector3) -> Vector3 {
+		displacement * -self.force(displacement.norm_squared())
+	}
+}
+
+/// The Lennard-Jones potential of the course, U(r) = 4ε((r*/r)^12 - 2(r*/r)^6)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LennardJones {
+	/// Distance at which the potential reaches its minimum
+	pub r_star: f64,
+	/// Depth of the potential well
+	pub epsilon_star: f64,
+}
+
+impl LennardJones {
+	/// Create a Lennard-Jones potential
+	///
+	/// # Arguments
+	///
+	/// * `r_star` - Distance at which the potential reaches its minimum
+	/// * `epsilon_star` - Depth of the potential well
+	pub fn new(r_star: f64, epsilon_star: f64) -> Self {
+		Self { r_star, epsilon_star }
+	}
+
+	/// (r*/r)^6
+	#[inline(always)]
+	fn r_star_over_r_pow6(&self, distance_squared: f64) -> f64 {
+		let r_star_over_r_squared = self.r_star * self.r_star / distance_squared;
+		return r_star_over_r_squared * r_star_over_r_squared * r_star_over_r_squared;
+	}
+}
+
+impl PairPotential for LennardJones {
+	#[inline(always)]
+	fn energy(&self, distance_squared: f64) -> f64 {
+		let s6 = self.r_star_over_r_pow6(distance_squared);
+		return 4.0 * self.epsilon_star * (s6 * s6 - 2.0 * s6);
+	}
+
+	#[inline(always)]
+	fn force(&self, distance_squared: f64) -> f64 {
+		let s6 = self.r_star_over_r_pow6(distance_squared);
+		return 48.0 * self.epsilon_star * (s6 * s6 - s6) / distance_squared;
+	}
+
+	#[inline(always)]
+	fn energy_and_force(&self, distance_squared: f64) -> (f64, f64) {
+		let s6 = self.r_star_over_r_pow6(distance_squared);
+		let energy = 4.0 * self.epsilon_star * (s6 * s6 - 2.0 * s6);
+		let force = 48.0 * self.epsilon_star * (s6 * s6 - s6) / distance_squared;
+		return (energy, force);
+	}
+}
+
+/// The Morse potential, U(r) = D((1 - exp(-a(r - r0)))² - 1), for bonded-like interactions
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Morse {
+	/// Depth of the potential well, D
+	pub depth: f64,
+	/// Inverse of the width of the potential well, a
+	pub stiffness: f64,
+	/// Distance at which the potential reaches its minimum, r0
+	pub equilibrium_distance: f64,
+}
+
+impl Morse {
+	/// Create a Morse potential
+	///
+	/// # Arguments
+	///
+	/// * `depth` - Depth of the potential well, D
+	/// * `stiffness` - Inverse of the width of the potential well, a
+	/// * `equilibrium_distance` - Distance at which the potential reaches its minimum, r0
+	pub fn new(depth: f64, stiffness: f64, equilibrium_distance: f64) -> Self {
+		Self {
+			depth,
+			stiffness,
+			equilibrium_distance,
+		}
+	}
+}
+
+impl PairPotential for Morse {
+	fn energy(&self, distance_squared: f64) -> f64 {
+		let exponential = (-self.stiffness * (distance_squared.sqrt() - self.equilibrium_distance)).exp();
+		return self.depth * ((1.0 - exponential).powi(2) - 1.0);
+	}
+
+	fn force(&self, distance_squared: f64) -> f64 {
+		let distance = distance_squared.sqrt();
+		let exponential = (-self.stiffness * (distance - self.equilibrium_distance)).exp();
+		return -2.0 * self.depth * self.stiffness * exponential * (1.0 - exponential) / distance;
+	}
+}
+
+/// The Buckingham (exp-6) potential, U(r) = A exp(-r/ρ) - C/r^6
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Buckingham {
+	/// Strength of the repulsion, A
+	pub a: f64,
+	/// Range of the repulsion, ρ
+	pub rho: f64,
+	/// Strength of the dispersion, C
+	pub c: f64,
+}
+
+impl Buckingham {
+	/// Create a Buckingham potential
+	///
+	/// # Arguments
+	///
+	/// * `a` - Strength of the repulsion, A
+	/// * `rho` - Range of the repulsion, ρ
+	/// * `c` - Strength of the dispersion, C
+	pub fn new(a: f64, rho: f64, c: f64) -> Self {
+		Self { a, rho, c }
+	}
+}
+
+impl PairPotential for Buckingham {
+	fn energy(&self, distance_squared: f64) -> f64 {
+		return self.a * (-distance_squared.sqrt() / self.rho).exp() - self.c / distance_squared.powi(3);
+	}
+
+	fn force(&self, distance_squared: f64) -> f64 {
+		let distance = distance_squared.sqrt();
+		let repulsion = self.a / self.rho * (-distance / self.rho).exp() / distance;
+		return repulsion - 6.0 * self.c / distance_squared.powi(4);
+	}
+}
+
+/// The generalized Mie n-m potential, U(r) = C ε ((σ/r)^n - (σ/r)^m), with C = n/(n-m) (n/m)^(m/(n-m))
+/// so that the depth of the well is ε. The Mie 12-6 potential is the usual Lennard-Jones potential.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mie {
+	/// Exponent of the repulsion, n
+	pub n: f64,
+	/// Exponent of the attraction, m, smaller than n
+	pub m: f64,
+	/// Distance at which the potential is 0, σ
+	pub sigma: f64,
+	/// Depth of the potential well, ε
+	pub epsilon: f64,
+}
+
+impl Mie {
+	/// Create a Mie potential
+	///
+	/// # Arguments
+	///
+	/// * `n` - Exponent of the repulsion
+	/// * `m` - Exponent of the attraction, smaller than n
+	/// * `sigma` - Distance at which the potential is 0
+	/// * `epsilon` - Depth of the potential well
+	pub fn new(n: f64, m: f64, sigma: f64, epsilon: f64) -> Self {
+		Self { n, m, sigma, epsilon }
+	}
+
+	/// The prefactor C ε
+	pub(crate) fn prefactor(&self) -> f64 {
+		self.n / (self.n - self.m) * (self.n / self.m).powf(self.m / (self.n - self.m)) * self.epsilon
+	}
+}
+
+impl PairPotential for Mie {
+	fn energy(&self, distance_squared: f64) -> f64 {
+		let sigma_over_r_squared = self.sigma * self.sigma / distance_squared;
+		return self.prefactor() * (sigma_over_r_squared.powf(self.n / 2.0) - sigma_over_r_squared.powf(self.m / 2.0));
+	}
+
+	fn force(&self, distance_squared: f64) -> f64 {
+		let sigma_over_r_squared = self.sigma * self.sigma / distance_squared;
+		let repulsion = self.n * sigma_over_r_squared.powf(self.n / 2.0);
+		let attraction = self.m * sigma_over_r_squared.powf(self.m / 2.0);
+		return self.prefactor() * (repulsion - attraction) / distance_squared;
+	}
+}
+
+/// The Weeks-Chandler-Andersen potential, i.e. the purely repulsive part of the [Lennard-Jones](LennardJones) potential:
+/// cut at its minimum r* and shifted up by its depth, so that the energy and the force are continuous
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WeeksChandlerAndersen {
+	/// Distance at which the Lennard-Jones potential reaches its minimum, above which there is no interaction
+	pub r_star: f64,
+	/// Depth of the Lennard-Jones potential well
+	pub epsilon_star: f64,
+}
+
+impl WeeksChandlerAndersen {
+	/// Create a Weeks-Chandler-Andersen potential
+	///
+	/// # Arguments
+	///
+	/// * `r_star` - Distance at which the Lennard-Jones potential reaches its minimum
+	/// * `epsilon_star` - Depth of the Lennard-Jones potential well
+	pub fn new(r_star: f64, epsilon_star: f64) -> Self {
+		Self { r_star, epsilon_star }
+	}
+
+	/// The Lennard-Jones potential which is cut
+	fn lennard_jones(&self) -> LennardJones {
+		LennardJones::new(self.r_star, self.epsilon_star)
+	}
+}
+
+impl PairPotential for WeeksChandlerAndersen {
+	fn energy(&self, distance_squared: f64) -> f64 {
+		if distance_squared >= self.r_star * self.r_star {
+			return 0.0;
+		}
+		// The minimum of the Lennard-Jones potential of the course is -4ε
+		return self.lennard_jones().energy(distance_squared) + 4.0 * self.epsilon_star;
+	}
+
+	fn force(&self, distance_squared: f64) -> f64 {
+		if distance_squared >= self.r_star * self.r_star {
+			return 0.0;
+		}
+		return self.lennard_jones().force(distance_squared);
+	}
+}
+
+/// The Yukawa (screened Coulomb) potential, U(r) = A exp(-κr)/r
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Yukawa {
+	/// Strength of the interaction, A, positive when repulsive
+	pub strength: f64,
+	/// Inverse of the screening length, κ
+	pub kappa: f64,
+}
+
+impl Yukawa {
+	/// Create a Yukawa potential
+	///
+	/// # Arguments
+	///
+	/// * `strength` - Strength of the interaction, positive when repulsive
+	/// * `kappa` - Inverse of the screening length
+	pub fn new(strength: f64, kappa: f64) -> Self {
+		Self { strength, kappa }
+	}
+}
+
+impl PairPotential for Yukawa {
+	fn energy(&self, distance_squared: f64) -> f64 {
+		let distance = distance_squared.sqrt();
+		return self.strength * (-self.kappa * distance).exp() / distance;
+	}
+
+	fn force(&self, distance_squared: f64) -> f64 {
+		let distance = distance_squared.sqrt();
+		return self.strength * (-self.kappa * distance).exp() * (self.kappa * distance + 1.0) / (distance_squared * distance);
+	}
+}
+
+/// One of the built-in potentials, which can be chosen in the [parameters](crate::parameters::SimulationParameters)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Potential {
+	LennardJones(LennardJones),
+	Morse(Morse),
+	Buckingham(Buckingham),
+	Mie(Mie),
+	WeeksChandlerAndersen(WeeksChandlerAndersen),
+	Yukawa(Yukawa),
+	Tabulated(TabulatedPotential),
+}
+
+impl PairPotential for Potential {
+	fn energy(&self, distance_squared: f64) -> f64 {
+		match self {
+			Self::LennardJones(potential) => potential.energy(distance_squared),
+			Self::Morse(potential) => potential.energy(distance_squared),
+			Self::Buckingham(potential) => potential.energy(distance_squared),
+			Self::Mie(potential) => potential.energy(distance_squared),
+			Self::WeeksChandlerAndersen(potential) => potential.energy(distance_squared),
+			Self::Yukawa(potential) => potential.energy(distance_squared),
+			Self::Tabulated(potential) => potential.energy(distance_squared),
+		}
+	}
+
+	fn force(&self, distance_squared: f64) -> f64 {
+		match self {
+			Self::LennardJones(potential) => potential.force(distance_squared),
+			Self::Morse(potential) => potential.force(distance_squared),
+			Self::Buckingham(potential) => potential.force(distance_squared),
+			Self::Mie(potential) => potential.force(distance_squared),
+			Self::WeeksChandlerAndersen(potential) => potential.force(distance_squared),
+			Self::Yukawa(potential) => potential.force(distance_squared),
+			Self::Tabulated(potential) => potential.force(distance_squared),
+		}
+	}
+
+	fn energy_and_force(&self, distance_squared: f64) -> (f64, f64) {
+		match self {
+			Self::LennardJones(potential) => potential.energy_and_force(distance_squared),
+			Self::Morse(potential) => potential.energy_and_force(distance_squared),
+			Self::Buckingham(potential) => potential.energy_and_force(distance_squared),
+			Self::Mie(potential) => potential.energy_and_force(distance_squared),
+			Self::WeeksChandlerAndersen(potential) => potential.energy_and_force(distance_squared),
+			Self::Yukawa(potential) => potential.energy_and_force(distance_squared),
+			Self::Tabulated(potential) => potential.energy_and_force(distance_squared),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::assert_approx_eq;
+
+	/// Check that the force is the opposite of the derivative of the energy, divided by the distance
+	fn assert_force_is_the_numerical_gradient(potential: &impl PairPotential, distances: &[f64]) {
+		let h = 1e-6;
+		for &r in distances {
+			let derivative = (potential.energy((r + h).powi(2)) - potential.energy((r - h).powi(2))) / (2.0 * h);
+			assert_approx_eq!(potential.force(r * r), -derivative / r);
+
+			let (energy, force) = potential.energy_and_force(r * r);
+			assert_approx_eq!(energy, potential.energy(r * r));
+			assert_approx_eq!(force, potential.force(r * r));
+		}
+	}
+
+	const DISTANCES: [f64; 6] = [2.8, 3.0, 3.4, 4.0, 5.5, 9.0];
+
+	#[test]
+	fn forces_are_the_gradients_of_the_energies() {
+		assert_force_is_the_numerical_gradient(&LennardJones::new(3.0, 0.2), &DISTANCES);
+		assert_force_is_the_numerical_gradient(&Morse::new(0.2, 1.5, 3.0), &DISTANCES);
+		assert_force_is_the_numerical_gradient(&Buckingham::new(1000.0, 0.3, 20.0), &DISTANCES);
+		assert_force_is_the_numerical_gradient(&Mie::new(14.0, 7.0, 3.0, 0.2), &DISTANCES);
+		assert_force_is_the_numerical_gradient(&WeeksChandlerAndersen::new(3.5, 0.2), &DISTANCES);
+		assert_force_is_the_numerical_gradient(&Yukawa::new(5.0, 0.5), &DISTANCES);
+		assert_force_is_the_numerical_gradient(&Potential::Yukawa(Yukawa::new(5.0, 0.5)), &DISTANCES);
+	}
+
+	#[test]
+	fn minima_are_where_expected() {
+		let morse = Morse::new(0.2, 1.5, 3.0);
+		assert_approx_eq!(morse.energy(9.0), -0.2);
+		assert_approx_eq!(morse.force(9.0), 0.0);
+
+		// The Mie 12-6 potential is the Lennard-Jones potential, whose minimum is at 2^(1/6) σ
+		let mie = Mie::new(12.0, 6.0, 3.0, 0.2);
+		let r_min = 2f64.powf(1.0 / 6.0) * 3.0;
+		assert_approx_eq!(mie.energy(r_min * r_min), -0.2);
+		assert_approx_eq!(mie.force(r_min * r_min), 0.0);
+		assert_approx_eq!(mie.energy(4.0), LennardJones::new(r_min, 0.05).energy(4.0));
+	}
+
+	#[test]
+	fn weeks_chandler_andersen_is_purely_repulsive() {
+		let wca = WeeksChandlerAndersen::new(3.0, 0.2);
+		for r in [2.5, 2.9, 2.999] {
+			assert!(wca.energy(r * r) > 0.0);
+			assert!(wca.force(r * r) > 0.0);
+		}
+		// Continuous at the cut
+		assert_approx_eq!(wca.energy(2.999999f64.powi(2)), 0.0);
+		assert_eq!(wca.energy(9.0), 0.0);
+		assert_eq!(wca.force(16.0), 0.0);
+	}
+
+	#[test]
+	fn lennard_jones_minimum_is_at_r_star() {
+		let potential = LennardJones::new(3.0, 0.2);
+		assert_approx_eq!(potential.energy(9.0), -4.0 * 0.2);
+		assert_approx_eq!(potential.force(9.0), 0.0);
+		// Repulsive closer, attractive further
+		assert!(potential.force(8.0) > 0.0);
+		assert!(potential.force(10.0) < 0.0);
+	}
+}
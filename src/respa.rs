@@ -0,0 +1,225 @@
+//! Multiple time step integration with r-RESPA (reversible reference system propagator algorithm)
+//!
+//! The interactions are split into a fast group, which changes quickly and is cheap to evaluate, and a slow group.
+//! The pair potential is split between an inner shell and an outer shell with the CHARMM switching function, so that
+//! both parts are smooth: the inner shell, with the [bonded interactions](crate::topology), is fast, while the outer
+//! shell, with the [electrostatic interactions](crate::ewald), is slow. The slow forces kick the momenta at the start
+//! and at the end of a time step, and in between the fast forces are integrated with velocity Verlet over several
+//! inner steps. Both groups add up to the whole potential, so the integrated energy is the one of the system.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	cell_list::CellList, cutoff::switching, forces::ForceEvaluation, integrator::Integrator, potential::PairPotential, system::System,
+};
+
+/// Distance beyond which the pair potential is only slow, unless given
+pub const RESPA_INNER_CUT: f64 = 6.0;
+/// Width of the switching of the pair potential from the fast group to the slow group, unless given
+pub const RESPA_SWITCH_WIDTH: f64 = 1.0;
+/// Number of inner steps of the fast forces per time step, unless given
+pub const RESPA_NB_INNER_STEPS: usize = 4;
+
+/// The parameters of r-RESPA
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Respa {
+	/// Distance beyond which the pair potential is in the slow group, smaller than the cut
+	pub inner_cut: f64,
+	/// Width of the switching of the pair potential from the fast group to the slow group, below the inner cut
+	pub switch_width: f64,
+	/// Number of inner steps of the fast forces per time step, each of `delta_time / nb_inner_steps`
+	pub nb_inner_steps: usize,
+}
+
+impl Default for Respa {
+	fn default() -> Self {
+		Self {
+			inner_cut: RESPA_INNER_CUT,
+			switch_width: RESPA_SWITCH_WIDTH,
+			nb_inner_steps: RESPA_NB_INNER_STEPS,
+		}
+	}
+}
+
+impl Respa {
+	/// The part of a pair potential in the inner shell, i.e. in the fast group
+	///
+	/// # Arguments
+	///
+	/// * `potential` - The whole pair potential
+	pub fn inner<P: PairPotential>(&self, potential: P) -> ShellPotential<P> {
+		ShellPotential {
+			potential,
+			shell: Shell::Inner,
+			inner_cut: self.inner_cut,
+			r_switch: self.inner_cut - self.switch_width,
+		}
+	}
+
+	/// The part of a pair potential in the outer shell, i.e. in the slow group
+	///
+	/// # Arguments
+	///
+	/// * `potential` - The whole pair potential
+	pub fn outer<P: PairPotential>(&self, potential: P) -> ShellPotential<P> {
+		ShellPotential {
+			shell: Shell::Outer,
+			..self.inner(potential)
+		}
+	}
+}
+
+/// A shell of the distances between 2 particles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+	/// The short distances, up to the inner cut
+	Inner,
+	/// The long distances, from the start of the switching
+	Outer,
+}
+
+/// The part of a pair potential in a [shell](Shell): the potential times the switching function for the inner shell,
+/// and times its complement for the outer one, so that both parts add up to the potential
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShellPotential<P> {
+	/// The whole potential
+	pub potential: P,
+	/// The shell which is kept
+	pub shell: Shell,
+	/// The distance at which the switching ends
+	inner_cut: f64,
+	/// The distance at which the switching starts
+	r_switch: f64,
+}
+
+impl<P: PairPotential> PairPotential for ShellPotential<P> {
+	fn energy(&self, distance_squared: f64) -> f64 {
+		self.energy_and_force(distance_squared).0
+	}
+
+	fn force(&self, distance_squared: f64) -> f64 {
+		self.energy_and_force(distance_squared).1
+	}
+
+	fn energy_and_force(&self, distance_squared: f64) -> (f64, f64) {
+		let (switch, switch_derivative) = switching(distance_squared, self.r_switch, self.inner_cut);
+		let (switch, switch_derivative) = match self.shell {
+			Shell::Inner if switch == 0.0 => return (0.0, 0.0),
+			Shell::Inner => (switch, switch_derivative),
+			Shell::Outer if switch == 1.0 => return (0.0, 0.0),
+			Shell::Outer => (1.0 - switch, -switch_derivative),
+		};
+		let (energy, force) = self.potential.energy_and_force(distance_squared);
+		return (energy * switch, force * switch - energy * switch_derivative);
+	}
+}
+
+impl Integrator for Respa {
+	fn integrate(&self, system: &mut System) {
+		let delta_time = system.parameters.delta_time;
+		let inner_time = delta_time / self.nb_inner_steps as f64;
+
+		// p += F_slow(t) dt/2
+		system.update_verlet_list();
+		system.kick(&system.evaluate_slow_forces(self).forces(), 0.5 * delta_time);
+
+		// Velocity Verlet of the fast forces, with the inner time step
+		let mut fast_forces = system.evaluate_fast_forces(self).forces();
+		for _ in 0..self.nb_inner_steps {
+			system.kick(&fast_forces, 0.5 * inner_time);
+			system.drift(inner_time);
+			fast_forces = system.evaluate_fast_forces(self).forces();
+			system.kick(&fast_forces, 0.5 * inner_time);
+			if system.has_constraints() {
+				system.constrain_momenta();
+			}
+		}
+
+		// p += F_slow(t + dt) dt/2
+		system.update_verlet_list();
+		system.kick(&system.evaluate_slow_forces(self).forces(), 0.5 * delta_time);
+		if system.has_constraints() {
+			system.constrain_momenta();
+		}
+	}
+}
+
+impl System {
+	/// Evaluate the fast group of interactions of r-RESPA: the inner shell of the pair potential, and the
+	/// [bonded interactions](System::evaluate_bonded).
+	/// The pairs are found with a cell list of the inner cut, whatever the [pair search](crate::parameters::PairSearch),
+	/// so that the inner steps only visit the close pairs.
+	///
+	/// # Arguments
+	///
+	/// * `respa` - The split of the interactions
+	pub fn evaluate_fast_forces(&self, respa: &Respa) -> ForceEvaluation {
+		let inner = self.interactions().map(|pair| respa.inner(pair.clone()));
+		let cell_list = CellList::new(&self.particles, self.parameters.box_side, respa.inner_cut);
+		let mut evaluation = self.accumulate_forces(&inner, |f| self.for_each_pair_within(&cell_list, respa.inner_cut, f));
+		if let Some(bonded) = self.evaluate_bonded() {
+			evaluation += &bonded;
+		}
+		return evaluation;
+	}
+
+	/// Evaluate the slow group of interactions of r-RESPA: the outer shell of the pair potential, with the configured
+	/// [pair search](crate::parameters::PairSearch), and the [electrostatic interactions](System::evaluate_electrostatics)
+	///
+	/// # Arguments
+	///
+	/// * `respa` - The split of the interactions
+	pub fn evaluate_slow_forces(&self, respa: &Respa) -> ForceEvaluation {
+		let outer = self.interactions().map(|pair| respa.outer(pair.clone()));
+		let mut evaluation = self.evaluate_forces_with(&outer);
+		if let Some(electrostatics) = self.evaluate_electrostatics() {
+			evaluation += &electrostatics;
+		}
+		return evaluation;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{assert_approx_eq, potential::LennardJones};
+
+	#[test]
+	fn shells_add_up_to_the_potential() {
+		let respa = Respa::default();
+		let potential = LennardJones::new(3.0, 0.2);
+		let (inner, outer) = (respa.inner(potential), respa.outer(potential));
+		for r in [2.8f64, 4.0, 5.0, 5.5, 5.99, 6.0, 8.0] {
+			assert_approx_eq!(inner.energy(r * r) + outer.energy(r * r), potential.energy(r * r));
+			assert_approx_eq!(inner.force(r * r) + outer.force(r * r), potential.force(r * r));
+		}
+		assert_eq!(outer.energy(16.0), 0.0);
+		assert_eq!(inner.energy(49.0), 0.0);
+
+		// The forces of both shells are the gradients of their energies
+		let h = 1e-6;
+		for shell in [inner, outer] {
+			for r in [5.2f64, 5.5, 5.8] {
+				let derivative = (shell.energy((r + h).powi(2)) - shell.energy((r - h).powi(2))) / (2.0 * h);
+				assert_approx_eq!(shell.force(r * r), -derivative / r);
+			}
+		}
+	}
+
+	#[test]
+	fn fast_and_slow_forces_add_up_to_the_forces() {
+		let system = System::from_file(std::path::Path::new("dataset/particles.xyz"), 0).unwrap();
+		let respa = Respa::default();
+		let mut split = system.evaluate_fast_forces(&respa);
+		split += &system.evaluate_slow_forces(&respa);
+		let whole = system.evaluate_forces();
+
+		let tolerance = 1e-9 * whole.energy.abs().max(1.0);
+		assert!((split.energy - whole.energy).abs() < tolerance);
+		assert!((split.virial - whole.virial).abs() < 1e-9 * whole.virial.abs().max(1.0));
+		for (split, whole) in split.gradients.iter().zip(&whole.gradients) {
+			assert!((*split - *whole).norm() < 1e-9 * whole.norm().max(1.0));
+		}
+	}
+}
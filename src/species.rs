@@ -0,0 +1,293 @@
+//! Particles of several species, each with its own mass and Lennard-Jones parameters
+//!
+//! The Lennard-Jones parameters of a pair of different species are combined from the ones of both species with a
+//! [mixing rule](MixingRule), unless they are given explicitly, as for non-additive mixtures such as Kob-Andersen.
+//! The species are named in the XYZ file, and configured by name in the [parameters](SimulationParameters).
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	parameters::SimulationParameters,
+	potential::{LennardJones, PairPotential},
+};
+
+/// The parameters of a species, the ones of the simulation being used for the missing ones
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpeciesParameters {
+	/// Mass of a particle of the species
+	pub mass: Option<f64>,
+	/// Distance at which the Lennard-Jones potential between 2 particles of the species reaches its minimum
+	pub r_star: Option<f64>,
+	/// Depth of the Lennard-Jones potential well between 2 particles of the species
+	pub epsilon_star: Option<f64>,
+	/// Electric charge of a particle of the species, in elementary charges, unless given in the XYZ file
+	pub charge: Option<f64>,
+}
+
+/// The Lennard-Jones parameters of a pair of species, replacing the mixing rule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PairParameters {
+	/// The names of both species, in any order
+	pub species: [String; 2],
+	/// Distance at which the Lennard-Jones potential reaches its minimum
+	pub r_star: f64,
+	/// Depth of the Lennard-Jones potential well
+	pub epsilon_star: f64,
+}
+
+/// How the Lennard-Jones parameters of 2 species are combined for a pair of particles of each species
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MixingRule {
+	/// Arithmetic mean of the distances, geometric mean of the depths
+	#[default]
+	LorentzBerthelot,
+	/// Geometric mean of both the distances and the depths
+	Geometric,
+}
+
+impl MixingRule {
+	/// Combine the Lennard-Jones potentials of 2 species
+	///
+	/// # Arguments
+	///
+	/// * `a` - The potential between 2 particles of the first species
+	/// * `b` - The potential between 2 particles of the second species
+	pub fn mix(&self, a: &LennardJones, b: &LennardJones) -> LennardJones {
+		let epsilon_star = (a.epsilon_star * b.epsilon_star).sqrt();
+		let r_star = match self {
+			Self::LorentzBerthelot => (a.r_star + b.r_star) / 2.0,
+			Self::Geometric => (a.r_star * b.r_star).sqrt(),
+		};
+		return LennardJones::new(r_star, epsilon_star);
+	}
+}
+
+/// Values of parameters, with their names
+pub(crate) type NamedValues = Vec<(&'static str, f64)>;
+
+impl SimulationParameters {
+	/// Whether some species or pairs of species have their own parameters
+	pub fn has_species_parameters(&self) -> bool {
+		!self.species.is_empty() || !self.pair_overrides.is_empty()
+	}
+
+	/// The mass of a particle of the given species
+	///
+	/// # Arguments
+	///
+	/// * `species` - The name of the species
+	pub fn mass_of(&self, species: &str) -> f64 {
+		self.species
+			.get(species)
+			.and_then(|parameters| parameters.mass)
+			.unwrap_or(self.particle_mass)
+	}
+
+	/// The electric charge of a particle of the given species, 0 unless given
+	///
+	/// # Arguments
+	///
+	/// * `species` - The name of the species
+	pub fn charge_of(&self, species: &str) -> f64 {
+		self.species.get(species).and_then(|parameters| parameters.charge).unwrap_or(0.0)
+	}
+
+	/// The Lennard-Jones potential between 2 particles of the given species:
+	/// the explicit parameters of the pair if any, otherwise the [mixing](MixingRule) of the ones of both species
+	///
+	/// # Arguments
+	///
+	/// * `a` - The name of the species of the first particle
+	/// * `b` - The name of the species of the second particle
+	pub fn lennard_jones_between(&self, a: &str, b: &str) -> LennardJones {
+		let is_pair = |pair: &&PairParameters| {
+			(pair.species[0] == a && pair.species[1] == b) || (pair.species[0] == b && pair.species[1] == a)
+		};
+		if let Some(pair) = self.pair_overrides.iter().find(is_pair) {
+			return LennardJones::new(pair.r_star, pair.epsilon_star);
+		}
+
+		let of_species = |name: &str| {
+			let parameters = self.species.get(name).copied().unwrap_or_default();
+			LennardJones::new(
+				parameters.r_star.unwrap_or(self.r_star),
+				parameters.epsilon_star.unwrap_or(self.epsilon_star),
+			)
+		};
+		if a == b {
+			return of_species(a);
+		}
+		return self.mixing_rule.mix(&of_species(a), &of_species(b));
+	}
+
+	/// The parameters of each species and pair of species, to be [validated](SimulationParameters::validate)
+	pub(crate) fn species_values(&self) -> (NamedValues, NamedValues) {
+		let (mut strictly_positive, mut positive) = (vec![], vec![]);
+		for parameters in self.species.values() {
+			strictly_positive.extend(parameters.mass.map(|mass| ("species.mass", mass)));
+			strictly_positive.extend(parameters.r_star.map(|r_star| ("species.r_star", r_star)));
+			positive.extend(parameters.epsilon_star.map(|epsilon_star| ("species.epsilon_star", epsilon_star)));
+		}
+		for pair in &self.pair_overrides {
+			strictly_positive.push(("pair_overrides.r_star", pair.r_star));
+			positive.push(("pair_overrides.epsilon_star", pair.epsilon_star));
+		}
+		return (strictly_positive, positive);
+	}
+}
+
+/// The interactions between the particles, which may depend on the species of both particles of a pair
+pub trait Interactions {
+	/// The interaction between 2 particles
+	type Potential: PairPotential;
+
+	/// The interaction between a particle of the first species and a particle of the second one
+	///
+	/// # Arguments
+	///
+	/// * `species_i` - The index of the species of the first particle
+	/// * `species_j` - The index of the species of the second particle
+	fn between(&self, species_i: usize, species_j: usize) -> &Self::Potential;
+}
+
+/// A single potential is the same for all the species
+impl<P: PairPotential> Interactions for P {
+	type Potential = P;
+
+	#[inline(always)]
+	fn between(&self, _species_i: usize, _species_j: usize) -> &P {
+		self
+	}
+}
+
+/// A potential for each pair of species
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeciesPairs<P> {
+	/// The number of species
+	nb_species: usize,
+	/// The potentials, indexed by the species of the first particle then the one of the second particle
+	potentials: Vec<P>,
+}
+
+impl<P> SpeciesPairs<P> {
+	/// Compute the potential of each pair of species
+	///
+	/// # Arguments
+	///
+	/// * `nb_species` - The number of species
+	/// * `potential` - Gives the potential between the species of the given indices, called once per unordered pair
+	pub fn from_fn(nb_species: usize, mut potential: impl FnMut(usize, usize) -> P) -> Self
+	where
+		P: Clone,
+	{
+		let mut potentials: Vec<Option<P>> = vec![None; nb_species * nb_species];
+		for a in 0..nb_species {
+			for b in a..nb_species {
+				let pair = potential(a, b);
+				potentials[b * nb_species + a] = Some(pair.clone());
+				potentials[a * nb_species + b] = Some(pair);
+			}
+		}
+		return Self {
+			nb_species,
+			potentials: potentials.into_iter().map(|pair| pair.expect("every pair is filled")).collect(),
+		};
+	}
+
+	/// The number of species
+	pub fn nb_species(&self) -> usize {
+		self.nb_species
+	}
+
+	/// Apply a function to the potential of each pair of species
+	///
+	/// # Arguments
+	///
+	/// * `f` - Transforms a potential
+	pub fn map<Q>(&self, f: impl FnMut(&P) -> Q) -> SpeciesPairs<Q> {
+		SpeciesPairs {
+			nb_species: self.nb_species,
+			potentials: self.potentials.iter().map(f).collect(),
+		}
+	}
+}
+
+impl<P: PairPotential> Interactions for SpeciesPairs<P> {
+	type Potential = P;
+
+	#[inline(always)]
+	fn between(&self, species_i: usize, species_j: usize) -> &P {
+		&self.potentials[species_i * self.nb_species + species_j]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::assert_approx_eq;
+
+	#[test]
+	fn mixing_rules_combine_both_species() {
+		let (a, b) = (LennardJones::new(3.0, 0.2), LennardJones::new(4.0, 0.8));
+		let lorentz_berthelot = MixingRule::LorentzBerthelot.mix(&a, &b);
+		assert_approx_eq!(lorentz_berthelot.r_star, 3.5);
+		assert_approx_eq!(lorentz_berthelot.epsilon_star, 0.4);
+		let geometric = MixingRule::Geometric.mix(&a, &b);
+		assert_approx_eq!(geometric.r_star, 12f64.sqrt());
+		assert_approx_eq!(geometric.epsilon_star, 0.4);
+	}
+
+	#[test]
+	fn species_parameters_replace_the_global_ones() {
+		let mut parameters = SimulationParameters::default();
+		parameters.species.insert(
+			"A".to_string(),
+			SpeciesParameters {
+				mass: Some(40.0),
+				r_star: Some(4.0),
+				epsilon_star: None,
+				charge: Some(-1.0),
+			},
+		);
+		parameters.pair_overrides.push(PairParameters {
+			species: ["B".to_string(), "A".to_string()],
+			r_star: 2.0,
+			epsilon_star: 1.0,
+		});
+
+		assert_eq!(parameters.mass_of("A"), 40.0);
+		assert_eq!(parameters.mass_of("B"), parameters.particle_mass);
+		assert_eq!(parameters.charge_of("A"), -1.0);
+		assert_eq!(parameters.charge_of("B"), 0.0);
+		assert_eq!(
+			parameters.lennard_jones_between("A", "A"),
+			LennardJones::new(4.0, parameters.epsilon_star)
+		);
+		assert_eq!(
+			parameters.lennard_jones_between("C", "C"),
+			LennardJones::new(parameters.r_star, parameters.epsilon_star)
+		);
+		// Overrides apply in both orders
+		assert_eq!(parameters.lennard_jones_between("A", "B"), LennardJones::new(2.0, 1.0));
+		assert_eq!(parameters.lennard_jones_between("B", "A"), LennardJones::new(2.0, 1.0));
+		assert_eq!(
+			parameters.lennard_jones_between("A", "C"),
+			LennardJones::new(3.5, parameters.epsilon_star)
+		);
+	}
+
+	#[test]
+	fn species_pairs_are_symmetric() {
+		let pairs = SpeciesPairs::from_fn(3, |a, b| LennardJones::new((1 + a + 10 * b) as f64, 1.0));
+		assert_eq!(pairs.nb_species(), 3);
+		for a in 0..3 {
+			for b in 0..3 {
+				assert_eq!(pairs.between(a, b), pairs.between(b, a));
+			}
+		}
+		assert_eq!(pairs.between(2, 0).r_star, 21.0);
+	}
+}
@@ -1,10 +1,20 @@
 //! A system of particles
 
-use std::{fs::File, io::Read, path::Path};
+use std::{fs, path::Path};
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 
 use crate::{
 	algebra::{Point3, Vector3},
-	parameters::*,
+	cutoff::CutPotential,
+	parameters::SimulationParameters,
+	particle_arrays::ParticleArrays,
+	potential::{LennardJones, PairPotential, Potential},
+	species::{Interactions, SpeciesPairs},
+	topology::{Exclusions, Topology},
+	verlet_list::VerletList,
+	xyz::{Frame, ParseError, ParseErrorReason, header_atom_count, parse_course_line, read_course_frame, read_frame},
 };
 
 /// A particle in the system
@@ -14,28 +24,34 @@ pub struct Particle {
 	pub(crate) coordinates: Point3,
 	/// The momentum of the particle
 	pub(crate) momentum: Vector3,
+	/// The index of the species of the particle in its [system](System)
+	pub(crate) species: usize,
+	/// The electric charge of the particle, in elementary charges
+	pub(crate) charge: f64,
 }
 
 impl Particle {
-	/// Parse a particle from a string
-	/// The format should be an unsigned integer, followed by 3 floating point numbers, all separated by whitespace
+	/// Parse a particle and its type from a string
+	/// The format should be an unsigned integer, the type, followed by 3 floating point numbers, all separated by
+	/// whitespace.
+	/// The type is returned apart, and the particle is of the first species, since species are indexed by the
+	/// [system](System) it belongs to.
 	///
 	/// # Arguments
 	///
 	/// * `s` - The string to parse
-	pub fn parse(s: &str) -> Self {
-		let mut parts = s.split_whitespace();
-		let _type: usize = parts.next().unwrap().parse().unwrap();
-		let x: f64 = parts.next().unwrap().parse().unwrap();
-		let y: f64 = parts.next().unwrap().parse().unwrap();
-		let z: f64 = parts.next().unwrap().parse().unwrap();
+	pub fn parse(s: &str) -> Result<(usize, Self), ParseError> {
+		let (particle_type, coordinates) = parse_course_line(s, 1)?;
 
 		let momentum = Vector3::zero(); // 0 for now
 
-		return Self {
-			coordinates: Point3::from(x, y, z),
+		let particle = Self {
+			coordinates,
 			momentum,
+			species: 0,
+			charge: 0.0,
 		};
+		return Ok((particle_type, particle));
 	}
 
 	/// The x coordinate of the particle
@@ -58,6 +74,11 @@ impl Particle {
 		(self.x(), self.y(), self.z())
 	}
 
+	/// The electric charge of the particle, in elementary charges
+	pub fn charge(&self) -> f64 {
+		self.charge
+	}
+
 	/// Compute the distance to another [particle](Self), squared
 	/// More optimized than calling `distance_to` then squaring the result.
 	///
@@ -77,23 +98,32 @@ impl Particle {
 		return self.distance_to_squared(rhs).sqrt();
 	}
 
+	/// The index of the species of the particle in its [system](System)
+	pub fn species(&self) -> usize {
+		self.species
+	}
+
 	/// Compute the kinetic moment of the [particle](Self)
 	pub fn kinetic_moment(&self) -> Vector3 {
 		return self.momentum;
 	}
 
 	/// Put the particle back in the box
-	pub fn put_back_in_box(&mut self) {
-		self.coordinates.x = (self.coordinates.x + BOX_SIDE / 2.0).rem_euclid(BOX_SIDE) - BOX_SIDE / 2.0;
-		self.coordinates.y = (self.coordinates.y + BOX_SIDE / 2.0).rem_euclid(BOX_SIDE) - BOX_SIDE / 2.0;
-		self.coordinates.z = (self.coordinates.z + BOX_SIDE / 2.0).rem_euclid(BOX_SIDE) - BOX_SIDE / 2.0;
-
-		assert!(self.coordinates.x <= BOX_SIDE / 2.0);
-		assert!(self.coordinates.x >= -BOX_SIDE / 2.0);
-		assert!(self.coordinates.y <= BOX_SIDE / 2.0);
-		assert!(self.coordinates.y >= -BOX_SIDE / 2.0);
-		assert!(self.coordinates.z <= BOX_SIDE / 2.0);
-		assert!(self.coordinates.z >= -BOX_SIDE / 2.0);
+	///
+	/// # Arguments
+	///
+	/// * `box_side` - The side of the cubic box, centered on the origin
+	pub fn put_back_in_box(&mut self, box_side: f64) {
+		self.coordinates.x = (self.coordinates.x + box_side / 2.0).rem_euclid(box_side) - box_side / 2.0;
+		self.coordinates.y = (self.coordinates.y + box_side / 2.0).rem_euclid(box_side) - box_side / 2.0;
+		self.coordinates.z = (self.coordinates.z + box_side / 2.0).rem_euclid(box_side) - box_side / 2.0;
+
+		assert!(self.coordinates.x <= box_side / 2.0);
+		assert!(self.coordinates.x >= -box_side / 2.0);
+		assert!(self.coordinates.y <= box_side / 2.0);
+		assert!(self.coordinates.y >= -box_side / 2.0);
+		assert!(self.coordinates.z <= box_side / 2.0);
+		assert!(self.coordinates.z >= -box_side / 2.0);
 	}
 }
 
@@ -104,41 +134,176 @@ pub struct System {
 	pub(crate) particles: Vec<Particle>,
 	/// The number of local particles (unused for now)
 	pub(crate) nb_particles_local: usize,
+	/// The parameters of the simulation
+	pub(crate) parameters: SimulationParameters,
+	/// The names of the species of the particles
+	pub(crate) species: Vec<String>,
+	/// The number of steps done since the beginning of the simulation
+	pub(crate) step_count: usize,
+	/// The random number generator of the simulation, kept so that a run can be resumed exactly
+	pub(crate) rng: ChaCha8Rng,
+	/// Whether the momenta are half a time step behind the positions, as kept by [leapfrog](crate::integrator::Leapfrog),
+	/// rather than at the same time
+	pub(crate) half_step_momenta: bool,
+	/// The [Verlet list](VerletList) of the system, kept across steps when it is the [pair search](crate::parameters::PairSearch)
+	pub(crate) verlet_list: Option<VerletList>,
+	/// The coordinates of the particles as a [structure of arrays](ParticleArrays), kept across steps when the pair
+	/// search is [vectorized](crate::parameters::PairSearch::Vectorized)
+	pub(crate) particle_arrays: Option<ParticleArrays>,
+	/// The bonded interactions between the particles of the molecules
+	pub(crate) topology: Topology,
+	/// The pairs of particles which skip the pair potential, according to the topology
+	pub(crate) exclusions: Exclusions,
 }
 
 impl System {
-	/// Parse a system from a string
-	pub fn from_str(s: &str, nb_particles_local: usize) -> Self {
-		// Ignore first line
-		let lines = s.lines().skip(1);
-
-		// Parse the rest of the lines
-		let mut particles = Vec::new();
-		for line in lines {
-			particles.push(Particle::parse(line));
+	/// Parse a system from a string, with the default [parameters](SimulationParameters)
+	pub fn from_str(s: &str, nb_particles_local: usize) -> Result<Self, ParseError> {
+		Self::from_str_with_parameters(s, nb_particles_local, SimulationParameters::default())
+	}
+
+	/// Parse a system from a string, simulated with the given [parameters](SimulationParameters).
+	/// The string is either in the course format, or in the standard or extended XYZ format (see [`crate::xyz`]).
+	pub fn from_str_with_parameters(s: &str, nb_particles_local: usize, parameters: SimulationParameters) -> Result<Self, ParseError> {
+		let mut lines = s.lines().enumerate().map(|(index, line)| (index + 1, line));
+
+		// The header is either the atom count of the standard format, or the course header
+		let Some(header) = s.lines().next() else {
+			return Err(ParseError::new(0, 0, ParseErrorReason::EmptyFile));
+		};
+		let frame = match header_atom_count(header) {
+			Some(expected) => {
+				let mut numbered_lines = lines.by_ref().map(Ok);
+				let frame = read_frame(&mut numbered_lines)?.expect("the header is not blank");
+
+				// Only a single frame is expected
+				let nb_extra_lines = lines.filter(|(_, line)| !line.trim().is_empty()).count();
+				if nb_extra_lines > 0 {
+					let reason = ParseErrorReason::AtomCountMismatch {
+						expected,
+						found: expected + nb_extra_lines,
+					};
+					return Err(ParseError::new(1, 1, reason));
+				}
+				frame
+			}
+			None => read_course_frame(lines.skip(1))?,
+		};
+
+		return Self::from_frame(frame, nb_particles_local, parameters);
+	}
+
+	/// Create a system from a [frame](Frame), simulated with the given [parameters](SimulationParameters).
+	/// The box given by the frame replaces the one of the parameters.
+	/// The momentums are initialized randomly, unless the frame holds the velocities or the momenta of the particles.
+	pub fn from_frame(frame: Frame, nb_particles_local: usize, mut parameters: SimulationParameters) -> Result<Self, ParseError> {
+		if nb_particles_local >= frame.nb_atoms() {
+			let reason = ParseErrorReason::TooManyLocalParticles {
+				nb_particles_local,
+				nb_particles: frame.nb_atoms(),
+			};
+			return Err(ParseError::new(0, 0, reason));
+		}
+
+		// The parameters may not come from a file, or the box of the frame may not fit them
+		if let Some(box_side) = frame.box_side {
+			parameters.box_side = box_side;
+		}
+		parameters
+			.validate()
+			.map_err(|error| ParseError::new(0, 0, ParseErrorReason::InvalidParameters(Box::new(error))))?;
+
+		// Species are numbered in their order of appearance
+		let mut species: Vec<String> = Vec::new();
+		let mut particles = Vec::with_capacity(frame.nb_atoms());
+		for (i, (name, coordinates)) in frame.species.iter().zip(&frame.positions).enumerate() {
+			let species_index = match species.iter().position(|known| known == name) {
+				Some(index) => index,
+				None => {
+					species.push(name.clone());
+					species.len() - 1
+				}
+			};
+
+			let momentum = match (&frame.momenta, &frame.velocities) {
+				(Some(momenta), _) => momenta[i],
+				(None, Some(velocities)) => velocities[i] * parameters.mass_of(name),
+				(None, None) => Vector3::zero(),
+			};
+
+			let charge = match &frame.charges {
+				Some(charges) => charges[i],
+				None => parameters.charge_of(name),
+			};
+
+			particles.push(Particle {
+				coordinates: *coordinates,
+				momentum,
+				species: species_index,
+				charge,
+			});
 		}
 
 		// Create the system
-		assert!(nb_particles_local < particles.len());
 		let mut system = Self {
 			particles,
 			nb_particles_local,
+			parameters,
+			species,
+			step_count: 0,
+			rng: ChaCha8Rng::from_rng(&mut rand::rng()),
+			half_step_momenta: false,
+			verlet_list: None,
+			particle_arrays: None,
+			topology: Topology::default(),
+			exclusions: Exclusions::default(),
 		};
+		system.update_particle_arrays();
+		if let Some(seed) = system.parameters.seed {
+			system.reseed(seed);
+		}
+
+		// Initialize the particle momentums, if the file doesn't give them
+		if frame.momenta.is_none() && frame.velocities.is_none() {
+			system.init_particles_momentums();
+		}
+
+		return Ok(system);
+	}
 
-		// Initialize the particle momentums
-		system.init_particles_momentums();
+	/// Parse a system from a file, with the default [parameters](SimulationParameters)
+	pub fn from_file(path: &Path, nb_particles_local: usize) -> Result<Self, ParseError> {
+		Self::from_file_with_parameters(path, nb_particles_local, SimulationParameters::default())
+	}
+
+	/// Parse a system from a file, simulated with the given [parameters](SimulationParameters)
+	pub fn from_file_with_parameters(
+		path: &Path, nb_particles_local: usize, parameters: SimulationParameters,
+	) -> Result<Self, ParseError> {
+		let contents =
+			fs::read_to_string(path).map_err(|error| ParseError::new(0, 0, ParseErrorReason::Io(error)).in_file(path))?;
 
-		return system;
+		return Self::from_str_with_parameters(&contents, nb_particles_local, parameters).map_err(|error| error.in_file(path));
 	}
 
-	/// Parse a system from a file
-	pub fn from_file(path: &Path, nb_particles_local: usize) -> Self {
-		// Read the file
-		let mut file = File::open(path).unwrap();
-		let mut contents = String::new();
-		file.read_to_string(&mut contents).unwrap();
+	/// Get the parameters of the simulation
+	pub fn parameters(&self) -> &SimulationParameters {
+		&self.parameters
+	}
+
+	/// Reset the random number generator of the simulation with the given seed
+	pub fn reseed(&mut self, seed: u64) {
+		self.rng = ChaCha8Rng::seed_from_u64(seed);
+	}
 
-		return Self::from_str(&contents, nb_particles_local);
+	/// Get the number of steps done since the beginning of the simulation
+	pub fn step_count(&self) -> usize {
+		self.step_count
+	}
+
+	/// Get the names of the species of the particles, indexed by [`Particle::species`]
+	pub fn species(&self) -> &[String] {
+		&self.species
 	}
 
 	/// Get the total number of particles in the [system](Self)
@@ -181,7 +346,8 @@ impl System {
 					continue;
 				}
 				let r_ij = self.distance_between_squared(i, j).sqrt();
-				let u_ij = EPSILON_STAR * ((R_STAR / r_ij).powi(12) - 2.0 * (R_STAR / r_ij).powi(6));
+				let (r_star, epsilon_star) = (self.parameters.r_star, self.parameters.epsilon_star);
+				let u_ij = epsilon_star * ((r_star / r_ij).powi(12) - 2.0 * (r_star / r_ij).powi(6));
 				total += u_ij;
 			}
 		}
@@ -189,36 +355,110 @@ impl System {
 		return 2.0 * total;
 	}
 
-	pub fn energy_gradient(&self, particle_i: &Particle, particle_j: &Particle) -> Vector3 {
-		let r_ij = particle_i.distance_to(&particle_j);
-		let gradient = |c_i, c_j| {
-			-48.0 * EPSILON_STAR * ((R_STAR / r_ij).powi(12) - (R_STAR / r_ij).powi(6)) * ((c_i - c_j) / r_ij.powi(2))
+	/// Get the interaction between the particles of a pair, from the parameters of the simulation:
+	/// the chosen potential, or the Lennard-Jones potential of `r_star` and `epsilon_star` by default,
+	/// with the chosen [treatment of the cut](crate::cutoff::Cutoff)
+	pub fn potential(&self) -> CutPotential<Potential> {
+		let potential = match &self.parameters.potential {
+			Some(potential) => potential.clone(),
+			None => Potential::LennardJones(LennardJones::new(self.parameters.r_star, self.parameters.epsilon_star)),
 		};
+		return CutPotential::new(potential, self.parameters.cutoff, self.parameters.r_cut);
+	}
+
+	/// Get the Lennard-Jones potential of each pair of species, when the [parameters](SimulationParameters) give
+	/// parameters to species and no other potential is chosen
+	pub fn lennard_jones_pairs(&self) -> Option<SpeciesPairs<LennardJones>> {
+		if self.parameters.potential.is_some() || !self.parameters.has_species_parameters() {
+			return None;
+		}
+		return Some(SpeciesPairs::from_fn(self.species.len(), |a, b| {
+			self.parameters.lennard_jones_between(&self.species[a], &self.species[b])
+		}));
+	}
+
+	/// Get the interaction between the particles of each pair of species: the [potential](System::potential) of the
+	/// system, or the [Lennard-Jones potentials](System::lennard_jones_pairs) of the species
+	pub fn interactions(&self) -> SpeciesPairs<CutPotential<Potential>> {
+		let potential = self.potential();
+		match self.lennard_jones_pairs() {
+			Some(lennard_jones) => lennard_jones.map(|pair| potential.with(Potential::LennardJones(*pair))),
+			None => SpeciesPairs::from_fn(self.species.len(), |_, _| potential.clone()),
+		}
+	}
+
+	/// Get the mass of the particles of each species
+	pub fn masses(&self) -> Vec<f64> {
+		self.species.iter().map(|name| self.parameters.mass_of(name)).collect()
+	}
+
+	/// Compute the gradient of the energy of a pair of particles according to the [potential](System::potential) of the
+	/// system, with respect to the first particle, or zero if the pair is [excluded](crate::topology::Exclusions)
+	///
+	/// # Arguments
+	///
+	/// * `i` - The index of the first particle
+	/// * `j` - The index of the second particle
+	pub fn energy_gradient(&self, i: usize, j: usize) -> Vector3 {
+		self.energy_gradient_with(&self.interactions(), i, j)
+	}
 
-		// Apply gradient in the x, y, and z directions
-		let (x_i, y_i, z_i) = particle_i.xyz();
-		let (x_j, y_j, z_j) = particle_j.xyz();
-		return Vector3::from(gradient(x_i, x_j), gradient(y_i, y_j), gradient(z_i, z_j));
+	/// Compute the gradient of the energy of a pair of particles according to the given potential,
+	/// with respect to the first particle, or zero if the pair is [excluded](crate::topology::Exclusions)
+	///
+	/// # Arguments
+	///
+	/// * `potential` - The interaction between the particles of a pair, which may depend on their species
+	/// * `i` - The index of the first particle
+	/// * `j` - The index of the second particle
+	pub fn energy_gradient_with(&self, potential: &impl Interactions, i: usize, j: usize) -> Vector3 {
+		if self.exclusions.contains(i, j) {
+			return Vector3::zero();
+		}
+		let (particle_i, particle_j) = (&self.particles[i], &self.particles[j]);
+		potential
+			.between(particle_i.species, particle_j.species)
+			.gradient(particle_i.coordinates - particle_j.coordinates)
 	}
 
-	/// Compute the microscopic energy in the system, according to the Lennard-Jones potential.
+	/// Compute the microscopic energy in the system, according to its [potential](System::potential).
 	pub fn microscopic_energy(&self) -> f64 {
+		self.microscopic_energy_with(&self.interactions())
+	}
+
+	/// Compute the microscopic energy in the system, according to the given potential.
+	/// The pairs [excluded](crate::topology::Exclusions) by the topology are skipped.
+	///
+	/// # Arguments
+	///
+	/// * `potential` - The interaction between the particles of a pair, which may depend on their species
+	pub fn microscopic_energy_with(&self, potential: &impl Interactions) -> f64 {
 		let mut total = 0.0;
 		for i in 0..self.nb_particles_total() {
 			for j in (i + 1)..self.nb_particles_total() {
-				let r_ij = self.distance_between(i, j);
-				let r_star_over_r_ij_pow6 = (R_STAR / r_ij).powi(6);
-				let r_star_over_r_ij_pow12 = (R_STAR / r_ij).powi(12);
-				let u_ij = EPSILON_STAR * (r_star_over_r_ij_pow12 - (2.0 * r_star_over_r_ij_pow6));
-				total += u_ij;
+				if self.exclusions.contains(i, j) {
+					continue;
+				}
+				let pair_potential = potential.between(self.particles[i].species, self.particles[j].species);
+				total += pair_potential.energy(self.distance_between_squared(i, j));
 			}
 		}
 
-		return 4.0 * total;
+		return total;
 	}
 
 	/// Compute the forces between pairs of particles
 	pub fn compute_forces(&self) -> Vec<Vec<Vector3>> {
+		self.compute_forces_with(&self.interactions())
+	}
+
+	/// Compute the forces between pairs of particles, according to the given potential.
+	/// The pairs [excluded](crate::topology::Exclusions) by the topology have no force.
+	///
+	/// # Arguments
+	///
+	/// * `potential` - The interaction between the particles of a pair, which may depend on their species
+	pub fn compute_forces_with(&self, potential: &impl Interactions) -> Vec<Vec<Vector3>> {
 		let mut forces = vec![vec![Vector3::zero(); self.nb_particles_total()]; self.nb_particles_total()];
 		for i in 0..self.nb_particles_total() {
 			for j in 0..self.nb_particles_total() {
@@ -227,7 +467,7 @@ impl System {
 					continue;
 				}
 
-				forces[i][j] = self.energy_gradient(&self.particles[i], &self.particles[j]);
+				forces[i][j] = self.energy_gradient_with(potential, i, j);
 			}
 		}
 
@@ -235,7 +475,7 @@ impl System {
 	}
 
 	/// Compute the sum of all the forces between pairs of particles in the system
-	pub fn sum_of_forces(forces: &Vec<Vec<Vector3>>) -> Vector3 {
+	pub fn sum_of_forces(forces: &[Vec<Vector3>]) -> Vector3 {
 		let mut sx = 0.0;
 		let mut sy = 0.0;
 		let mut sz = 0.0;
@@ -266,7 +506,15 @@ mod tests {
 
 	#[test]
 	fn check_energy_optimizations() {
-		let system = System::from_file(Path::new("dataset/particles.xyz"), 0);
+		let system = System::from_file(Path::new("dataset/particles.xyz"), 0).unwrap();
 		assert_approx_eq!(system.microscopic_energy_reference(), system.microscopic_energy());
 	}
+
+	#[test]
+	fn particles_are_parsed_with_their_type() {
+		let (particle_type, particle) = Particle::parse("2  1.5 -2.0 3.25").unwrap();
+		assert_eq!(particle_type, 2);
+		assert_eq!(particle.xyz(), (1.5, -2.0, 3.25));
+		assert!(Particle::parse("x 1.5 -2.0 3.25").is_err());
+	}
 }
@@ -0,0 +1,439 @@
+//! Pair potentials given as tables of the energy and the force, such as the ones of coarse-grained models obtained by
+//! iterative Boltzmann inversion
+//!
+//! A table file holds one `r U(r) F(r)` line per distance, in increasing order, where F = -dU/dr.
+//! Empty lines and lines starting with `#` are ignored. The energy and the force are interpolated between the lines
+//! with natural cubic splines.
+//!
+//! The serialized potential, e.g. in a checkpoint, holds the rows of the table along with its path, so that it is
+//! restored without reading the file again, whatever the working directory.
+
+use std::{
+	fmt::Display,
+	fs,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::potential::PairPotential;
+
+/// The tolerance between each tabulated force and the derivative of the interpolated energy, relative to the force
+const CONSISTENCY_TOLERANCE: f64 = 1e-2;
+/// The tolerance between a tabulated force and the derivative of the interpolated energy where the force vanishes,
+/// in kcal/(mol.Å)
+const CONSISTENCY_FLOOR: f64 = 1e-3;
+
+/// An error that occurred while loading a table
+#[derive(Debug)]
+pub enum TableError {
+	/// The file could not be read
+	Io(std::io::Error),
+	/// A line does not have 3 columns
+	WrongColumnCount {
+		/// The line, starting at 1
+		line: usize,
+		/// The number of columns found
+		found: usize,
+	},
+	/// A column is not a valid floating point number
+	InvalidFloat {
+		/// The line, starting at 1
+		line: usize,
+		/// The text that was found instead
+		token: String,
+	},
+	/// The distances are not strictly increasing and positive
+	NotIncreasing {
+		/// The line, starting at 1
+		line: usize,
+	},
+	/// There are not enough lines to interpolate
+	TooFewPoints(usize),
+	/// The tabulated force is not the opposite of the derivative of the tabulated energy
+	Inconsistent {
+		/// The distance at which the force is inconsistent
+		r: f64,
+		/// The tabulated force
+		force: f64,
+		/// The opposite of the derivative of the interpolated energy
+		energy_derivative: f64,
+	},
+}
+
+impl Display for TableError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Io(error) => write!(f, "could not read the table: {error}"),
+			Self::WrongColumnCount { line, found } => write!(f, "line {line}: expected 3 columns (r U F), found {found}"),
+			Self::InvalidFloat { line, token } => write!(f, "line {line}: `{token}` is not a floating point number"),
+			Self::NotIncreasing { line } => write!(f, "line {line}: the distances must be positive and strictly increasing"),
+			Self::TooFewPoints(nb_points) => write!(f, "at least 3 points are needed to interpolate, found {nb_points}"),
+			Self::Inconsistent {
+				r,
+				force,
+				energy_derivative,
+			} => write!(f, "at r = {r}, the tabulated force {force} is not -dU/dr = {energy_derivative}"),
+		}
+	}
+}
+
+impl std::error::Error for TableError {}
+
+/// A natural cubic spline, i.e. with a second derivative of 0 at both ends
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubicSpline {
+	/// The abscissas of the knots, strictly increasing
+	x: Vec<f64>,
+	/// The values at the knots
+	y: Vec<f64>,
+	/// The second derivatives at the knots
+	second_derivatives: Vec<f64>,
+}
+
+impl CubicSpline {
+	/// Interpolate the given values, solving the tridiagonal system of the second derivatives
+	///
+	/// # Arguments
+	///
+	/// * `x` - The abscissas of the knots, strictly increasing, at least 3 of them
+	/// * `y` - The values at the knots
+	pub fn new(x: Vec<f64>, y: Vec<f64>) -> Self {
+		let n = x.len();
+		assert!(n >= 3 && y.len() == n, "a spline needs at least 3 knots, each with a value");
+
+		// Thomas algorithm, eliminating the lower diagonal from the top
+		let mut second_derivatives = vec![0.0; n];
+		let mut diagonal = vec![0.0; n];
+		let mut right_side = vec![0.0; n];
+		for i in 1..n - 1 {
+			let (h_before, h_after) = (x[i] - x[i - 1], x[i + 1] - x[i]);
+			diagonal[i] = 2.0 * (h_before + h_after);
+			right_side[i] = 6.0 * ((y[i + 1] - y[i]) / h_after - (y[i] - y[i - 1]) / h_before);
+			if i > 1 {
+				let factor = h_before / diagonal[i - 1];
+				diagonal[i] -= factor * h_before;
+				right_side[i] -= factor * right_side[i - 1];
+			}
+		}
+		for i in (1..n - 1).rev() {
+			let h_after = x[i + 1] - x[i];
+			second_derivatives[i] = (right_side[i] - h_after * second_derivatives[i + 1]) / diagonal[i];
+		}
+
+		Self { x, y, second_derivatives }
+	}
+
+	/// The first abscissa of the knots
+	pub fn start(&self) -> f64 {
+		self.x[0]
+	}
+
+	/// The last abscissa of the knots
+	pub fn end(&self) -> f64 {
+		self.x[self.x.len() - 1]
+	}
+
+	/// The index of the first knot of the interval containing the given abscissa, the first or last interval outside
+	fn interval(&self, x: f64) -> usize {
+		self.x.partition_point(|&knot| knot <= x).clamp(1, self.x.len() - 1) - 1
+	}
+
+	/// The interpolated value and derivative
+	///
+	/// # Arguments
+	///
+	/// * `x` - The abscissa, extrapolated with the cubic of the closest interval outside of the knots
+	pub fn value_and_derivative(&self, x: f64) -> (f64, f64) {
+		let i = self.interval(x);
+		let h = self.x[i + 1] - self.x[i];
+		let a = (self.x[i + 1] - x) / h;
+		let b = (x - self.x[i]) / h;
+		let (m_i, m_next) = (self.second_derivatives[i], self.second_derivatives[i + 1]);
+
+		let value = a * self.y[i] + b * self.y[i + 1] + ((a * a * a - a) * m_i + (b * b * b - b) * m_next) * h * h / 6.0;
+		let derivative = (self.y[i + 1] - self.y[i]) / h + ((1.0 - 3.0 * a * a) * m_i + (3.0 * b * b - 1.0) * m_next) * h / 6.0;
+		return (value, derivative);
+	}
+
+	/// The interpolated value
+	///
+	/// # Arguments
+	///
+	/// * `x` - The abscissa, extrapolated with the cubic of the closest interval outside of the knots
+	pub fn value(&self, x: f64) -> f64 {
+		self.value_and_derivative(x).0
+	}
+}
+
+/// How a [`TabulatedPotential`] appears in the parameters: the path of its table, and its rows once loaded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TableFile {
+	/// The path of the table file
+	file: PathBuf,
+	/// The `r U F` rows of the table, read from the file if absent
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	rows: Option<Vec<[f64; 3]>>,
+}
+
+/// A pair potential interpolated from a table of energies and forces.
+/// Below the first distance of the table the interpolation is extrapolated, beyond the last one there is no interaction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "TableFile", into = "TableFile")]
+pub struct TabulatedPotential {
+	/// The path of the table file
+	file: PathBuf,
+	/// The interpolation of the energy, shared between the copies of the potential
+	energy: Arc<CubicSpline>,
+	/// The interpolation of the force F = -dU/dr, shared between the copies of the potential
+	force: Arc<CubicSpline>,
+}
+
+impl TabulatedPotential {
+	/// Load a table file, and check that the tabulated forces are consistent with the tabulated energies
+	///
+	/// # Arguments
+	///
+	/// * `path` - The path of the table file
+	pub fn from_file(path: &Path) -> Result<Self, TableError> {
+		let contents = fs::read_to_string(path).map_err(TableError::Io)?;
+		return Self::parse(path, &contents);
+	}
+
+	/// Parse the contents of a table file, and check that the tabulated forces are consistent with the tabulated energies
+	///
+	/// # Arguments
+	///
+	/// * `path` - The path the table comes from
+	/// * `contents` - The contents of the table file
+	pub fn parse(path: &Path, contents: &str) -> Result<Self, TableError> {
+		let (mut r, mut energies, mut forces) = (vec![], vec![], vec![]);
+		for (index, line) in contents.lines().enumerate() {
+			let line_number = index + 1;
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let columns: Vec<&str> = line.split_whitespace().collect();
+			if columns.len() != 3 {
+				return Err(TableError::WrongColumnCount {
+					line: line_number,
+					found: columns.len(),
+				});
+			}
+			let mut values = [0.0; 3];
+			for (value, token) in values.iter_mut().zip(&columns) {
+				*value = token.parse().map_err(|_| TableError::InvalidFloat {
+					line: line_number,
+					token: token.to_string(),
+				})?;
+			}
+
+			// Also rejects NaN distances
+			let is_increasing = values[0] > r.last().copied().unwrap_or(0.0);
+			if !is_increasing {
+				return Err(TableError::NotIncreasing { line: line_number });
+			}
+			r.push(values[0]);
+			energies.push(values[1]);
+			forces.push(values[2]);
+		}
+		return Self::from_columns(path, r, energies, forces);
+	}
+
+	/// Interpolate the given rows of a table, and check that the tabulated forces are consistent with the tabulated
+	/// energies
+	///
+	/// # Arguments
+	///
+	/// * `path` - The path the table comes from
+	/// * `rows` - The `r U F` rows of the table
+	pub fn from_rows(path: &Path, rows: &[[f64; 3]]) -> Result<Self, TableError> {
+		let mut previous = 0.0;
+		for (index, row) in rows.iter().enumerate() {
+			// Also rejects NaN distances
+			let is_increasing = row[0] > previous;
+			if !is_increasing {
+				return Err(TableError::NotIncreasing { line: index + 1 });
+			}
+			previous = row[0];
+		}
+		let column = |c: usize| rows.iter().map(|row| row[c]).collect();
+		return Self::from_columns(path, column(0), column(1), column(2));
+	}
+
+	/// Interpolate the columns of a table, with strictly increasing distances, and check that the tabulated forces are
+	/// consistent with the tabulated energies
+	fn from_columns(path: &Path, r: Vec<f64>, energies: Vec<f64>, forces: Vec<f64>) -> Result<Self, TableError> {
+		if r.len() < 3 {
+			return Err(TableError::TooFewPoints(r.len()));
+		}
+
+		let potential = Self {
+			file: path.to_path_buf(),
+			energy: Arc::new(CubicSpline::new(r.clone(), energies)),
+			force: Arc::new(CubicSpline::new(r.clone(), forces.clone())),
+		};
+
+		// The ends of a natural spline have a poor derivative, so only the inner points are checked
+		for k in 1..r.len() - 1 {
+			let energy_derivative = -potential.energy.value_and_derivative(r[k]).1;
+			let tolerance = CONSISTENCY_TOLERANCE * forces[k].abs() + CONSISTENCY_FLOOR;
+			if (forces[k] - energy_derivative).abs() > tolerance {
+				return Err(TableError::Inconsistent {
+					r: r[k],
+					force: forces[k],
+					energy_derivative,
+				});
+			}
+		}
+		return Ok(potential);
+	}
+
+	/// The path of the table file
+	pub fn file(&self) -> &Path {
+		&self.file
+	}
+
+	/// The `r U F` rows of the table
+	pub fn rows(&self) -> Vec<[f64; 3]> {
+		(0..self.energy.x.len())
+			.map(|k| [self.energy.x[k], self.energy.y[k], self.force.y[k]])
+			.collect()
+	}
+}
+
+impl TryFrom<TableFile> for TabulatedPotential {
+	type Error = TableError;
+
+	fn try_from(table_file: TableFile) -> Result<Self, Self::Error> {
+		match &table_file.rows {
+			Some(rows) => Self::from_rows(&table_file.file, rows),
+			None => Self::from_file(&table_file.file),
+		}
+	}
+}
+
+impl From<TabulatedPotential> for TableFile {
+	fn from(potential: TabulatedPotential) -> Self {
+		Self {
+			rows: Some(potential.rows()),
+			file: potential.file,
+		}
+	}
+}
+
+impl PairPotential for TabulatedPotential {
+	fn energy(&self, distance_squared: f64) -> f64 {
+		let distance = distance_squared.sqrt();
+		if distance > self.energy.end() {
+			return 0.0;
+		}
+		return self.energy.value(distance);
+	}
+
+	fn force(&self, distance_squared: f64) -> f64 {
+		let distance = distance_squared.sqrt();
+		if distance > self.force.end() {
+			return 0.0;
+		}
+		return self.force.value(distance) / distance;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{assert_approx_eq, potential::LennardJones};
+
+	/// A table of the Lennard-Jones potential of the course, from 2 to 12 Å
+	fn lennard_jones_table(step: f64) -> String {
+		let potential = LennardJones::new(3.0, 0.2);
+		let mut table = String::from("# r U F\n");
+		let nb_points = (10.0 / step).round() as usize;
+		for k in 0..=nb_points {
+			let r = 2.0 + k as f64 * step;
+			table += &format!("{r} {} {}\n", potential.energy(r * r), potential.force(r * r) * r);
+		}
+		return table;
+	}
+
+	#[test]
+	fn spline_interpolates_between_the_knots() {
+		let x: Vec<f64> = (0..50).map(|k| k as f64 * 0.1).collect();
+		let spline = CubicSpline::new(x.clone(), x.iter().map(|x| x.sin()).collect());
+		for k in 0..50 {
+			assert_approx_eq!(spline.value(x[k]), x[k].sin());
+		}
+		for x in [0.55, 1.234, 3.9] {
+			let (value, derivative) = spline.value_and_derivative(x);
+			assert!((value - f64::sin(x)).abs() < 1e-5);
+			assert!((derivative - f64::cos(x)).abs() < 1e-3);
+		}
+	}
+
+	#[test]
+	fn tabulated_lennard_jones_matches_the_analytic_one() {
+		let tabulated = TabulatedPotential::parse(Path::new("lj.table"), &lennard_jones_table(0.005)).unwrap();
+		let potential = LennardJones::new(3.0, 0.2);
+		for r in [2.5, 3.0, 3.3337, 5.0, 9.99] {
+			let (energy, force) = (potential.energy(r * r), potential.force(r * r));
+			assert!((tabulated.energy(r * r) - energy).abs() <= 1e-6 * energy.abs().max(1.0));
+			assert!((tabulated.force(r * r) - force).abs() <= 1e-6 * force.abs().max(1.0));
+		}
+		assert_eq!(tabulated.energy(13.0 * 13.0), 0.0);
+		assert_eq!(tabulated.force(13.0 * 13.0), 0.0);
+	}
+
+	#[test]
+	fn invalid_tables_are_rejected() {
+		let parse = |contents: &str| TabulatedPotential::parse(Path::new("test.table"), contents);
+		assert!(matches!(
+			parse("1 2 3\n2 3\n"),
+			Err(TableError::WrongColumnCount { line: 2, found: 2 })
+		));
+		assert!(matches!(parse("1 2 3\n2 x 3\n"), Err(TableError::InvalidFloat { line: 2, .. })));
+		assert!(matches!(
+			parse("1 0 0\n# comment\n\n1 0 0\n"),
+			Err(TableError::NotIncreasing { line: 4 })
+		));
+		assert!(matches!(parse("1 0 0\n2 0 0\n"), Err(TableError::TooFewPoints(2))));
+
+		// Forces with the wrong sign, or 5% too large, which a tolerance relative to the largest force would accept
+		let scaled_forces = |factor: f64| -> String {
+			lennard_jones_table(0.01)
+				.lines()
+				.skip(1)
+				.map(|line| {
+					let columns: Vec<f64> = line.split_whitespace().map(|token| token.parse().unwrap()).collect();
+					format!("{} {} {}\n", columns[0], columns[1], columns[2] * factor)
+				})
+				.collect()
+		};
+		assert!(parse(&scaled_forces(1.0)).is_ok());
+		assert!(matches!(parse(&scaled_forces(-1.0)), Err(TableError::Inconsistent { .. })));
+		assert!(matches!(parse(&scaled_forces(1.05)), Err(TableError::Inconsistent { .. })));
+
+		let from_rows = |rows: &[[f64; 3]]| TabulatedPotential::from_rows(Path::new("test.table"), rows);
+		assert!(matches!(
+			from_rows(&[[1.0, 0.0, 0.0], [0.5, 0.0, 0.0]]),
+			Err(TableError::NotIncreasing { line: 2 })
+		));
+		assert!(matches!(from_rows(&[[1.0, 0.0, 0.0]]), Err(TableError::TooFewPoints(1))));
+	}
+
+	#[test]
+	fn serialized_potential_holds_the_table() {
+		// The file doesn't exist, so the potential can only be restored from the serialized rows
+		let tabulated = TabulatedPotential::parse(Path::new("missing.table"), &lennard_jones_table(0.01)).unwrap();
+		let serialized = toml::to_string(&tabulated).unwrap();
+		let restored: TabulatedPotential = toml::from_str(&serialized).unwrap();
+		assert_eq!(restored, tabulated);
+		assert_eq!(restored.file(), Path::new("missing.table"));
+
+		assert!(toml::from_str::<TabulatedPotential>("file = \"missing.table\"").is_err());
+	}
+}
@@ -0,0 +1,181 @@
+//! Long-range corrections of the energy and the pressure, for the interactions beyond the cut
+//!
+//! For a homogeneous system of density ρ = N/V, the pair distribution is 1 beyond the cut, so the missing energy is
+//! E_tail = 2πNρ ∫ r² U(r) dr and the missing pressure is P_tail = -(2π/3)ρ² ∫ r³ U'(r) dr, integrated from the cut
+//! to infinity. Both are analytic for the potentials which are sums of powers of the distance.
+
+use crate::{potential::Potential, species::Interactions, system::System};
+
+/// The corrections for the interactions beyond the cut
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TailCorrections {
+	/// The energy missing from the microscopic energy
+	pub energy: f64,
+	/// The pressure missing from the virial pressure
+	pub pressure: f64,
+}
+
+/// The terms c r^-n of a potential which is a sum of powers of the distance beyond the cut, or `None` if it is not
+///
+/// # Arguments
+///
+/// * `potential` - The interaction between the particles of a pair
+/// * `r_cut` - The distance above which interactions are ignored
+pub(crate) fn power_law_terms(potential: &Potential, r_cut: f64) -> Option<Vec<(f64, f64)>> {
+	match potential {
+		Potential::LennardJones(potential) => {
+			let (r_star, epsilon_star) = (potential.r_star, potential.epsilon_star);
+			Some(vec![
+				(4.0 * epsilon_star * r_star.powi(12), 12.0),
+				(-8.0 * epsilon_star * r_star.powi(6), 6.0),
+			])
+		}
+		Potential::Mie(potential) => {
+			let prefactor = potential.prefactor();
+			Some(vec![
+				(prefactor * potential.sigma.powf(potential.n), potential.n),
+				(-prefactor * potential.sigma.powf(potential.m), potential.m),
+			])
+		}
+		// Purely repulsive, without interaction beyond r*
+		Potential::WeeksChandlerAndersen(potential) if r_cut >= potential.r_star => Some(vec![]),
+		_ => None,
+	}
+}
+
+impl System {
+	/// The corrections for the interactions beyond the cut, assuming the system is homogeneous.
+	/// With several species, the corrections of each pair of species are weighted by the fractions of both species.
+	/// `None` if they are disabled in the parameters, which [reject](crate::parameters::SimulationParameters::validate) them when the
+	/// potential has no analytic correction, i.e. is not a sum of powers of the distance.
+	/// The correction is the one of the potential without [treatment of the cut](crate::cutoff::Cutoff).
+	pub fn tail_corrections(&self) -> Option<TailCorrections> {
+		if !self.parameters.tail_corrections {
+			return None;
+		}
+		let r_cut = self.parameters.r_cut;
+		let interactions = self.interactions();
+
+		let nb_particles = self.nb_particles_total() as f64;
+		let density = nb_particles / self.parameters.box_side.powi(3);
+		let mut fractions = vec![0.0; self.species.len()];
+		for particle in &self.particles {
+			fractions[particle.species] += 1.0 / nb_particles;
+		}
+
+		let mut corrections = TailCorrections::default();
+		for a in 0..self.species.len() {
+			for b in 0..self.species.len() {
+				let weight = fractions[a] * fractions[b];
+				for (c, n) in power_law_terms(&interactions.between(a, b).potential, r_cut)? {
+					// ∫ r² c r^-n dr from the cut to infinity, and ∫ r³ d(c r^-n)/dr dr = -n times it
+					let integral = weight * c * r_cut.powf(3.0 - n) / (n - 3.0);
+					corrections.energy += 2.0 * std::f64::consts::PI * nb_particles * density * integral;
+					corrections.pressure += 2.0 / 3.0 * std::f64::consts::PI * density * density * n * integral;
+				}
+			}
+		}
+		return Some(corrections);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::Path;
+
+	use super::*;
+	use crate::{
+		assert_approx_eq,
+		parameters::{ParameterError, SimulationParameters},
+		potential::{Buckingham, Mie, Morse, PairPotential, WeeksChandlerAndersen, Yukawa},
+		tabulated::TabulatedPotential,
+	};
+
+	fn system_with_tail_corrections(potential: Option<Potential>) -> System {
+		let parameters = SimulationParameters {
+			tail_corrections: true,
+			potential,
+			..Default::default()
+		};
+		System::from_file_with_parameters(Path::new("dataset/particles.xyz"), 0, parameters).unwrap()
+	}
+
+	/// Integrate numerically E_tail and P_tail with the trapezoidal rule, over x = 1/r so that the domain is finite
+	fn numerical_tail_corrections(system: &System) -> TailCorrections {
+		let potential = system.potential().potential;
+		let r_cut = system.parameters.r_cut;
+		let nb_particles = system.nb_particles_total() as f64;
+		let density = nb_particles / system.parameters.box_side.powi(3);
+
+		let nb_steps = 100_000;
+		let h = 1.0 / r_cut / nb_steps as f64;
+		let (mut energy, mut virial) = (0.0, 0.0);
+		// At x = 0, i.e. infinitely far, both integrands are 0
+		for k in 1..=nb_steps {
+			let r = 1.0 / (k as f64 * h);
+			let weight = if k == nb_steps { 0.5 } else { 1.0 };
+			let (pair_energy, pair_force) = potential.energy_and_force(r * r);
+			// dr = -r² dx, and U'(r) = -r force
+			energy += weight * h * r.powi(4) * pair_energy;
+			virial += weight * h * r.powi(5) * -r * pair_force;
+		}
+		return TailCorrections {
+			energy: 2.0 * std::f64::consts::PI * nb_particles * density * energy,
+			pressure: -2.0 / 3.0 * std::f64::consts::PI * density * density * virial,
+		};
+	}
+
+	#[test]
+	fn tail_corrections_are_the_integrals_beyond_the_cut() {
+		for potential in [None, Some(Potential::Mie(Mie::new(14.0, 7.0, 3.0, 0.2)))] {
+			let system = system_with_tail_corrections(potential);
+			let analytic = system.tail_corrections().unwrap();
+			let numerical = numerical_tail_corrections(&system);
+			assert!(analytic.energy < 0.0);
+			assert!((analytic.energy - numerical.energy).abs() <= 1e-6 * analytic.energy.abs());
+			assert!((analytic.pressure - numerical.pressure).abs() <= 1e-6 * analytic.pressure.abs());
+		}
+	}
+
+	#[test]
+	fn tail_corrections_can_be_disabled() {
+		let system = System::from_file(Path::new("dataset/particles.xyz"), 0).unwrap();
+		assert_eq!(system.tail_corrections(), None);
+
+		let wca = WeeksChandlerAndersen::new(3.0, 0.2);
+		let system = system_with_tail_corrections(Some(Potential::WeeksChandlerAndersen(wca)));
+		assert_approx_eq!(system.tail_corrections().unwrap().energy, 0.0);
+	}
+
+	#[test]
+	fn tail_corrections_need_a_power_law() {
+		let table = TabulatedPotential::from_rows(Path::new("zero.table"), &[[1.0, 0.0, 0.0], [2.0, 0.0, 0.0], [3.0, 0.0, 0.0]]);
+		for potential in [
+			Potential::Morse(Morse::new(0.2, 1.0, 4.0)),
+			Potential::Buckingham(Buckingham::new(1000.0, 0.3, 10.0)),
+			Potential::Yukawa(Yukawa::new(1.0, 0.5)),
+			Potential::Tabulated(table.unwrap()),
+			// Still repulsive beyond the cut
+			Potential::WeeksChandlerAndersen(WeeksChandlerAndersen::new(15.0, 0.2)),
+		] {
+			let parameters = SimulationParameters {
+				tail_corrections: true,
+				potential: Some(potential),
+				..Default::default()
+			};
+			assert!(matches!(
+				parameters.validate(),
+				Err(ParameterError::Invalid {
+					name: "tail_corrections",
+					..
+				})
+			));
+			assert!(SimulationParameters {
+				tail_corrections: false,
+				..parameters
+			}
+			.validate()
+			.is_ok());
+		}
+	}
+}
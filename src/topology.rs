@@ -0,0 +1,626 @@
+//! Bonded interactions between the particles of molecules: harmonic bonds and angles, proper and improper dihedrals
+//!
+//! The [topology](Topology) lists the bonded terms with their force-field parameters, and the pairs of particles
+//! which skip the non-bonded pair potential since their interaction is already described by the bonded terms:
+//! the particles of a bond (1-2 pairs), the ends of an angle (1-3 pairs), the particles of a
+//! [constraint](crate::constraints) or of a rigid water molecule, and any other listed pair.
+//! The geometry of each term is computed with the nearest images of its particles to the first one, so a molecule
+//! must be smaller than half the box.
+
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	algebra::Vector3,
+	constraints::{Constraint, RigidWater},
+	forces::ForceEvaluation,
+	periodic_conditions::minimum_image,
+	system::System,
+};
+
+/// A harmonic bond `U = k (r - r0)²` between 2 particles
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bond {
+	/// The indices of the particles
+	pub particles: [usize; 2],
+	/// The stiffness k, in kcal/(mol.Å²)
+	pub stiffness: f64,
+	/// The equilibrium length r0, in Å
+	pub length: f64,
+}
+
+/// A harmonic angle `U = k (θ - θ0)²` between 3 particles, around the second one
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Angle {
+	/// The indices of the particles, the vertex of the angle being the second one
+	pub particles: [usize; 3],
+	/// The stiffness k, in kcal/(mol.rad²)
+	pub stiffness: f64,
+	/// The equilibrium angle θ0, in degrees
+	pub angle: f64,
+}
+
+/// A proper dihedral `U = k (1 + cos(nφ - δ))`, where φ is the angle between the planes of the first 3 particles and
+/// of the last 3 particles of a chain of 4 particles, 180° in the trans conformation
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Dihedral {
+	/// The indices of the particles, in the order of the chain
+	pub particles: [usize; 4],
+	/// The height of the barrier k, in kcal/mol
+	pub barrier: f64,
+	/// The number of minima n in a full turn
+	pub multiplicity: u32,
+	/// The phase δ, in degrees
+	pub phase: f64,
+}
+
+/// An improper dihedral `U = k (ψ - ψ0)²`, which keeps a particle in the plane of 3 others or a center chiral,
+/// where ψ is the dihedral angle of the 4 particles as for a [proper dihedral](Dihedral)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Improper {
+	/// The indices of the particles, the central particle being the first one
+	pub particles: [usize; 4],
+	/// The stiffness k, in kcal/(mol.rad²)
+	pub stiffness: f64,
+	/// The equilibrium angle ψ0, in degrees
+	pub angle: f64,
+}
+
+/// A bonded interaction between a fixed number of particles
+pub trait BondedTerm<const N: usize> {
+	/// The indices of the particles of the term
+	fn particles(&self) -> [usize; N];
+
+	/// Compute the energy of the term, and its gradient with respect to the position of each particle
+	///
+	/// # Arguments
+	///
+	/// * `positions` - The positions of the particles, relative to the first one
+	fn energy_and_gradients(&self, positions: [Vector3; N]) -> (f64, [Vector3; N]);
+}
+
+impl BondedTerm<2> for Bond {
+	fn particles(&self) -> [usize; 2] {
+		self.particles
+	}
+
+	fn energy_and_gradients(&self, [first, second]: [Vector3; 2]) -> (f64, [Vector3; 2]) {
+		let displacement = second - first;
+		let distance = displacement.norm();
+		let stretch = distance - self.length;
+		let gradient = displacement * (2.0 * self.stiffness * stretch / distance);
+		return (self.stiffness * stretch * stretch, [gradient * -1.0, gradient]);
+	}
+}
+
+impl BondedTerm<3> for Angle {
+	fn particles(&self) -> [usize; 3] {
+		self.particles
+	}
+
+	fn energy_and_gradients(&self, [first, vertex, last]: [Vector3; 3]) -> (f64, [Vector3; 3]) {
+		let (a, b) = (first - vertex, last - vertex);
+		let (norm_a, norm_b) = (a.norm(), b.norm());
+		let cos = (a.dot(&b) / (norm_a * norm_b)).clamp(-1.0, 1.0);
+		let angle = cos.acos();
+		let deviation = angle - self.angle.to_radians();
+		let energy = self.stiffness * deviation * deviation;
+
+		// The gradient of θ is undefined when the angle is flat
+		let sin = (1.0 - cos * cos).sqrt();
+		if sin < 1e-12 {
+			return (energy, [Vector3::zero(); 3]);
+		}
+		let derivative = -2.0 * self.stiffness * deviation / sin;
+		let gradient_first = (b / (norm_a * norm_b) - a * (cos / (norm_a * norm_a))) * derivative;
+		let gradient_last = (a / (norm_a * norm_b) - b * (cos / (norm_b * norm_b))) * derivative;
+		return (energy, [gradient_first, (gradient_first + gradient_last) * -1.0, gradient_last]);
+	}
+}
+
+/// Compute the dihedral angle of 4 particles, in (-π, π], and its gradient with respect to the position of each one
+///
+/// # Arguments
+///
+/// * `positions` - The positions of the particles
+fn dihedral_angle_and_gradients([i, j, k, l]: [Vector3; 4]) -> (f64, [Vector3; 4]) {
+	let (r_ij, r_kj, r_kl) = (i - j, k - j, k - l);
+	let (m, n) = (r_ij.cross(&r_kj), r_kj.cross(&r_kl));
+	let norm_kj = r_kj.norm();
+	let angle = (r_ij.dot(&n) * norm_kj).atan2(m.dot(&n));
+
+	// Blondel and Karplus, J. Comput. Chem. 17 (1996)
+	let gradient_i = m * (norm_kj / m.norm_squared());
+	let gradient_l = n * (-norm_kj / n.norm_squared());
+	let p = r_ij.dot(&r_kj) / (norm_kj * norm_kj);
+	let q = r_kl.dot(&r_kj) / (norm_kj * norm_kj);
+	let gradient_j = gradient_i * (p - 1.0) - gradient_l * q;
+	let gradient_k = gradient_l * (q - 1.0) - gradient_i * p;
+	return (angle, [gradient_i, gradient_j, gradient_k, gradient_l]);
+}
+
+impl BondedTerm<4> for Dihedral {
+	fn particles(&self) -> [usize; 4] {
+		self.particles
+	}
+
+	fn energy_and_gradients(&self, positions: [Vector3; 4]) -> (f64, [Vector3; 4]) {
+		let (angle, gradients) = dihedral_angle_and_gradients(positions);
+		let multiplicity = self.multiplicity as f64;
+		let argument = multiplicity * angle - self.phase.to_radians();
+		let derivative = -self.barrier * multiplicity * argument.sin();
+		return (
+			self.barrier * (1.0 + argument.cos()),
+			gradients.map(|gradient| gradient * derivative),
+		);
+	}
+}
+
+impl BondedTerm<4> for Improper {
+	fn particles(&self) -> [usize; 4] {
+		self.particles
+	}
+
+	fn energy_and_gradients(&self, positions: [Vector3; 4]) -> (f64, [Vector3; 4]) {
+		let (angle, gradients) = dihedral_angle_and_gradients(positions);
+		// The deviation is the shortest way around the circle
+		let deviation = angle - self.angle.to_radians();
+		let deviation = deviation - 2.0 * std::f64::consts::PI * (deviation / (2.0 * std::f64::consts::PI)).round();
+		let derivative = 2.0 * self.stiffness * deviation;
+		return (
+			self.stiffness * deviation * deviation,
+			gradients.map(|gradient| gradient * derivative),
+		);
+	}
+}
+
+/// The bonded interactions of a [system](System), and the pairs which skip the non-bonded pair potential
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Topology {
+	/// The harmonic bonds
+	pub bonds: Vec<Bond>,
+	/// The harmonic angles
+	pub angles: Vec<Angle>,
+	/// The proper dihedrals
+	pub dihedrals: Vec<Dihedral>,
+	/// The improper dihedrals
+	pub impropers: Vec<Improper>,
+	/// The distances kept fixed by SHAKE and RATTLE
+	pub constraints: Vec<Constraint>,
+	/// The rigid water molecules kept by SETTLE
+	pub rigid_waters: Vec<RigidWater>,
+	/// The pairs which skip the pair potential, besides the particles of the bonds, the ends of the angles and the
+	/// constrained pairs
+	pub extra_exclusions: Vec<[usize; 2]>,
+}
+
+/// An error that occurred while attaching a [topology](Topology) to a [system](System)
+#[derive(Debug, Clone, PartialEq)]
+pub enum TopologyError {
+	/// A term refers to a particle which is not in the system
+	UnknownParticle {
+		/// The index of the particle
+		index: usize,
+		/// The number of particles of the system
+		nb_particles: usize,
+	},
+	/// A term refers several times to the same particle
+	RepeatedParticle(usize),
+	/// A parameter of a term has a nonsensical value
+	Invalid {
+		/// The name of the parameter
+		name: &'static str,
+		/// Its value
+		value: f64,
+		/// Why the value is rejected
+		reason: &'static str,
+	},
+}
+
+impl Display for TopologyError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::UnknownParticle { index, nb_particles } => {
+				write!(
+					f,
+					"unknown particle {index} in the topology, the system has {nb_particles} particles"
+				)
+			}
+			Self::RepeatedParticle(index) => write!(f, "particle {index} appears several times in a term of the topology"),
+			Self::Invalid { name, value, reason } => write!(f, "invalid topology parameter {name} = {value}: {reason}"),
+		}
+	}
+}
+
+impl std::error::Error for TopologyError {}
+
+impl Topology {
+	/// Whether there are no bonded terms, constraints nor exclusions
+	pub fn is_empty(&self) -> bool {
+		self.bonds.is_empty()
+			&& self.angles.is_empty()
+			&& self.dihedrals.is_empty()
+			&& self.impropers.is_empty()
+			&& self.constraints.is_empty()
+			&& self.rigid_waters.is_empty()
+			&& self.extra_exclusions.is_empty()
+	}
+
+	/// The number of constrained distances, each removing a degree of liberty
+	pub fn nb_constraints(&self) -> usize {
+		self.constraints.len() + 3 * self.rigid_waters.len()
+	}
+
+	/// Check that the terms refer to distinct particles of a system, and that their parameters make sense
+	///
+	/// # Arguments
+	///
+	/// * `nb_particles` - The number of particles of the system
+	pub fn validate(&self, nb_particles: usize) -> Result<(), TopologyError> {
+		let invalid = |name, value, reason| Err(TopologyError::Invalid { name, value, reason });
+
+		let particles = self
+			.bonds
+			.iter()
+			.map(|bond| bond.particles.to_vec())
+			.chain(self.angles.iter().map(|angle| angle.particles.to_vec()))
+			.chain(self.dihedrals.iter().map(|dihedral| dihedral.particles.to_vec()))
+			.chain(self.impropers.iter().map(|improper| improper.particles.to_vec()))
+			.chain(self.constraints.iter().map(|constraint| constraint.particles.to_vec()))
+			.chain(self.rigid_waters.iter().map(|water| water.particles.to_vec()))
+			.chain(self.extra_exclusions.iter().map(|pair| pair.to_vec()));
+		for term in particles {
+			for (position, &index) in term.iter().enumerate() {
+				if index >= nb_particles {
+					return Err(TopologyError::UnknownParticle { index, nb_particles });
+				}
+				if term[..position].contains(&index) {
+					return Err(TopologyError::RepeatedParticle(index));
+				}
+			}
+		}
+
+		let strictly_positive = self
+			.bonds
+			.iter()
+			.map(|bond| ("bonds.length", bond.length))
+			.chain(self.constraints.iter().map(|constraint| ("constraints.length", constraint.length)))
+			.chain(self.rigid_waters.iter().map(|water| ("rigid_waters.oh_length", water.oh_length)))
+			.chain(self.rigid_waters.iter().map(|water| ("rigid_waters.hh_length", water.hh_length)));
+		for (name, value) in strictly_positive {
+			if !value.is_finite() || value <= 0.0 {
+				return invalid(name, value, "must be finite and strictly positive");
+			}
+		}
+		let positive = self
+			.bonds
+			.iter()
+			.map(|bond| ("bonds.stiffness", bond.stiffness))
+			.chain(self.angles.iter().map(|angle| ("angles.stiffness", angle.stiffness)))
+			.chain(self.impropers.iter().map(|improper| ("impropers.stiffness", improper.stiffness)));
+		for (name, value) in positive {
+			if !value.is_finite() || value < 0.0 {
+				return invalid(name, value, "must be finite and positive");
+			}
+		}
+		let finite = self
+			.angles
+			.iter()
+			.map(|angle| ("angles.angle", angle.angle))
+			.chain(self.dihedrals.iter().map(|dihedral| ("dihedrals.barrier", dihedral.barrier)))
+			.chain(self.dihedrals.iter().map(|dihedral| ("dihedrals.phase", dihedral.phase)))
+			.chain(self.impropers.iter().map(|improper| ("impropers.angle", improper.angle)));
+		for (name, value) in finite {
+			if !value.is_finite() {
+				return invalid(name, value, "must be finite");
+			}
+		}
+
+		for water in &self.rigid_waters {
+			if water.hh_length >= 2.0 * water.oh_length {
+				return invalid("rigid_waters.hh_length", water.hh_length, "must be smaller than twice oh_length");
+			}
+		}
+
+		return Ok(());
+	}
+
+	/// The pairs which skip the pair potential: the particles of the bonds, the ends of the angles, the constrained
+	/// pairs and the extra pairs
+	///
+	/// # Arguments
+	///
+	/// * `nb_particles` - The number of particles of the system
+	pub fn exclusions(&self, nb_particles: usize) -> Exclusions {
+		let mut excluded = vec![Vec::new(); nb_particles];
+		let pairs = self
+			.bonds
+			.iter()
+			.map(|bond| bond.particles)
+			.chain(self.angles.iter().map(|angle| [angle.particles[0], angle.particles[2]]))
+			.chain(self.constraints.iter().map(|constraint| constraint.particles))
+			.chain(self.rigid_waters.iter().flat_map(|water| water.pairs().map(|pair| pair.particles)))
+			.chain(self.extra_exclusions.iter().copied());
+		for [i, j] in pairs {
+			excluded[i].push(j);
+			excluded[j].push(i);
+		}
+		if excluded.iter().all(Vec::is_empty) {
+			return Exclusions::default();
+		}
+		for partners in excluded.iter_mut() {
+			partners.sort_unstable();
+			partners.dedup();
+		}
+		return Exclusions { excluded };
+	}
+}
+
+/// The pairs of particles which skip the pair potential, listed for each particle
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Exclusions {
+	/// The sorted indices of the particles excluded with each particle, empty without a [topology](Topology)
+	excluded: Vec<Vec<usize>>,
+}
+
+impl Exclusions {
+	/// Whether the pair of the given particles skips the pair potential
+	///
+	/// # Arguments
+	///
+	/// * `i` - The index of the first particle
+	/// * `j` - The index of the second particle
+	pub fn contains(&self, i: usize, j: usize) -> bool {
+		self.excluded.get(i).is_some_and(|partners| partners.binary_search(&j).is_ok())
+	}
+
+	/// The sorted indices of the particles excluded with the given one
+	///
+	/// # Arguments
+	///
+	/// * `i` - The index of the particle
+	pub fn of(&self, i: usize) -> &[usize] {
+		self.excluded.get(i).map_or(&[], Vec::as_slice)
+	}
+}
+
+impl System {
+	/// Get the bonded interactions of the system
+	pub fn topology(&self) -> &Topology {
+		&self.topology
+	}
+
+	/// Get the pairs of particles which skip the pair potential, according to the [topology](Topology)
+	pub fn exclusions(&self) -> &Exclusions {
+		&self.exclusions
+	}
+
+	/// Attach the bonded interactions of the molecules to the system, replacing the previous ones.
+	/// The positions must satisfy the constraints, and the relative velocities along the constrained bonds are removed.
+	///
+	/// # Arguments
+	///
+	/// * `topology` - The bonded interactions, whose particles are indices in the system
+	pub fn set_topology(&mut self, topology: Topology) -> Result<(), TopologyError> {
+		topology.validate(self.nb_particles_total())?;
+		self.exclusions = topology.exclusions(self.nb_particles_total());
+		self.topology = topology;
+		if self.has_constraints() {
+			self.constrain_momenta();
+		}
+		return Ok(());
+	}
+
+	/// Evaluate the forces, energy and virial of the bonded interactions of the [topology](Topology),
+	/// or `None` if there are none.
+	/// Like [`System::energy_gradient`], the evaluation holds the [gradients](ForceEvaluation::gradients) of the energy.
+	pub fn evaluate_bonded(&self) -> Option<ForceEvaluation> {
+		if self.topology.is_empty() {
+			return None;
+		}
+		let mut evaluation = ForceEvaluation::zero(self.nb_particles_total());
+		self.accumulate_bonded(&mut evaluation, &self.topology.bonds);
+		self.accumulate_bonded(&mut evaluation, &self.topology.angles);
+		self.accumulate_bonded(&mut evaluation, &self.topology.dihedrals);
+		self.accumulate_bonded(&mut evaluation, &self.topology.impropers);
+		return Some(evaluation);
+	}
+
+	/// Add the forces, energy and virial of bonded terms to an evaluation
+	///
+	/// # Arguments
+	///
+	/// * `evaluation` - Where the interactions are added
+	/// * `terms` - The bonded terms
+	fn accumulate_bonded<const N: usize>(&self, evaluation: &mut ForceEvaluation, terms: &[impl BondedTerm<N>]) {
+		let box_side = self.parameters.box_side;
+		for term in terms {
+			let particles = term.particles();
+			let first = self.particles[particles[0]].coordinates;
+			let positions = particles.map(|i| minimum_image(self.particles[i].coordinates - first, box_side));
+			let (energy, gradients) = term.energy_and_gradients(positions);
+
+			evaluation.energy += energy;
+			for ((&i, gradient), position) in particles.iter().zip(&gradients).zip(&positions) {
+				evaluation.gradients[i] += *gradient;
+				// The gradients sum to 0, so the origin of the positions doesn't matter
+				evaluation.virial -= position.dot(gradient);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Check the gradients of a term against finite differences of its energy
+	fn assert_gradients_match_energy<const N: usize>(term: &impl BondedTerm<N>, positions: [Vector3; N]) {
+		let (_, gradients) = term.energy_and_gradients(positions);
+		let h = 1e-6;
+		for a in 0..N {
+			let energy_moved = |displacement: Vector3| {
+				let mut moved = positions;
+				moved[a] += displacement;
+				term.energy_and_gradients(moved).0
+			};
+			let numerical = Vector3::from(
+				energy_moved(Vector3::from(h, 0.0, 0.0)) - energy_moved(Vector3::from(-h, 0.0, 0.0)),
+				energy_moved(Vector3::from(0.0, h, 0.0)) - energy_moved(Vector3::from(0.0, -h, 0.0)),
+				energy_moved(Vector3::from(0.0, 0.0, h)) - energy_moved(Vector3::from(0.0, 0.0, -h)),
+			) / (2.0 * h);
+			assert!(
+				(gradients[a] - numerical).norm() < 1e-6 * numerical.norm().max(1.0),
+				"particle {a}: {:?} != {numerical:?}",
+				gradients[a]
+			);
+		}
+	}
+
+	/// The positions of a twisted chain of 4 particles
+	fn chain() -> [Vector3; 4] {
+		[
+			Vector3::zero(),
+			Vector3::from(1.1, 0.9, -0.2),
+			Vector3::from(2.5, 1.2, 0.4),
+			Vector3::from(2.9, 2.1, 1.5),
+		]
+	}
+
+	#[test]
+	fn bond_gradients_match_the_energy() {
+		let bond = Bond {
+			particles: [0, 1],
+			stiffness: 450.0,
+			length: 1.0,
+		};
+		assert_gradients_match_energy(&bond, [chain()[0], chain()[1]]);
+		let (energy, _) = bond.energy_and_gradients([Vector3::zero(), Vector3::from(0.0, 1.0, 0.0)]);
+		assert_eq!(energy, 0.0);
+	}
+
+	#[test]
+	fn angle_gradients_match_the_energy() {
+		let angle = Angle {
+			particles: [0, 1, 2],
+			stiffness: 55.0,
+			angle: 104.52,
+		};
+		assert_gradients_match_energy(&angle, [chain()[0], chain()[1], chain()[2]]);
+		let right_angle = [Vector3::from(1.0, 0.0, 0.0), Vector3::zero(), Vector3::from(0.0, 2.0, 0.0)];
+		let (energy, _) = Angle { angle: 90.0, ..angle }.energy_and_gradients(right_angle);
+		assert!(energy.abs() < 1e-20);
+	}
+
+	#[test]
+	fn dihedral_angles_follow_the_convention() {
+		let conformation = |angle: f64| {
+			let angle: f64 = angle.to_radians();
+			[
+				Vector3::from(1.0, 1.0, 0.0),
+				Vector3::from(0.0, 1.0, 0.0),
+				Vector3::zero(),
+				Vector3::from(angle.cos(), 0.0, angle.sin()),
+			]
+		};
+		assert!(dihedral_angle_and_gradients(conformation(0.0)).0.abs() < 1e-12);
+		assert!((dihedral_angle_and_gradients(conformation(180.0)).0.abs() - std::f64::consts::PI).abs() < 1e-12);
+		let (angle, _) = dihedral_angle_and_gradients(conformation(60.0));
+		assert!((angle.abs() - 60f64.to_radians()).abs() < 1e-12);
+	}
+
+	#[test]
+	fn dihedral_gradients_match_the_energy() {
+		for multiplicity in [1, 2, 3] {
+			let dihedral = Dihedral {
+				particles: [0, 1, 2, 3],
+				barrier: 1.4,
+				multiplicity,
+				phase: 30.0,
+			};
+			assert_gradients_match_energy(&dihedral, chain());
+		}
+	}
+
+	#[test]
+	fn improper_gradients_match_the_energy() {
+		for angle in [0.0, 35.0, 180.0] {
+			let improper = Improper {
+				particles: [0, 1, 2, 3],
+				stiffness: 10.0,
+				angle,
+			};
+			assert_gradients_match_energy(&improper, chain());
+		}
+	}
+
+	#[test]
+	fn bonds_and_angle_ends_are_excluded() {
+		let topology = Topology {
+			bonds: vec![
+				Bond {
+					particles: [0, 1],
+					stiffness: 1.0,
+					length: 1.0,
+				},
+				Bond {
+					particles: [1, 2],
+					stiffness: 1.0,
+					length: 1.0,
+				},
+			],
+			angles: vec![Angle {
+				particles: [0, 1, 2],
+				stiffness: 1.0,
+				angle: 109.5,
+			}],
+			extra_exclusions: vec![[3, 4]],
+			..Default::default()
+		};
+		let exclusions = topology.exclusions(5);
+		assert_eq!(exclusions.of(0), [1, 2]);
+		assert_eq!(exclusions.of(1), [0, 2]);
+		assert!(exclusions.contains(4, 3));
+		assert!(!exclusions.contains(0, 3));
+		assert!(!Exclusions::default().contains(0, 1));
+
+		assert_eq!(
+			topology.validate(4),
+			Err(TopologyError::UnknownParticle { index: 4, nb_particles: 4 })
+		);
+		assert!(topology.validate(5).is_ok());
+	}
+
+	#[test]
+	fn constrained_pairs_are_excluded() {
+		let mut topology = Topology {
+			constraints: vec![Constraint {
+				particles: [3, 4],
+				length: 1.5,
+			}],
+			rigid_waters: vec![RigidWater::spc_e([0, 1, 2])],
+			..Default::default()
+		};
+		assert_eq!(topology.nb_constraints(), 4);
+		let exclusions = topology.exclusions(5);
+		assert_eq!(exclusions.of(0), [1, 2]);
+		assert_eq!(exclusions.of(1), [0, 2]);
+		assert!(exclusions.contains(4, 3));
+		assert!(topology.validate(5).is_ok());
+
+		topology.rigid_waters[0].hh_length = 2.5;
+		assert!(matches!(
+			topology.validate(5),
+			Err(TopologyError::Invalid {
+				name: "rigid_waters.hh_length",
+				..
+			})
+		));
+	}
+}
@@ -0,0 +1,197 @@
+//! Persistence of the trajectory of a simulation, as multi-frame extended XYZ files readable by VMD or OVITO
+
+use std::{
+	fs::File,
+	io::{BufRead, BufReader, BufWriter, Lines, Write},
+	iter::Enumerate,
+	path::{Path, PathBuf},
+};
+
+use crate::{
+	system::System,
+	xyz::{Frame, ParseError, ParseErrorReason, read_frame},
+};
+
+/// Writes frames of a [system](System) every given number of steps.
+/// A writer to any output can be used as a `TrajectoryWriter<dyn Write>`.
+pub struct TrajectoryWriter<W: Write + ?Sized> {
+	/// The number of steps between 2 frames
+	every: usize,
+	/// Whether the momenta of the particles are written
+	with_momenta: bool,
+	/// Whether the forces applied to the particles are written
+	with_forces: bool,
+	/// Where the frames are written
+	writer: W,
+}
+
+impl TrajectoryWriter<BufWriter<File>> {
+	/// Create a writer to a new file, replacing it if it exists
+	///
+	/// # Arguments
+	///
+	/// * `path` - The path of the file
+	/// * `every` - The number of steps between 2 frames
+	pub fn create(path: &Path, every: usize) -> std::io::Result<Self> {
+		Ok(Self::new(BufWriter::new(File::create(path)?), every))
+	}
+}
+
+impl<W: Write> TrajectoryWriter<W> {
+	/// Create a writer writing the positions of the particles every given number of steps
+	///
+	/// # Arguments
+	///
+	/// * `writer` - Where the frames are written
+	/// * `every` - The number of steps between 2 frames
+	pub fn new(writer: W, every: usize) -> Self {
+		assert!(every > 0);
+		Self {
+			writer,
+			every,
+			with_momenta: false,
+			with_forces: false,
+		}
+	}
+
+	/// Also write the momenta of the particles
+	pub fn with_momenta(mut self) -> Self {
+		self.with_momenta = true;
+		self
+	}
+
+	/// Also write the forces applied to the particles
+	pub fn with_forces(mut self) -> Self {
+		self.with_forces = true;
+		self
+	}
+
+	/// Get back the underlying writer
+	pub fn into_inner(self) -> W {
+		self.writer
+	}
+}
+
+impl<W: Write + ?Sized> TrajectoryWriter<W> {
+	/// Write a frame of the system if the step is a multiple of the number of steps between 2 frames
+	///
+	/// # Arguments
+	///
+	/// * `system` - The system to write
+	/// * `step` - The number of steps done since the beginning of the simulation
+	pub fn record(&mut self, system: &System, step: usize) -> std::io::Result<()> {
+		if step.is_multiple_of(self.every) {
+			self.write_frame(system, step)?;
+		}
+		return Ok(());
+	}
+
+	/// Write a frame of the system, whatever the step
+	///
+	/// # Arguments
+	///
+	/// * `system` - The system to write
+	/// * `step` - The number of steps done since the beginning of the simulation
+	pub fn write_frame(&mut self, system: &System, step: usize) -> std::io::Result<()> {
+		let parameters = system.parameters();
+		let box_side = parameters.box_side;
+
+		let mut properties = String::from("species:S:1:pos:R:3");
+		if self.with_momenta {
+			properties.push_str(":momenta:R:3");
+		}
+		let forces = if self.with_forces {
+			properties.push_str(":forces:R:3");
+			system.forces_per_particle()
+		} else {
+			Vec::new()
+		};
+
+		writeln!(self.writer, "{}", system.nb_particles_total())?;
+		writeln!(
+			self.writer,
+			"Lattice=\"{box_side} 0 0 0 {box_side} 0 0 0 {box_side}\" Properties={properties} Step={step} Time={} pbc=\"T T T\"",
+			step as f64 * parameters.delta_time
+		)?;
+		for (i, particle) in system.particles().iter().enumerate() {
+			let (x, y, z) = particle.xyz();
+			write!(self.writer, "{} {x} {y} {z}", system.species()[particle.species()])?;
+			if self.with_momenta {
+				let p = particle.kinetic_moment();
+				write!(self.writer, " {} {} {}", p.x(), p.y(), p.z())?;
+			}
+			if self.with_forces {
+				write!(self.writer, " {} {} {}", forces[i].x(), forces[i].y(), forces[i].z())?;
+			}
+			writeln!(self.writer)?;
+		}
+
+		return Ok(());
+	}
+
+	/// Flush the frames written so far to the underlying writer
+	pub fn flush(&mut self) -> std::io::Result<()> {
+		return self.writer.flush();
+	}
+}
+
+/// Reads the frames of a multi-frame XYZ file lazily, one at a time
+pub struct TrajectoryReader<R: BufRead> {
+	/// The lines of the input, with their index
+	lines: Enumerate<Lines<R>>,
+	/// The file being read, if the input comes from a file
+	file: Option<PathBuf>,
+	/// Whether an error occurred, after which no frame is read anymore
+	failed: bool,
+}
+
+impl TrajectoryReader<BufReader<File>> {
+	/// Open a trajectory file
+	///
+	/// # Arguments
+	///
+	/// * `path` - The path of the file
+	pub fn open(path: &Path) -> Result<Self, ParseError> {
+		let file = File::open(path).map_err(|error| ParseError::new(0, 0, ParseErrorReason::Io(error)).in_file(path))?;
+		let mut reader = Self::new(BufReader::new(file));
+		reader.file = Some(path.to_path_buf());
+		return Ok(reader);
+	}
+}
+
+impl<R: BufRead> TrajectoryReader<R> {
+	/// Read the frames of the given input
+	///
+	/// # Arguments
+	///
+	/// * `reader` - The input to read
+	pub fn new(reader: R) -> Self {
+		Self {
+			lines: reader.lines().enumerate(),
+			file: None,
+			failed: false,
+		}
+	}
+}
+
+impl<R: BufRead> Iterator for TrajectoryReader<R> {
+	type Item = Result<Frame, ParseError>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.failed {
+			return None;
+		}
+
+		let mut lines = self.lines.by_ref().map(|(index, line)| {
+			line.map(|line| (index + 1, line))
+				.map_err(|error| ParseError::new(index + 1, 0, ParseErrorReason::Io(error)))
+		});
+		let frame = read_frame(&mut lines).transpose()?;
+
+		self.failed = frame.is_err();
+		return Some(match &self.file {
+			Some(file) => frame.map_err(|error| error.in_file(file)),
+			None => frame,
+		});
+	}
+}
@@ -0,0 +1,160 @@
+//! Verlet neighbour lists, reused across steps until a particle moved more than half the skin
+//!
+//! The neighbours of each particle are the particles closer than the cut plus a skin distance.
+//! As long as no particle moved more than half the skin, no pair can have entered the cut without being in the list.
+
+use crate::{
+	algebra::{Point3, Vector3},
+	cell_list::CellList,
+	parameters::PairSearch,
+	periodic_conditions::minimum_image,
+	system::System,
+};
+
+/// How often the [Verlet list](VerletList) of a [system](System) had to be rebuilt
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct VerletListStatistics {
+	/// The number of times the list was built
+	pub nb_builds: usize,
+	/// The number of times the list was used to compute forces
+	pub nb_updates: usize,
+	/// The largest displacement of a particle since the last build, at the last update
+	pub max_displacement: f64,
+}
+
+/// The neighbours of each particle of a [system](System), closer than the cut plus a skin distance
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerletList {
+	/// The neighbours of each particle, sorted, so that each pair is stored in both directions
+	neighbours: Vec<Vec<usize>>,
+	/// The positions of the particles when the list was built
+	reference_positions: Vec<Point3>,
+	/// The skin distance added to the cut
+	skin: f64,
+	/// How often the list had to be rebuilt
+	statistics: VerletListStatistics,
+}
+
+impl VerletList {
+	/// Build the list of the neighbours of the particles of a system
+	///
+	/// # Arguments
+	///
+	/// * `system` - The system whose particles are listed
+	/// * `skin` - The skin distance added to the cut of the system
+	pub fn new(system: &System, skin: f64) -> Self {
+		let mut list = Self {
+			neighbours: Vec::new(),
+			reference_positions: Vec::new(),
+			skin,
+			statistics: VerletListStatistics::default(),
+		};
+		list.build(system);
+		return list;
+	}
+
+	/// Build the list again, from the current positions of the particles
+	fn build(&mut self, system: &System) {
+		let radius = system.parameters.r_cut + self.skin;
+		let cell_list = CellList::new(&system.particles, system.parameters.box_side, radius);
+
+		self.neighbours = vec![Vec::new(); system.nb_particles_total()];
+		system.for_each_pair_within(&cell_list, radius, |i, j, _| {
+			self.neighbours[i].push(j);
+			self.neighbours[j].push(i);
+		});
+		// The order of the sums then doesn't depend on when the list was built
+		for neighbours in self.neighbours.iter_mut() {
+			neighbours.sort_unstable();
+		}
+
+		self.reference_positions = system.particles.iter().map(|particle| particle.coordinates).collect();
+		self.statistics.nb_builds += 1;
+		self.statistics.max_displacement = 0.0;
+	}
+
+	/// The largest displacement of a particle since the list was built
+	fn max_displacement(&self, system: &System) -> f64 {
+		let box_side = system.parameters.box_side;
+		return system
+			.particles
+			.iter()
+			.zip(&self.reference_positions)
+			.map(|(particle, reference)| minimum_image(particle.coordinates - *reference, box_side).norm())
+			.fold(0.0, f64::max);
+	}
+
+	/// Whether the list is still valid for the current positions of the particles of the system
+	pub fn is_valid_for(&self, system: &System) -> bool {
+		self.neighbours.len() == system.nb_particles_total() && self.max_displacement(system) <= self.skin / 2.0
+	}
+
+	/// Update the list for the current positions of the particles, building it again if a particle moved too much
+	///
+	/// # Arguments
+	///
+	/// * `system` - The system whose particles are listed
+	pub fn update(&mut self, system: &System) {
+		self.statistics.nb_updates += 1;
+		let max_displacement = self.max_displacement(system);
+		if self.neighbours.len() != system.nb_particles_total() || max_displacement > self.skin / 2.0 {
+			self.build(system);
+		} else {
+			self.statistics.max_displacement = max_displacement;
+		}
+	}
+
+	/// How often the list had to be rebuilt
+	pub fn statistics(&self) -> VerletListStatistics {
+		self.statistics
+	}
+
+	/// The neighbours of a particle, sorted by index
+	///
+	/// # Arguments
+	///
+	/// * `i` - The index of the particle
+	pub fn neighbours(&self, i: usize) -> &[usize] {
+		&self.neighbours[i]
+	}
+}
+
+impl System {
+	/// Call the given function on each pair of particles closer than the cut, with the displacement from the nearest
+	/// image of the second particle to the first one, found with a [Verlet list](VerletList).
+	pub(crate) fn for_each_pair_in_verlet_list(&self, verlet_list: &VerletList, mut f: impl FnMut(usize, usize, Vector3)) {
+		let box_side = self.parameters.box_side;
+		let radius_cut_squared = self.parameters.r_cut.powi(2);
+		for (i, neighbours) in verlet_list.neighbours.iter().enumerate() {
+			// Each pair is only visited from its particle with the lowest index
+			let greater = neighbours.partition_point(|&j| j < i);
+			for &j in &neighbours[greater..] {
+				let displacement = minimum_image(self.particles[i].coordinates - self.particles[j].coordinates, box_side);
+				if displacement.norm_squared() <= radius_cut_squared {
+					f(i, j, displacement);
+				}
+			}
+		}
+	}
+
+	/// Update the [Verlet list](VerletList) of the system for the current positions, if the Verlet list is used.
+	/// The list is built again if a particle moved more than half the skin since it was built.
+	pub fn update_verlet_list(&mut self) {
+		if self.parameters.pair_search != PairSearch::VerletList {
+			self.verlet_list = None;
+			return;
+		}
+
+		let mut verlet_list = match self.verlet_list.take() {
+			Some(verlet_list) => verlet_list,
+			None => VerletList::new(self, self.parameters.verlet_skin),
+		};
+		verlet_list.update(self);
+		self.verlet_list = Some(verlet_list);
+	}
+
+	/// How often the [Verlet list](VerletList) of the system had to be rebuilt, if it is used
+	pub fn verlet_list_statistics(&self) -> Option<VerletListStatistics> {
+		self.verlet_list.as_ref().map(VerletList::statistics)
+	}
+}
@@ -0,0 +1,749 @@
+//! Reading of XYZ files, and the errors that can occur while doing so
+//!
+//! Three flavours are supported:
+//! * The course format: a header line with two integers, then one `type x y z` line per particle
+//! * The standard XYZ format: the number of atoms, a comment line, then one `symbol x y z` line per atom
+//! * The extended XYZ format: a standard XYZ file whose comment line holds `key=value` pairs, such as
+//!   `Lattice="42 0 0 0 42 0 0 0 42" Properties=species:S:1:pos:R:3:velo:R:3`
+
+use std::{
+	fmt::Display,
+	path::{Path, PathBuf},
+	str::FromStr,
+};
+
+use crate::{
+	algebra::{Point3, Vector3},
+	parameters::ParameterError,
+};
+
+/// Why a line of an XYZ file could not be parsed
+#[derive(Debug)]
+pub enum ParseErrorReason {
+	/// The file could not be read
+	Io(std::io::Error),
+	/// The file contains no line at all
+	EmptyFile,
+	/// A field is missing at the end of the line
+	MissingField(&'static str),
+	/// A field is not a valid unsigned integer
+	InvalidInteger {
+		/// The name of the field
+		field: &'static str,
+		/// The text that was found instead
+		token: String,
+	},
+	/// A field is not a valid floating point number
+	InvalidFloat {
+		/// The name of the field
+		field: &'static str,
+		/// The text that was found instead
+		token: String,
+	},
+	/// There is more text on the line than expected
+	UnexpectedToken(String),
+	/// The number of atoms announced in the header is not the number of atoms in the file
+	AtomCountMismatch {
+		/// The number of atoms announced in the header
+		expected: usize,
+		/// The number of atoms in the file
+		found: usize,
+	},
+	/// The `Properties` key of an extended XYZ comment line is malformed
+	InvalidProperties(String),
+	/// The `Lattice` key of an extended XYZ comment line is malformed, or is not a cubic box
+	UnsupportedLattice(String),
+	/// A quoted value of an extended XYZ comment line is never closed
+	UnterminatedQuote,
+	/// The parameters of the simulation are invalid with the box read from the file
+	InvalidParameters(Box<ParameterError>),
+	/// There are not more particles than the requested number of local particles
+	TooManyLocalParticles {
+		/// The requested number of local particles
+		nb_particles_local: usize,
+		/// The number of particles in the file
+		nb_particles: usize,
+	},
+}
+
+impl Display for ParseErrorReason {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Io(error) => write!(f, "{error}"),
+			Self::EmptyFile => write!(f, "the file is empty"),
+			Self::MissingField(field) => write!(f, "missing {field}"),
+			Self::InvalidInteger { field, token } => write!(f, "invalid {field}: `{token}` is not an unsigned integer"),
+			Self::InvalidFloat { field, token } => write!(f, "invalid {field}: `{token}` is not a floating point number"),
+			Self::UnexpectedToken(token) => write!(f, "unexpected `{token}` at the end of the line"),
+			Self::InvalidProperties(reason) => write!(f, "invalid properties: {reason}"),
+			Self::UnsupportedLattice(reason) => write!(f, "unsupported lattice: {reason}"),
+			Self::UnterminatedQuote => write!(f, "unterminated quote"),
+			Self::InvalidParameters(error) => write!(f, "{error}"),
+			Self::AtomCountMismatch { expected, found } => {
+				write!(f, "the header announces {expected} atoms, but {found} were found")
+			}
+			Self::TooManyLocalParticles {
+				nb_particles_local,
+				nb_particles,
+			} => write!(
+				f,
+				"{nb_particles_local} local particles requested, but there are only {nb_particles} particles"
+			),
+		}
+	}
+}
+
+/// An error that occurred while parsing an XYZ file, with its location
+#[derive(Debug)]
+pub struct ParseError {
+	/// The file being parsed, if the input comes from a file
+	pub(crate) file: Option<PathBuf>,
+	/// The line of the error, starting at 1 (0 if the error is not tied to a line)
+	pub(crate) line: usize,
+	/// The column of the error, starting at 1 (0 if the error is not tied to a line)
+	pub(crate) column: usize,
+	/// Why the parsing failed
+	pub(crate) reason: ParseErrorReason,
+}
+
+impl ParseError {
+	/// Create an error at the given line and column
+	pub(crate) fn new(line: usize, column: usize, reason: ParseErrorReason) -> Self {
+		Self {
+			file: None,
+			line,
+			column,
+			reason,
+		}
+	}
+
+	/// Attach the file being parsed to the error
+	pub(crate) fn in_file(mut self, path: &Path) -> Self {
+		self.file = Some(path.to_path_buf());
+		self
+	}
+
+	/// The file being parsed, if the input comes from a file
+	pub fn file(&self) -> Option<&Path> {
+		self.file.as_deref()
+	}
+
+	/// The line of the error, starting at 1 (0 if the error is not tied to a line)
+	pub fn line(&self) -> usize {
+		self.line
+	}
+
+	/// The column of the error, starting at 1 (0 if the error is not tied to a line)
+	pub fn column(&self) -> usize {
+		self.column
+	}
+
+	/// Why the parsing failed
+	pub fn reason(&self) -> &ParseErrorReason {
+		&self.reason
+	}
+}
+
+impl Display for ParseError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match &self.file {
+			Some(file) => write!(f, "{}:", file.display())?,
+			None => write!(f, "<string>:")?,
+		}
+		if self.line != 0 {
+			write!(f, "{}:{}:", self.line, self.column)?;
+		}
+		write!(f, " {}", self.reason)
+	}
+}
+
+impl std::error::Error for ParseError {}
+
+/// The whitespace separated tokens of a line, keeping track of their column for error reporting
+pub(crate) struct Tokens<'a> {
+	/// The line being split
+	line: &'a str,
+	/// The number of the line, starting at 1
+	line_number: usize,
+	/// The byte offset of the rest of the line to split
+	offset: usize,
+}
+
+impl<'a> Tokens<'a> {
+	/// Split the given line
+	///
+	/// # Arguments
+	///
+	/// * `line` - The line to split
+	/// * `line_number` - The number of the line in the file, starting at 1
+	pub(crate) fn new(line: &'a str, line_number: usize) -> Self {
+		Self {
+			line,
+			line_number,
+			offset: 0,
+		}
+	}
+
+	/// The column of the given byte offset, starting at 1
+	fn column(&self, offset: usize) -> usize {
+		self.line[..offset].chars().count() + 1
+	}
+
+	/// Create an error at the given byte offset of the line
+	pub(crate) fn error_at(&self, offset: usize, reason: ParseErrorReason) -> ParseError {
+		ParseError::new(self.line_number, self.column(offset), reason)
+	}
+
+	/// Get the next token with its byte offset, if any
+	pub(crate) fn next_token(&mut self) -> Option<(usize, &'a str)> {
+		let rest = &self.line[self.offset..];
+		let start = self.offset + (rest.len() - rest.trim_start().len());
+		let length = self.line[start..].find(char::is_whitespace).unwrap_or(self.line.len() - start);
+		self.offset = start + length;
+
+		if length == 0 {
+			return None;
+		}
+		return Some((start, &self.line[start..start + length]));
+	}
+
+	/// Get the next token, which must be present
+	///
+	/// # Arguments
+	///
+	/// * `field` - The name of the expected field, for error reporting
+	pub(crate) fn expect(&mut self, field: &'static str) -> Result<(usize, &'a str), ParseError> {
+		self.next_token()
+			.ok_or_else(|| self.error_at(self.line.trim_end().len(), ParseErrorReason::MissingField(field)))
+	}
+
+	/// Parse the next token as an unsigned integer
+	///
+	/// # Arguments
+	///
+	/// * `field` - The name of the expected field, for error reporting
+	pub(crate) fn integer(&mut self, field: &'static str) -> Result<usize, ParseError> {
+		let (offset, token) = self.expect(field)?;
+		return token.parse().map_err(|_| {
+			self.error_at(
+				offset,
+				ParseErrorReason::InvalidInteger {
+					field,
+					token: token.to_string(),
+				},
+			)
+		});
+	}
+
+	/// Parse the next token as a floating point number
+	///
+	/// # Arguments
+	///
+	/// * `field` - The name of the expected field, for error reporting
+	pub(crate) fn float(&mut self, field: &'static str) -> Result<f64, ParseError> {
+		let (offset, token) = self.expect(field)?;
+		return f64::from_str(token).map_err(|_| {
+			self.error_at(
+				offset,
+				ParseErrorReason::InvalidFloat {
+					field,
+					token: token.to_string(),
+				},
+			)
+		});
+	}
+
+	/// Check that there is nothing left on the line
+	pub(crate) fn end(&mut self) -> Result<(), ParseError> {
+		match self.next_token() {
+			Some((offset, token)) => Err(self.error_at(offset, ParseErrorReason::UnexpectedToken(token.to_string()))),
+			None => Ok(()),
+		}
+	}
+}
+
+/// A snapshot of particles read from an XYZ file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+	/// The side of the cubic box, if given by the `Lattice` key
+	pub(crate) box_side: Option<f64>,
+	/// The other `key=value` pairs of the comment line
+	pub(crate) info: Vec<(String, String)>,
+	/// The species of each atom
+	pub(crate) species: Vec<String>,
+	/// The position of each atom
+	pub(crate) positions: Vec<Point3>,
+	/// The velocity of each atom, in Å/fs, if given by the `velo` property
+	pub(crate) velocities: Option<Vec<Vector3>>,
+	/// The momentum of each atom, if given by the `momenta` property
+	pub(crate) momenta: Option<Vec<Vector3>>,
+	/// The force applied to each atom, if given by the `forces` property
+	pub(crate) forces: Option<Vec<Vector3>>,
+	/// The electric charge of each atom, in elementary charges, if given by the `charge` property
+	pub(crate) charges: Option<Vec<f64>>,
+}
+
+impl Frame {
+	/// The number of atoms in the frame
+	pub fn nb_atoms(&self) -> usize {
+		self.positions.len()
+	}
+
+	/// The side of the cubic box, if given by the `Lattice` key
+	pub fn box_side(&self) -> Option<f64> {
+		self.box_side
+	}
+
+	/// The value of a `key=value` pair of the comment line, other than `Lattice` and `Properties`
+	pub fn info(&self, key: &str) -> Option<&str> {
+		self.info
+			.iter()
+			.find(|(k, _)| k.eq_ignore_ascii_case(key))
+			.map(|(_, value)| value.as_str())
+	}
+
+	/// The step of the simulation at which the frame was taken, if given by the `Step` key
+	pub fn step(&self) -> Option<usize> {
+		self.info("Step")?.parse().ok()
+	}
+
+	/// The species of each atom
+	pub fn species(&self) -> &[String] {
+		&self.species
+	}
+
+	/// The position of each atom
+	pub fn positions(&self) -> &[Point3] {
+		&self.positions
+	}
+
+	/// The velocity of each atom, if present in the file
+	pub fn velocities(&self) -> Option<&[Vector3]> {
+		self.velocities.as_deref()
+	}
+
+	/// The momentum of each atom, if present in the file
+	pub fn momenta(&self) -> Option<&[Vector3]> {
+		self.momenta.as_deref()
+	}
+
+	/// The force applied to each atom, if present in the file
+	pub fn forces(&self) -> Option<&[Vector3]> {
+		self.forces.as_deref()
+	}
+
+	/// The electric charge of each atom, if present in the file
+	pub fn charges(&self) -> Option<&[f64]> {
+		self.charges.as_deref()
+	}
+}
+
+/// What a column of an extended XYZ file holds
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Role {
+	/// The species of the atom
+	Species,
+	/// The position of the atom
+	Position,
+	/// The velocity of the atom
+	Velocity,
+	/// The momentum of the atom
+	Momentum,
+	/// The force applied to the atom
+	Force,
+	/// The electric charge of the atom
+	Charge,
+	/// A property which is not used by the simulation, and is skipped
+	Ignored,
+}
+
+/// A property of the `Properties` key of an extended XYZ comment line
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Property {
+	/// What the property holds
+	role: Role,
+	/// The number of columns of the property
+	count: usize,
+}
+
+/// The layout of the atom lines, as given by the `Properties` key
+#[derive(Debug, Clone, PartialEq)]
+struct Properties(Vec<Property>);
+
+impl Default for Properties {
+	/// The layout of a standard XYZ file, `species:S:1:pos:R:3`
+	fn default() -> Self {
+		Self(vec![
+			Property {
+				role: Role::Species,
+				count: 1,
+			},
+			Property {
+				role: Role::Position,
+				count: 3,
+			},
+		])
+	}
+}
+
+impl Properties {
+	/// Parse the value of a `Properties` key, such as `species:S:1:pos:R:3`
+	fn parse(value: &str) -> Result<Self, ParseErrorReason> {
+		let invalid = |reason: String| ParseErrorReason::InvalidProperties(reason);
+
+		let fields: Vec<&str> = value.split(':').collect();
+		if !fields.len().is_multiple_of(3) {
+			return Err(invalid(format!("`{value}` is not made of name:type:count triplets")));
+		}
+
+		let mut properties = Vec::new();
+		for triplet in fields.chunks(3) {
+			let (name, kind, count) = (triplet[0], triplet[1], triplet[2]);
+			let count: usize = count.parse().map_err(|_| invalid(format!("`{count}` is not a column count")))?;
+			if !["S", "R", "I", "L"].contains(&kind) {
+				return Err(invalid(format!("`{kind}` is not a property type")));
+			}
+
+			let (role, expected) = match name.to_ascii_lowercase().as_str() {
+				"species" => (Role::Species, ("S", 1)),
+				"pos" | "positions" => (Role::Position, ("R", 3)),
+				"velo" | "velocities" => (Role::Velocity, ("R", 3)),
+				"momenta" => (Role::Momentum, ("R", 3)),
+				"forces" | "force" => (Role::Force, ("R", 3)),
+				"charge" | "charges" => (Role::Charge, ("R", 1)),
+				_ => (Role::Ignored, (kind, count)),
+			};
+			if (kind, count) != expected {
+				return Err(invalid(format!("`{name}` should be {}:{}", expected.0, expected.1)));
+			}
+			if role != Role::Ignored && properties.iter().any(|property: &Property| property.role == role) {
+				return Err(invalid(format!("`{name}` is given twice")));
+			}
+			properties.push(Property { role, count });
+		}
+
+		if !properties.iter().any(|property| property.role == Role::Position) {
+			return Err(invalid("the positions are missing".to_string()));
+		}
+		return Ok(Self(properties));
+	}
+
+	/// Whether the layout holds the given role
+	fn has(&self, role: Role) -> bool {
+		self.0.iter().any(|property| property.role == role)
+	}
+
+	/// Parse the line of an atom, and add it to the frame
+	fn parse_atom(&self, line: &str, line_number: usize, frame: &mut Frame) -> Result<(), ParseError> {
+		let mut tokens = Tokens::new(line, line_number);
+		let mut species = "X";
+		for property in &self.0 {
+			let mut vector = |name| -> Result<Vector3, ParseError> {
+				let x = tokens.float(name)?;
+				let y = tokens.float(name)?;
+				let z = tokens.float(name)?;
+				Ok(Vector3::from(x, y, z))
+			};
+			match property.role {
+				Role::Species => species = tokens.expect("species")?.1,
+				Role::Position => frame.positions.push(vector("position")?.as_point()),
+				Role::Velocity => frame.velocities.get_or_insert_default().push(vector("velocity")?),
+				Role::Momentum => frame.momenta.get_or_insert_default().push(vector("momentum")?),
+				Role::Force => frame.forces.get_or_insert_default().push(vector("force")?),
+				Role::Charge => frame.charges.get_or_insert_default().push(tokens.float("charge")?),
+				Role::Ignored => {
+					for _ in 0..property.count {
+						tokens.expect("property")?;
+					}
+				}
+			}
+		}
+		tokens.end()?;
+
+		frame.species.push(species.to_string());
+		return Ok(());
+	}
+}
+
+/// Split the comment line of an extended XYZ file into `key=value` pairs, ignoring the words without a value.
+/// Values may be quoted to contain whitespace.
+fn key_values(line: &str, line_number: usize) -> Result<Vec<(usize, &str, &str)>, ParseError> {
+	let mut pairs = Vec::new();
+	let mut offset = 0;
+	while offset < line.len() {
+		let rest = &line[offset..];
+		let trimmed = rest.trim_start();
+		if trimmed.is_empty() {
+			break;
+		}
+		let start = offset + (rest.len() - trimmed.len());
+
+		let word_end = start + trimmed.find(|c: char| c.is_whitespace() || c == '=').unwrap_or(trimmed.len());
+		if !line[word_end..].starts_with('=') {
+			// A word of the comment without any value
+			offset = word_end;
+			continue;
+		}
+
+		let key = &line[start..word_end];
+		let value_start = word_end + 1;
+		let (value, end) = if let Some(quoted) = line[value_start..].strip_prefix('"') {
+			let length = quoted.find('"').ok_or_else(|| {
+				ParseError::new(
+					line_number,
+					line[..value_start].chars().count() + 1,
+					ParseErrorReason::UnterminatedQuote,
+				)
+			})?;
+			(&quoted[..length], value_start + length + 2)
+		} else {
+			let length = line[value_start..].find(char::is_whitespace).unwrap_or(line.len() - value_start);
+			(&line[value_start..value_start + length], value_start + length)
+		};
+
+		pairs.push((start, key, value));
+		offset = end;
+	}
+
+	return Ok(pairs);
+}
+
+/// Parse the value of a `Lattice` key into the side of the box, which must be cubic
+fn parse_lattice(value: &str) -> Result<f64, ParseErrorReason> {
+	let unsupported = |reason: String| ParseErrorReason::UnsupportedLattice(reason);
+
+	let components = value
+		.split_whitespace()
+		.map(|component| {
+			component
+				.parse::<f64>()
+				.map_err(|_| unsupported(format!("`{component}` is not a number")))
+		})
+		.collect::<Result<Vec<f64>, _>>()?;
+	if components.len() != 9 {
+		return Err(unsupported(format!("expected 9 components, found {}", components.len())));
+	}
+
+	let side = components[0];
+	for row in 0..3 {
+		for column in 0..3 {
+			let expected = if row == column { side } else { 0.0 };
+			if components[3 * row + column] != expected {
+				return Err(unsupported(format!("`{value}` is not a cubic box")));
+			}
+		}
+	}
+
+	return Ok(side);
+}
+
+/// Read a frame in the standard or extended XYZ format: the number of atoms, a comment line, then the atoms.
+/// Blank lines before the frame are skipped, and `None` is returned if there is no frame left.
+///
+/// # Arguments
+///
+/// * `lines` - The lines of the input, with their number starting at 1
+pub(crate) fn read_frame<S: AsRef<str>>(
+	lines: &mut impl Iterator<Item = Result<(usize, S), ParseError>>,
+) -> Result<Option<Frame>, ParseError> {
+	let (count_line_number, count_line) = loop {
+		match lines.next().transpose()? {
+			None => return Ok(None),
+			Some((_, line)) if line.as_ref().trim().is_empty() => continue,
+			Some(line) => break line,
+		}
+	};
+	let mut tokens = Tokens::new(count_line.as_ref(), count_line_number);
+	let expected = tokens.integer("atom count")?;
+	tokens.end()?;
+	let mismatch = |found| ParseError::new(count_line_number, 1, ParseErrorReason::AtomCountMismatch { expected, found });
+
+	// The comment line may hold the layout of the atom lines and the box
+	let Some((comment_line_number, comment)) = lines.next().transpose()? else {
+		return Err(mismatch(0));
+	};
+	let comment = comment.as_ref();
+	let mut properties = Properties::default();
+	let mut frame = Frame {
+		box_side: None,
+		info: Vec::new(),
+		species: Vec::with_capacity(expected),
+		positions: Vec::with_capacity(expected),
+		velocities: None,
+		momenta: None,
+		forces: None,
+		charges: None,
+	};
+	for (offset, key, value) in key_values(comment, comment_line_number)? {
+		let error = |reason| ParseError::new(comment_line_number, comment[..offset].chars().count() + 1, reason);
+		if key.eq_ignore_ascii_case("Properties") {
+			properties = Properties::parse(value).map_err(error)?;
+		} else if key.eq_ignore_ascii_case("Lattice") {
+			frame.box_side = Some(parse_lattice(value).map_err(error)?);
+		} else {
+			frame.info.push((key.to_string(), value.to_string()));
+		}
+	}
+
+	for found in 0..expected {
+		let Some((line_number, line)) = lines.next().transpose()? else {
+			return Err(mismatch(found));
+		};
+		properties.parse_atom(line.as_ref(), line_number, &mut frame)?;
+	}
+
+	// Properties absent from every line are absent from the frame, so that an empty frame is consistent
+	for (role, column) in [
+		(Role::Velocity, &mut frame.velocities),
+		(Role::Momentum, &mut frame.momenta),
+		(Role::Force, &mut frame.forces),
+	] {
+		if properties.has(role) && column.is_none() {
+			*column = Some(Vec::new());
+		}
+	}
+	if properties.has(Role::Charge) && frame.charges.is_none() {
+		frame.charges = Some(Vec::new());
+	}
+
+	return Ok(Some(frame));
+}
+
+/// Parse a particle line of the course format, made of its type and its coordinates
+///
+/// # Arguments
+///
+/// * `line` - The line to parse
+/// * `line_number` - The number of the line in the file, starting at 1
+pub(crate) fn parse_course_line(line: &str, line_number: usize) -> Result<(usize, Point3), ParseError> {
+	let mut tokens = Tokens::new(line, line_number);
+	let particle_type = tokens.integer("particle type")?;
+	let x = tokens.float("x coordinate")?;
+	let y = tokens.float("y coordinate")?;
+	let z = tokens.float("z coordinate")?;
+	tokens.end()?;
+
+	return Ok((particle_type, Point3::from(x, y, z)));
+}
+
+/// Read a file in the course format: a header line, then one particle per line until the end of the input
+///
+/// # Arguments
+///
+/// * `lines` - The lines of the input after the header, with their number starting at 1
+pub(crate) fn read_course_frame<'a>(lines: impl Iterator<Item = (usize, &'a str)>) -> Result<Frame, ParseError> {
+	let mut frame = Frame {
+		box_side: None,
+		info: Vec::new(),
+		species: Vec::new(),
+		positions: Vec::new(),
+		velocities: None,
+		momenta: None,
+		forces: None,
+		charges: None,
+	};
+	for (line_number, line) in lines {
+		if line.trim().is_empty() {
+			continue;
+		}
+		let (particle_type, position) = parse_course_line(line, line_number)?;
+		frame.species.push(particle_type.to_string());
+		frame.positions.push(position);
+	}
+
+	return Ok(frame);
+}
+
+/// The number of atoms announced by the header line, if it is a standard XYZ header (a single integer).
+/// The header of the course format holds two integers instead, and is not followed by a comment line.
+pub(crate) fn header_atom_count(header: &str) -> Option<usize> {
+	let mut tokens = header.split_whitespace();
+	let count = tokens.next()?.parse().ok()?;
+	return tokens.next().is_none().then_some(count);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn tokens_have_columns() {
+		let mut tokens = Tokens::new("  2    3.5 \tfoo", 7);
+		assert_eq!(tokens.next_token(), Some((2, "2")));
+		assert_eq!(tokens.next_token(), Some((7, "3.5")));
+		let error = tokens.float("z").unwrap_err();
+		assert_eq!((error.line(), error.column()), (7, 13));
+		assert!(tokens.next_token().is_none());
+		let error = tokens.expect("x").unwrap_err();
+		assert_eq!(error.column(), 16);
+	}
+
+	fn read(s: &str) -> Result<Option<Frame>, ParseError> {
+		read_frame(&mut s.lines().enumerate().map(|(index, line)| Ok((index + 1, line))))
+	}
+
+	#[test]
+	fn extended_xyz() {
+		let frame = read(concat!(
+			"2\n",
+			"Lattice=\"30 0 0 0 30 0 0 0 30\" Properties=species:S:1:pos:R:3:Z:I:1:velo:R:3 Time=1.5 pbc=\"T T T\"\n",
+			"Ar 1.0 2.0 3.0 18 0.1 0.2 0.3\n",
+			"Kr -1.0 -2.0 -3.0 36 -0.1 -0.2 -0.3\n",
+		))
+		.unwrap()
+		.unwrap();
+
+		assert_eq!(frame.nb_atoms(), 2);
+		assert_eq!(frame.box_side(), Some(30.0));
+		assert_eq!(frame.info("time"), Some("1.5"));
+		assert_eq!(frame.info("pbc"), Some("T T T"));
+		assert_eq!(frame.species(), ["Ar", "Kr"]);
+		assert_eq!(frame.positions()[1], Point3::from(-1.0, -2.0, -3.0));
+		assert_eq!(frame.velocities().unwrap()[0], Vector3::from(0.1, 0.2, 0.3));
+		assert!(frame.momenta().is_none());
+	}
+
+	#[test]
+	fn charges_are_a_single_column() {
+		let frame = read("2\nProperties=species:S:1:pos:R:3:charge:R:1\nNa 0 0 0 1.0\nCl 2.8 0 0 -1.0\n")
+			.unwrap()
+			.unwrap();
+		assert_eq!(frame.charges(), Some(&[1.0, -1.0][..]));
+		assert!(read("1\nProperties=species:S:1:pos:R:3:charge:R:3\nNa 0 0 0 1 1 1\n").is_err());
+	}
+
+	#[test]
+	fn standard_xyz_with_free_comment() {
+		let frame = read("1\nwater molecule, sort of\nO 0.0 0.5 1.0\n").unwrap().unwrap();
+		assert_eq!(frame.species(), ["O"]);
+		assert_eq!(frame.box_side(), None);
+		assert!(read("\n\n").unwrap().is_none());
+	}
+
+	#[test]
+	fn malformed_extended_xyz() {
+		let error = read("1\nLattice=\"30 0 0 0 20 0 0 0 30\"\nO 0 0 0").unwrap_err();
+		assert!(matches!(error.reason(), ParseErrorReason::UnsupportedLattice(_)));
+		assert_eq!((error.line(), error.column()), (2, 1));
+
+		let error = read("1\na=1 Properties=species:S:1:pos:R:2\nO 0 0").unwrap_err();
+		assert!(matches!(error.reason(), ParseErrorReason::InvalidProperties(_)));
+		assert_eq!((error.line(), error.column()), (2, 5));
+
+		let error = read("1\nLattice=\"30 0 0\nO 0 0 0").unwrap_err();
+		assert!(matches!(error.reason(), ParseErrorReason::UnterminatedQuote));
+
+		let error = read("3\ncomment\nO 0 0 0\nH 1 0 0").unwrap_err();
+		assert!(matches!(
+			error.reason(),
+			ParseErrorReason::AtomCountMismatch { expected: 3, found: 2 }
+		));
+	}
+
+	#[test]
+	fn header_atom_count_only_for_standard_xyz() {
+		assert_eq!(header_atom_count(" 1000 "), Some(1000));
+		assert_eq!(header_atom_count(" 0 1"), None);
+		assert_eq!(header_atom_count("comment"), None);
+	}
+}
@@ -0,0 +1,212 @@
+use std::io::Cursor;
+use std::path::Path;
+
+use mlom::checkpoint::{CheckpointError, Checkpointer};
+use mlom::integrator::Integration;
+use mlom::parameters::{PairSearch, SimulationParameters};
+use mlom::system::System;
+use mlom::topology::{Bond, Topology};
+use mlom::trajectory::{TrajectoryReader, TrajectoryWriter};
+
+#[test]
+fn split_run_is_identical_to_continuous_run() {
+	// Leapfrog also keeps whether its momenta are half a step behind the positions
+	for integrator in [Integration::VelocityVerlet, Integration::Leapfrog] {
+		let parameters = SimulationParameters {
+			integrator,
+			..Default::default()
+		};
+		let initial = System::from_file_with_parameters(Path::new("dataset/3_particles.xyz"), 0, parameters).unwrap();
+
+		let mut continuous = initial.clone();
+		for _ in 0..6 {
+			continuous.step();
+		}
+
+		let mut first_half = initial.clone();
+		for _ in 0..3 {
+			first_half.step();
+		}
+		let mut checkpoint = Vec::new();
+		first_half.write_checkpoint(&mut checkpoint).unwrap();
+		drop(first_half);
+
+		let mut resumed = System::read_checkpoint(&mut checkpoint.as_slice()).unwrap();
+		assert_eq!(resumed.step_count(), 3);
+		for _ in 0..3 {
+			resumed.step();
+		}
+
+		assert_eq!(resumed, continuous);
+
+		// The random number generator is restored too
+		continuous.init_particles_momentums();
+		resumed.init_particles_momentums();
+		assert_eq!(resumed.particles(), continuous.particles());
+	}
+}
+
+#[test]
+fn topology_is_restored() {
+	let mut system = System::from_file(Path::new("dataset/3_particles.xyz"), 0).unwrap();
+	let topology = Topology {
+		bonds: vec![Bond {
+			particles: [0, 2],
+			stiffness: 100.0,
+			length: 4.0,
+		}],
+		extra_exclusions: vec![[1, 2]],
+		..Default::default()
+	};
+	system.set_topology(topology).unwrap();
+
+	let mut checkpoint = Vec::new();
+	system.write_checkpoint(&mut checkpoint).unwrap();
+	let restored = System::read_checkpoint(&mut checkpoint.as_slice()).unwrap();
+	assert_eq!(restored.topology(), system.topology());
+	assert!(restored.exclusions().contains(2, 1));
+	assert_eq!(restored.evaluate_forces(), system.evaluate_forces());
+}
+
+#[test]
+fn tabulated_potential_is_restored_without_its_file() {
+	let mut table = String::from("# r U F\n");
+	for k in 0..=1000 {
+		let r = 2.0 + k as f64 * 0.01;
+		table += &format!("{r} {} {}\n", 10.0 / r.powi(6), 60.0 / r.powi(7));
+	}
+	let path = std::env::temp_dir().join(format!("mlom_checkpoint_{}.table", std::process::id()));
+	std::fs::write(&path, table).unwrap();
+	let toml = format!("[potential]\nkind = \"tabulated\"\nfile = {:?}", path.display().to_string());
+	let parameters = SimulationParameters::from_toml(&toml).unwrap();
+	let system = System::from_file_with_parameters(Path::new("dataset/3_particles.xyz"), 0, parameters).unwrap();
+
+	let mut checkpoint = Vec::new();
+	system.write_checkpoint(&mut checkpoint).unwrap();
+	std::fs::remove_file(&path).unwrap();
+	let restored = System::read_checkpoint(&mut checkpoint.as_slice()).unwrap();
+	assert_eq!(restored.parameters(), system.parameters());
+	assert_eq!(restored.evaluate_forces(), system.evaluate_forces());
+}
+
+#[test]
+fn verlet_list_is_rebuilt_without_changing_the_run() {
+	let parameters = SimulationParameters {
+		pair_search: PairSearch::VerletList,
+		seed: Some(3),
+		..Default::default()
+	};
+	let initial = System::from_file_with_parameters(Path::new("dataset/particles.xyz"), 0, parameters).unwrap();
+
+	let mut continuous = initial.clone();
+	for _ in 0..4 {
+		continuous.step();
+	}
+
+	let mut first_half = initial;
+	for _ in 0..2 {
+		first_half.step();
+	}
+	let mut checkpoint = Vec::new();
+	first_half.write_checkpoint(&mut checkpoint).unwrap();
+
+	// The neighbours are listed in the same order whenever the list was built, so the sums are identical
+	let mut resumed = System::read_checkpoint(&mut checkpoint.as_slice()).unwrap();
+	for _ in 0..2 {
+		resumed.step();
+	}
+	assert_eq!(resumed.particles(), continuous.particles());
+}
+
+#[test]
+fn periodic_checkpoints_can_be_restored() {
+	let path = std::env::temp_dir().join(format!("mlom_checkpoint_{}.bin", std::process::id()));
+	let checkpointer = Checkpointer::new(&path, 2);
+
+	let mut system = System::from_file(Path::new("dataset/3_particles.xyz"), 0).unwrap();
+	let mut saved = vec![];
+	for _ in 0..5 {
+		system.step();
+		checkpointer.record(&system).unwrap();
+		if system.step_count().is_multiple_of(2) {
+			saved.push(system.clone());
+		}
+	}
+
+	let restored = System::restore(&path).unwrap();
+	std::fs::remove_file(&path).unwrap();
+	assert_eq!(&restored, saved.last().unwrap());
+}
+
+#[test]
+fn invalid_checkpoints_are_rejected() {
+	let system = System::from_file(Path::new("dataset/3_particles.xyz"), 0).unwrap();
+	let mut checkpoint = Vec::new();
+	system.write_checkpoint(&mut checkpoint).unwrap();
+
+	let truncated = &checkpoint[..checkpoint.len() - 4];
+	assert!(matches!(System::read_checkpoint(&mut &truncated[..]), Err(CheckpointError::Io(_))));
+
+	let mut wrong_version = checkpoint.clone();
+	wrong_version[8] = 42;
+	assert!(matches!(
+		System::read_checkpoint(&mut wrong_version.as_slice()),
+		Err(CheckpointError::UnsupportedVersion(42))
+	));
+
+	assert!(matches!(
+		System::read_checkpoint(&mut "not a checkpoint".as_bytes()),
+		Err(CheckpointError::NotACheckpoint)
+	));
+}
+
+#[test]
+fn runs_are_checkpointed_periodically() {
+	let directory = std::env::temp_dir();
+	let path = directory.join(format!("mlom_run_checkpoint_{}.bin", std::process::id()));
+	let plot = directory.join(format!("mlom_run_energy_{}.png", std::process::id()));
+	let checkpointer = Checkpointer::new(&path, 3);
+
+	let initial = System::from_file(Path::new("dataset/3_particles.xyz"), 0).unwrap();
+	let mut system = initial.clone();
+	system.energy_evolution(4, plot.to_str().unwrap(), None, Some(&checkpointer))
+		.unwrap();
+
+	// The last checkpoint is the one of step 3
+	let restored = System::restore(&path).unwrap();
+	std::fs::remove_file(&path).unwrap();
+	std::fs::remove_file(&plot).unwrap();
+	let mut expected = initial;
+	for _ in 0..3 {
+		expected.step();
+	}
+	assert_eq!(restored, expected);
+}
+
+#[test]
+fn resumed_trajectory_continues_the_steps() {
+	let directory = std::env::temp_dir();
+	let path = directory.join(format!("mlom_resumed_checkpoint_{}.bin", std::process::id()));
+	let plot = directory.join(format!("mlom_resumed_energy_{}.png", std::process::id()));
+	let mut system = System::from_file(Path::new("dataset/3_particles.xyz"), 0).unwrap();
+	for _ in 0..3 {
+		system.step();
+	}
+	system.save_checkpoint(&path).unwrap();
+
+	// The run resumes at step 3, which is not one of the frames
+	let mut resumed = System::restore(&path).unwrap();
+	std::fs::remove_file(&path).unwrap();
+	let mut trajectory = TrajectoryWriter::new(Vec::new(), 2);
+	resumed.energy_evolution(4, plot.to_str().unwrap(), Some(&mut trajectory), None)
+		.unwrap();
+	std::fs::remove_file(&plot).unwrap();
+
+	let frames: Vec<_> = TrajectoryReader::new(Cursor::new(trajectory.into_inner()))
+		.collect::<Result<_, _>>()
+		.unwrap();
+	let steps: Vec<_> = frames.iter().map(|frame| frame.step()).collect();
+	assert_eq!(steps, [Some(4), Some(6)]);
+	let time: f64 = frames[0].info("Time").unwrap().parse().unwrap();
+	assert_eq!(time, 4.0 * system.parameters().delta_time);
+}
@@ -0,0 +1,82 @@
+#![cfg(feature = "parallel")]
+
+use std::path::Path;
+
+use mlom::assert_approx_eq;
+use mlom::parameters::{PairSearch, SimulationParameters};
+use mlom::system::System;
+
+/// Run the given function on a pool with the given number of threads
+fn with_threads<T: Send>(nb_threads: usize, f: impl FnOnce() -> T + Send) -> T {
+	rayon::ThreadPoolBuilder::new().num_threads(nb_threads).build().unwrap().install(f)
+}
+
+#[test]
+fn results_do_not_depend_on_the_number_of_threads() {
+	for pair_search in [
+		PairSearch::Images,
+		PairSearch::MinimumImage,
+		PairSearch::Vectorized,
+		PairSearch::CellList,
+		PairSearch::VerletList,
+	] {
+		let parameters = SimulationParameters {
+			pair_search,
+			seed: Some(11),
+			..Default::default()
+		};
+		let mut system = System::from_file_with_parameters(Path::new("dataset/particles.xyz"), 0, parameters).unwrap();
+		system.step();
+
+		let reference = with_threads(1, || {
+			(
+				system.evaluate_forces_parallel(&system.potential()),
+				system.minimum_pair_distance_parallel(),
+			)
+		});
+		for nb_threads in [2, 3, 8] {
+			let parallel = with_threads(nb_threads, || {
+				(
+					system.evaluate_forces_parallel(&system.potential()),
+					system.minimum_pair_distance_parallel(),
+				)
+			});
+			assert_eq!(parallel, reference);
+		}
+	}
+}
+
+#[test]
+fn parallel_evaluation_is_equivalent_to_serial() {
+	for pair_search in [
+		PairSearch::Images,
+		PairSearch::MinimumImage,
+		PairSearch::Vectorized,
+		PairSearch::CellList,
+		PairSearch::VerletList,
+	] {
+		let parameters = SimulationParameters {
+			pair_search,
+			..Default::default()
+		};
+		let system = System::from_file_with_parameters(Path::new("dataset/particles.xyz"), 0, parameters).unwrap();
+
+		let serial = system.evaluate_forces_serial(&system.potential());
+		let parallel = system.evaluate_forces_parallel(&system.potential());
+		assert_approx_eq!(parallel.energy, serial.energy);
+		assert_approx_eq!(parallel.virial, serial.virial);
+		for (expected, actual) in serial.gradients.iter().zip(&parallel.gradients) {
+			assert_approx_eq!(expected.x(), actual.x());
+			assert_approx_eq!(expected.y(), actual.y());
+			assert_approx_eq!(expected.z(), actual.z());
+		}
+
+		let (serial_distance, serial_pair) = system.minimum_pair_distance_serial();
+		let (parallel_distance, parallel_pair) = system.minimum_pair_distance_parallel();
+		assert_approx_eq!(parallel_distance, serial_distance);
+		assert_eq!(
+			(parallel_pair.0.min(parallel_pair.1), parallel_pair.0.max(parallel_pair.1)),
+			(serial_pair.0.min(serial_pair.1), serial_pair.0.max(serial_pair.1))
+		);
+	}
+}
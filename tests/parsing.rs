@@ -0,0 +1,156 @@
+use std::{fs, path::Path};
+
+use mlom::algebra::Vector3;
+use mlom::parameters::SimulationParameters;
+use mlom::system::System;
+use mlom::xyz::ParseErrorReason;
+
+fn dataset() -> String {
+	fs::read_to_string("dataset/particles.xyz").unwrap()
+}
+
+#[test]
+fn dataset_parses() {
+	let system = System::from_str(&dataset(), 0).unwrap();
+	assert_eq!(system.nb_particles_total(), 1000);
+}
+
+#[test]
+fn truncated_file_reports_missing_coordinate() {
+	// Cut the file in the middle of the 501st line, right after the y coordinate
+	let contents = dataset();
+	let line = contents.lines().nth(500).unwrap();
+	let kept = &line[..line.rfind(' ').unwrap()];
+	let truncated = &contents[..contents.find(line).unwrap() + kept.len()];
+
+	let error = System::from_str(truncated, 0).unwrap_err();
+	assert!(matches!(error.reason(), ParseErrorReason::MissingField("z coordinate")));
+	assert_eq!(error.line(), 501);
+	assert_eq!(error.column(), kept.trim_end().len() + 1);
+}
+
+#[test]
+fn corrupted_float_reports_its_location() {
+	let contents = dataset().replacen("-14.66441", "-14.6.441", 1);
+
+	let error = System::from_str(&contents, 0).unwrap_err();
+	let ParseErrorReason::InvalidFloat { field, token } = error.reason() else {
+		panic!("unexpected error: {error}");
+	};
+	assert_eq!(*field, "y coordinate");
+	assert_eq!(token, "-14.6.441");
+	assert_eq!(error.line(), 3);
+	assert_eq!(error.column(), 21);
+}
+
+#[test]
+fn corrupted_type_and_merged_lines_are_rejected() {
+	let contents = dataset().replacen("  2       8.85807", "  x       8.85807", 1);
+	let error = System::from_str(&contents, 0).unwrap_err();
+	assert!(matches!(error.reason(), ParseErrorReason::InvalidInteger { .. }));
+	assert_eq!((error.line(), error.column()), (2, 3));
+
+	// Two particles on the same line
+	let contents = dataset().replacen("10.13694\n", "10.13694 ", 1);
+	let error = System::from_str(&contents, 0).unwrap_err();
+	assert!(matches!(error.reason(), ParseErrorReason::UnexpectedToken(token) if token == "2"));
+	assert_eq!(error.line(), 2);
+}
+
+#[test]
+fn wrong_atom_count_in_header() {
+	// Standard XYZ header: atom count, then a comment line
+	let dataset = dataset();
+	let particles: Vec<&str> = dataset.lines().skip(1).collect();
+	let contents = format!("999\ncomment\n{}\n", particles.join("\n"));
+
+	let error = System::from_str(&contents, 0).unwrap_err();
+	assert!(matches!(
+		error.reason(),
+		ParseErrorReason::AtomCountMismatch {
+			expected: 999,
+			found: 1000
+		}
+	));
+	assert_eq!(error.line(), 1);
+
+	let contents = format!("1000\ncomment\n{}\n", particles.join("\n"));
+	assert_eq!(System::from_str(&contents, 0).unwrap().nb_particles_total(), 1000);
+}
+
+#[test]
+fn too_many_local_particles() {
+	let error = System::from_str(&dataset(), 1000).unwrap_err();
+	assert!(matches!(error.reason(), ParseErrorReason::TooManyLocalParticles { .. }));
+}
+
+#[test]
+fn file_errors_report_the_file() {
+	let path = std::env::temp_dir().join(format!("mlom_corrupted_{}.xyz", std::process::id()));
+	fs::write(&path, dataset().replacen("5.38523", "5.38523e", 1)).unwrap();
+	let error = System::from_file(&path, 0).unwrap_err();
+	fs::remove_file(&path).unwrap();
+
+	assert_eq!(error.file(), Some(path.as_path()));
+	assert_eq!(error.line(), 3);
+	assert!(error.to_string().starts_with(&format!("{}:3:", path.display())));
+
+	let error = System::from_file(Path::new("dataset/does_not_exist.xyz"), 0).unwrap_err();
+	assert!(matches!(error.reason(), ParseErrorReason::Io(_)));
+}
+
+#[test]
+fn extended_xyz_gives_box_velocities_and_species() {
+	let contents = concat!(
+		"3\n",
+		"Lattice=\"30.0 0.0 0.0 0.0 30.0 0.0 0.0 0.0 30.0\" Properties=species:S:1:pos:R:3:velo:R:3\n",
+		"O   0.0 0.0 0.0   0.01 0.0 0.0\n",
+		"H   0.9 0.0 0.0   0.0 0.02 0.0\n",
+		"H  -0.3 0.9 0.0   0.0 0.0 -0.03\n",
+	);
+	let system = System::from_str(contents, 0).unwrap();
+
+	assert_eq!(system.parameters().box_side, 30.0);
+	assert_eq!(system.species(), ["O", "H"]);
+	let species: Vec<usize> = system.particles().iter().map(|particle| particle.species()).collect();
+	assert_eq!(species, [0, 1, 1]);
+
+	// The momentums come from the file instead of being drawn randomly
+	let masses = system.masses();
+	let velocities = [
+		Vector3::from(0.01, 0.0, 0.0),
+		Vector3::from(0.0, 0.02, 0.0),
+		Vector3::from(0.0, 0.0, -0.03),
+	];
+	for (particle, velocity) in system.particles().iter().zip(velocities) {
+		let expected = velocity * masses[particle.species()];
+		assert!(
+			(particle.kinetic_moment() - expected).norm() < 1e-12,
+			"{:?} != {expected:?}",
+			particle.kinetic_moment()
+		);
+	}
+}
+
+#[test]
+fn extended_xyz_box_must_fit_the_cut() {
+	let contents = "1\nLattice=\"15 0 0 0 15 0 0 0 15\"\nAr 0 0 0\n";
+	let error = System::from_str(contents, 0).unwrap_err();
+	assert!(matches!(error.reason(), ParseErrorReason::InvalidParameters(_)));
+}
+
+#[test]
+fn course_types_become_species() {
+	let system = System::from_file(Path::new("dataset/3_particles.xyz"), 0).unwrap();
+	assert_eq!(system.species(), ["2"]);
+}
+
+#[test]
+fn parameters_are_validated_without_a_box_in_the_file() {
+	let parameters = SimulationParameters {
+		r_cut: 30.0,
+		..Default::default()
+	};
+	let error = System::from_file_with_parameters(Path::new("dataset/3_particles.xyz"), 0, parameters).unwrap_err();
+	assert!(matches!(error.reason(), ParseErrorReason::InvalidParameters(_)));
+}
@@ -1,13 +1,23 @@
 use std::path::Path;
 
-use mlom::parameters::{BOX_SIDE, FAR_AWAY, R_CUT};
-use mlom::periodic_conditions::neighboring_3d_translations;
+use mlom::constraints::RigidWater;
+use mlom::cutoff::Cutoff;
+use mlom::ewald::{COULOMB_CONSTANT, Electrostatics, Ewald};
+use mlom::forces::ForceEvaluation;
+use mlom::integrator::Integration;
+use mlom::parameters::{BOX_SIDE, FAR_AWAY, MomentumDistribution, PARTICLE_MASS, PairSearch, R_CUT, SimulationParameters, T_0};
+use mlom::periodic_conditions::{minimum_image, neighboring_3d_translations};
+use mlom::potential::PairPotential;
+use mlom::respa::Respa;
+use mlom::topology::{Angle, Bond, Topology};
+use mlom::trajectory::TrajectoryWriter;
 use mlom::{algebra::Vector3, system::System};
 use mlom::{assert_approx_eq, assert_vector_approx_eq};
+use rand::{SeedableRng, rngs::StdRng};
 
 #[test]
 fn sum_of_forces_is_null() {
-	let system = System::from_file(Path::new("dataset/particles.xyz"), 0);
+	let system = System::from_file(Path::new("dataset/particles.xyz"), 0).unwrap();
 
 	// Non periodic conditions
 	system.compute_forces();
@@ -23,7 +33,7 @@ fn sum_of_forces_is_null() {
 
 #[test]
 fn if_nb_sym_1_then_equivalent_to_non_periodic() {
-	let system = System::from_file(Path::new("dataset/particles.xyz"), 0);
+	let system = System::from_file(Path::new("dataset/particles.xyz"), 0).unwrap();
 	let u_lj_non_periodic = system.microscopic_energy();
 	let u_lj_periodic = system.microscopic_energy_periodic(&[Vector3::zero()], FAR_AWAY);
 	assert_approx_eq!(u_lj_non_periodic, u_lj_periodic);
@@ -31,8 +41,812 @@ fn if_nb_sym_1_then_equivalent_to_non_periodic() {
 
 #[test]
 fn if_far_away_then_equivalent_to_non_periodic() {
-	let system = System::from_file(Path::new("dataset/particles.xyz"), 0);
+	let system = System::from_file(Path::new("dataset/particles.xyz"), 0).unwrap();
 	let u_lj_non_periodic = system.microscopic_energy();
 	let u_lj_periodic = system.microscopic_energy_periodic(&neighboring_3d_translations(FAR_AWAY), FAR_AWAY);
 	assert_approx_eq!(u_lj_non_periodic, u_lj_periodic);
 }
+
+#[test]
+fn same_seed_gives_same_trajectory() {
+	let with_seed = |seed| SimulationParameters {
+		seed: Some(seed),
+		..Default::default()
+	};
+	let mut a = System::from_file_with_parameters(Path::new("dataset/3_particles.xyz"), 0, with_seed(1234)).unwrap();
+	let mut b = System::from_file_with_parameters(Path::new("dataset/3_particles.xyz"), 0, with_seed(1234)).unwrap();
+	let c = System::from_file_with_parameters(Path::new("dataset/3_particles.xyz"), 0, with_seed(4321)).unwrap();
+	assert_eq!(a.particles(), b.particles());
+	assert_ne!(a.particles(), c.particles());
+
+	for _ in 0..5 {
+		a.step();
+		b.step();
+	}
+	assert_eq!(a.particles(), b.particles());
+}
+
+#[test]
+fn momentums_can_be_drawn_from_any_rng() {
+	let mut a = System::from_file(Path::new("dataset/3_particles.xyz"), 0).unwrap();
+	let mut b = a.clone();
+	a.init_particles_momentums_with_rng(&mut StdRng::seed_from_u64(7));
+	b.init_particles_momentums_with_rng(&mut StdRng::seed_from_u64(7));
+	assert_eq!(a.particles(), b.particles());
+}
+
+/// Kurtosis of the momentum components: 3 for a Gaussian, 1.8 for a uniform distribution
+fn momentum_kurtosis(system: &System) -> f64 {
+	let components: Vec<f64> = system
+		.particles()
+		.iter()
+		.flat_map(|particle| {
+			let p = particle.kinetic_moment();
+			[p.x(), p.y(), p.z()]
+		})
+		.collect();
+	let n = components.len() as f64;
+	let variance = components.iter().map(|c| c.powi(2)).sum::<f64>() / n;
+	let fourth_moment = components.iter().map(|c| c.powi(4)).sum::<f64>() / n;
+	return fourth_moment / variance.powi(2);
+}
+
+#[test]
+fn maxwell_boltzmann_initialization() {
+	let parameters = SimulationParameters {
+		seed: Some(42),
+		momentum_distribution: MomentumDistribution::MaxwellBoltzmann,
+		..Default::default()
+	};
+	let system = System::from_file_with_parameters(Path::new("dataset/particles.xyz"), 0, parameters).unwrap();
+
+	// Recalibrated to the initial temperature, with a motionless center of mass
+	let total_momentum = system
+		.particles()
+		.iter()
+		.fold(Vector3::zero(), |total, particle| total + particle.kinetic_moment());
+	assert!(total_momentum.norm() < 1e-9);
+	assert_approx_eq!(system.kinetic_energy_and_temperature().1, T_0);
+	assert!((momentum_kurtosis(&system) - 3.0).abs() < 0.3);
+
+	// The momenta are in amu.Å/fs: each degree of liberty holds k_B T / 2, converted from kcal/mol
+	let sum_p2_over_m: f64 = system
+		.particles()
+		.iter()
+		.map(|particle| particle.kinetic_moment().norm_squared() / PARTICLE_MASS)
+		.sum();
+	let k_b_t = system.parameters().r_constant * T_0 * system.parameters().conversion_force;
+	assert_approx_eq!(sum_p2_over_m / (system.degrees_of_liberty() * k_b_t), 1.0);
+
+	let parameters = SimulationParameters {
+		seed: Some(42),
+		..Default::default()
+	};
+	let system = System::from_file_with_parameters(Path::new("dataset/particles.xyz"), 0, parameters).unwrap();
+	assert!((momentum_kurtosis(&system) - 1.8).abs() < 0.2);
+}
+
+/// The particles of the dataset, simulated with the given parameters
+fn dataset(parameters: SimulationParameters) -> System {
+	return System::from_file_with_parameters(Path::new("dataset/particles.xyz"), 0, parameters).unwrap();
+}
+
+/// Every way to find the pairs of particles in the cut
+const PAIR_SEARCHES: [PairSearch; 5] = [
+	PairSearch::Images,
+	PairSearch::MinimumImage,
+	PairSearch::Vectorized,
+	PairSearch::CellList,
+	PairSearch::VerletList,
+];
+
+/// Whether 2 sums are equal, relatively to their magnitude since the energy of some systems is large
+fn close(expected: f64, actual: f64) -> bool {
+	return (expected - actual).abs() <= 1e-9 * expected.abs().max(1.0);
+}
+
+/// Evaluate the interactions of a system with every [pair search](PAIR_SEARCHES), check that they all give the forces,
+/// the energy and the virial of the images, and return the evaluation with the images
+///
+/// # Arguments
+///
+/// * `parameters` - The parameters of the system, whose pair search is replaced
+/// * `build` - Builds the system from its parameters
+fn evaluate_with_every_pair_search(parameters: SimulationParameters, build: impl Fn(SimulationParameters) -> System) -> ForceEvaluation {
+	let evaluate = |pair_search| {
+		build(SimulationParameters {
+			pair_search,
+			..parameters.clone()
+		})
+		.evaluate_forces()
+	};
+	let images = evaluate(PairSearch::Images);
+	for pair_search in PAIR_SEARCHES.into_iter().filter(|&pair_search| pair_search != PairSearch::Images) {
+		let evaluation = evaluate(pair_search);
+		assert!(
+			close(images.energy, evaluation.energy),
+			"{pair_search:?}: {} != {}",
+			evaluation.energy,
+			images.energy
+		);
+		assert!(
+			close(images.virial, evaluation.virial),
+			"{pair_search:?}: {} != {}",
+			evaluation.virial,
+			images.virial
+		);
+		for (expected, actual) in images.gradients.iter().zip(&evaluation.gradients) {
+			assert!((*actual - *expected).norm() <= 1e-9 * expected.norm().max(1.0), "{pair_search:?}");
+		}
+	}
+	return images;
+}
+
+/// Check that an evaluation gives the energy and the forces of the pair matrices with the images, for the cut of the system
+fn assert_equivalent_to_pair_matrices(system: &System, evaluation: &ForceEvaluation) {
+	let translations = neighboring_3d_translations(BOX_SIDE);
+	let r_cut = system.parameters().r_cut;
+	let energy = system.microscopic_energy_periodic(&translations, r_cut);
+	let forces = System::forces_applied_to_particles(&system.compute_forces_periodic(&translations, r_cut));
+	assert!(close(energy, evaluation.energy), "{} != {energy}", evaluation.energy);
+	for (expected, actual) in forces.iter().zip(&evaluation.gradients) {
+		assert!((*actual - *expected).norm() <= 1e-9 * expected.norm().max(1.0));
+	}
+}
+
+#[test]
+fn every_pair_search_is_equivalent_to_pair_matrices() {
+	for r_cut in [5.0, R_CUT, 20.0] {
+		let parameters = SimulationParameters {
+			r_cut,
+			..Default::default()
+		};
+		let evaluation = evaluate_with_every_pair_search(parameters.clone(), dataset);
+		assert_equivalent_to_pair_matrices(&dataset(parameters), &evaluation);
+	}
+}
+
+/// The same system, with the particles at the same positions, but with another pair search
+fn with_pair_search(system: &System, pair_search: PairSearch) -> System {
+	let mut frame = TrajectoryWriter::new(Vec::new(), 1);
+	frame.write_frame(system, 0).unwrap();
+	let xyz = String::from_utf8(frame.into_inner()).unwrap();
+	let parameters = SimulationParameters {
+		pair_search,
+		..system.parameters().clone()
+	};
+	return System::from_str_with_parameters(&xyz, 0, parameters).unwrap();
+}
+
+#[test]
+fn verlet_list_is_equivalent_to_cell_list() {
+	let parameters = SimulationParameters {
+		pair_search: PairSearch::VerletList,
+		seed: Some(7),
+		// At 300 K the particles move by less than 0.3 Å during the run, within half of the default skin, so the list
+		// would never be rebuilt
+		verlet_skin: 0.1,
+		..Default::default()
+	};
+	let mut system = System::from_file_with_parameters(Path::new("dataset/particles.xyz"), 0, parameters).unwrap();
+
+	for _ in 0..20 {
+		system.step();
+		system.update_verlet_list();
+
+		let cell_list = with_pair_search(&system, PairSearch::CellList).evaluate_forces();
+		assert!(close(cell_list.energy, system.potential_energy()));
+		for (expected, actual) in cell_list.forces().iter().zip(&system.forces_per_particle()) {
+			assert!((*actual - *expected).norm() <= 1e-9 * expected.norm().max(1.0));
+		}
+	}
+
+	// The list is reused across steps, but still rebuilt when the particles moved
+	let statistics = system.verlet_list_statistics().unwrap();
+	assert!(statistics.nb_builds > 1);
+	assert!(statistics.nb_builds < statistics.nb_updates);
+	assert!(statistics.max_displacement <= system.parameters().verlet_skin / 2.0);
+}
+
+/// A soft repulsion, U(r) = k (σ - r)² below σ, to check that any potential can be plugged into the routines
+struct SoftSpheres {
+	k: f64,
+	sigma: f64,
+}
+
+impl PairPotential for SoftSpheres {
+	fn energy(&self, distance_squared: f64) -> f64 {
+		let overlap = (self.sigma - distance_squared.sqrt()).max(0.0);
+		return self.k * overlap * overlap;
+	}
+
+	fn force(&self, distance_squared: f64) -> f64 {
+		let distance = distance_squared.sqrt();
+		return 2.0 * self.k * (self.sigma - distance).max(0.0) / distance;
+	}
+}
+
+#[test]
+fn custom_potential_is_used_by_every_routine() {
+	let potential = SoftSpheres { k: 0.5, sigma: 6.0 };
+	let system = System::from_file(Path::new("dataset/particles.xyz"), 0).unwrap();
+	let translations = neighboring_3d_translations(BOX_SIDE);
+
+	let energy = system.microscopic_energy_periodic_with(&potential, &translations, R_CUT);
+	let forces = System::forces_applied_to_particles(&system.compute_forces_periodic_with(&potential, &translations, R_CUT));
+	assert!(energy > 0.0);
+	assert!(energy != system.microscopic_energy_periodic(&translations, R_CUT));
+
+	let evaluation = system.evaluate_forces_with(&potential);
+	assert_approx_eq!(evaluation.energy, energy);
+	for (expected, actual) in forces.iter().zip(&evaluation.gradients) {
+		assert_approx_eq!(expected.x(), actual.x());
+		assert_approx_eq!(expected.y(), actual.y());
+		assert_approx_eq!(expected.z(), actual.z());
+	}
+
+	// Without periodic conditions
+	let forces = System::sum_of_forces(&system.compute_forces_with(&potential));
+	assert!(forces.norm() < 1e-5, "{forces:?}");
+	assert!(system.microscopic_energy_with(&potential) > 0.0);
+}
+
+#[test]
+fn tabulated_potential_is_chosen_in_the_parameters() {
+	// A fine table of the default Lennard-Jones potential
+	let lennard_jones = System::from_file(Path::new("dataset/particles.xyz"), 0).unwrap();
+	let potential = lennard_jones.potential();
+	let mut table = String::from("# r U F\n");
+	for k in 0..=5000 {
+		let r = 1.5 + k as f64 * 0.002;
+		table += &format!("{r} {} {}\n", potential.energy(r * r), potential.force(r * r) * r);
+	}
+	let path = std::env::temp_dir().join(format!("mlom_lennard_jones_{}.table", std::process::id()));
+	std::fs::write(&path, &table).unwrap();
+
+	let toml = format!("[potential]\nkind = \"tabulated\"\nfile = {:?}", path.display().to_string());
+	let parameters = SimulationParameters::from_toml(&toml).unwrap();
+	let tabulated = System::from_file_with_parameters(Path::new("dataset/particles.xyz"), 0, parameters).unwrap();
+	std::fs::remove_file(&path).unwrap();
+
+	let expected = lennard_jones.evaluate_forces();
+	let actual = tabulated.evaluate_forces();
+	assert!((actual.energy - expected.energy).abs() <= 1e-6 * expected.energy.abs());
+	assert!((actual.virial - expected.virial).abs() <= 1e-6 * expected.virial.abs());
+	for (expected, actual) in expected.gradients.iter().zip(&actual.gradients) {
+		assert!((*actual - *expected).norm() <= 1e-6 * expected.norm().max(1.0));
+	}
+
+	let missing = "[potential]\nkind = \"tabulated\"\nfile = \"missing.table\"";
+	assert!(SimulationParameters::from_toml(missing).is_err());
+
+	// In a parameters file, a relative table is found next to it rather than in the working directory
+	let directory = std::env::temp_dir().join(format!("mlom_relative_table_{}", std::process::id()));
+	std::fs::create_dir_all(&directory).unwrap();
+	std::fs::write(directory.join("lennard_jones.table"), table).unwrap();
+	for (name, contents) in [
+		(
+			"parameters.toml",
+			"[potential]\nkind = \"tabulated\"\nfile = \"lennard_jones.table\"",
+		),
+		(
+			"parameters.json",
+			r#"{"potential": {"kind": "tabulated", "file": "lennard_jones.table"}}"#,
+		),
+	] {
+		std::fs::write(directory.join(name), contents).unwrap();
+		let parameters = SimulationParameters::from_file(&directory.join(name)).unwrap();
+		let relative = System::from_file_with_parameters(Path::new("dataset/particles.xyz"), 0, parameters).unwrap();
+		assert_eq!(relative.evaluate_forces().energy, actual.energy);
+	}
+	std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn cutoff_treatment_is_used_by_every_routine() {
+	let truncated = dataset(SimulationParameters::default()).evaluate_forces();
+	for cutoff in [Cutoff::Shifted, Cutoff::ShiftedForce, Cutoff::Switched { r_switch: 8.0 }] {
+		let parameters = SimulationParameters {
+			cutoff,
+			..Default::default()
+		};
+		let evaluation = evaluate_with_every_pair_search(parameters.clone(), dataset);
+		assert_equivalent_to_pair_matrices(&dataset(parameters), &evaluation);
+		assert!((evaluation.energy - truncated.energy).abs() > 1e-3);
+	}
+}
+
+#[test]
+fn tail_corrections_are_reported_apart() {
+	let system = System::from_file(Path::new("dataset/particles.xyz"), 0).unwrap();
+	let breakdown = system.energy_breakdown();
+	assert_eq!(breakdown.tail_energy, 0.0);
+	assert_eq!(breakdown.tail_pressure, 0.0);
+	assert_approx_eq!(breakdown.total_energy(), breakdown.kinetic_energy + breakdown.potential_energy);
+
+	let parameters = SimulationParameters {
+		tail_corrections: true,
+		..Default::default()
+	};
+	let corrected = System::from_file_with_parameters(Path::new("dataset/particles.xyz"), 0, parameters).unwrap();
+	let corrected_breakdown = corrected.energy_breakdown();
+	assert!(corrected_breakdown.tail_energy < 0.0);
+	assert!(corrected_breakdown.tail_pressure < 0.0);
+	assert_approx_eq!(corrected_breakdown.potential_energy, breakdown.potential_energy);
+	assert_approx_eq!(corrected_breakdown.pressure, breakdown.pressure);
+	assert_approx_eq!(
+		corrected_breakdown.total_energy(),
+		breakdown.total_energy() + corrected_breakdown.tail_energy
+	);
+	assert_approx_eq!(corrected.total_energy(), corrected_breakdown.total_energy());
+}
+
+/// The particles of the dataset in the standard XYZ format, the first 80% of species A and the others of species B
+fn binary_mixture(parameters: SimulationParameters) -> System {
+	let contents = std::fs::read_to_string("dataset/particles.xyz").unwrap();
+	let particles: Vec<&str> = contents.lines().skip(1).collect();
+	let mut xyz = format!("{}\nbinary mixture\n", particles.len());
+	for (i, line) in particles.iter().enumerate() {
+		let species = if i < particles.len() * 4 / 5 { "A" } else { "B" };
+		xyz += &format!("{species} {}\n", line.split_whitespace().skip(1).collect::<Vec<_>>().join(" "));
+	}
+	return System::from_str_with_parameters(&xyz, 0, parameters).unwrap();
+}
+
+#[test]
+fn kob_andersen_mixture_uses_the_parameters_of_each_pair() {
+	// The Kob-Andersen mixture, with r* = 2^(1/6) σ and the depth of the course 4ε
+	let r_star = |sigma: f64| 2f64.powf(1.0 / 6.0) * sigma * 3.0;
+	let toml = format!(
+		concat!(
+			"seed = 1\n",
+			"[species.A]\nmass = 18.0\nr_star = {}\nepsilon_star = 0.2\n",
+			"[species.B]\nmass = 9.0\nr_star = {}\nepsilon_star = 0.1\n",
+			"[[pair_overrides]]\nspecies = [\"A\", \"B\"]\nr_star = {}\nepsilon_star = 0.3\n",
+		),
+		r_star(1.0),
+		r_star(0.88),
+		r_star(0.8)
+	);
+	let mixture = binary_mixture(SimulationParameters::from_toml(&toml).unwrap());
+	assert_eq!(mixture.species(), ["A", "B"]);
+	assert_eq!(mixture.masses(), [18.0, 9.0]);
+	assert_approx_eq!(mixture.kinetic_energy_and_temperature().1, T_0);
+
+	let evaluation = evaluate_with_every_pair_search(SimulationParameters::from_toml(&toml).unwrap(), binary_mixture);
+	assert_equivalent_to_pair_matrices(&mixture, &evaluation);
+	let single_species = dataset(SimulationParameters::default());
+	assert!((evaluation.energy - single_species.evaluate_forces().energy).abs() > 1.0);
+}
+
+#[test]
+fn identical_species_are_a_single_species() {
+	let mut parameters = SimulationParameters {
+		mixing_rule: mlom::species::MixingRule::Geometric,
+		..Default::default()
+	};
+	parameters.species.insert("B".to_string(), Default::default());
+	let mixture = binary_mixture(parameters);
+	let single_species = System::from_file(Path::new("dataset/particles.xyz"), 0).unwrap();
+	assert!(mixture.lennard_jones_pairs().is_some());
+	assert_approx_eq!(mixture.evaluate_forces().energy, single_species.evaluate_forces().energy);
+}
+
+/// A crystal of sodium chloride, made of 2x2x2 cubic cells of side `a`, with the charges given by the species
+fn rock_salt(a: f64, parameters: SimulationParameters) -> System {
+	let box_side = 2.0 * a;
+	let mut xyz = format!("64\nLattice=\"{box_side} 0 0 0 {box_side} 0 0 0 {box_side}\"\n");
+	for x in 0..4 {
+		for y in 0..4 {
+			for z in 0..4 {
+				let species = if (x + y + z) % 2 == 0 { "Na" } else { "Cl" };
+				let coordinate = |n: i32| n as f64 * a / 2.0 - box_side / 2.0;
+				xyz += &format!("{species} {} {} {}\n", coordinate(x), coordinate(y), coordinate(z));
+			}
+		}
+	}
+	return System::from_str_with_parameters(&xyz, 0, parameters).unwrap();
+}
+
+/// Check that the electrostatic energy of a crystal of sodium chloride is given by its Madelung constant
+///
+/// # Arguments
+///
+/// * `electrostatics` - The kind of the electrostatics in the parameters
+fn assert_madelung_constant_of_sodium_chloride(electrostatics: &str) {
+	const MADELUNG_CONSTANT: f64 = 1.747564594633;
+	let a = 5.64;
+	let parameters = SimulationParameters::from_toml(&format!(
+		concat!(
+			"r_cut = 5.0\n",
+			"[species.Na]\ncharge = 1.0\n",
+			"[species.Cl]\ncharge = -1.0\n",
+			"[electrostatics]\nkind = \"{}\"\naccuracy = 1e-8\n",
+		),
+		electrostatics
+	))
+	.unwrap();
+	let crystal = rock_salt(a, parameters);
+	assert_eq!(crystal.particles()[1].charge(), -1.0);
+
+	let evaluation = crystal.evaluate_electrostatics().unwrap();
+	let energy_per_pair = evaluation.energy / 32.0;
+	let expected = -COULOMB_CONSTANT * MADELUNG_CONSTANT / (a / 2.0);
+	assert!(
+		(energy_per_pair - expected).abs() < 1e-6 * expected.abs(),
+		"{electrostatics}: {energy_per_pair} != {expected}"
+	);
+
+	// Every ion is a center of symmetry of the crystal
+	for force in &evaluation.gradients {
+		assert!(force.norm() < 1e-6);
+	}
+}
+
+#[test]
+fn ewald_summation_gives_the_madelung_constant_of_sodium_chloride() {
+	assert_madelung_constant_of_sodium_chloride("ewald");
+}
+
+#[test]
+fn particle_mesh_ewald_gives_the_madelung_constant_of_sodium_chloride() {
+	assert_madelung_constant_of_sodium_chloride("particle_mesh_ewald");
+}
+
+#[test]
+fn electrostatics_are_added_to_the_pair_forces() {
+	let parameters = SimulationParameters::from_toml(concat!(
+		"r_cut = 5.0\n",
+		"[species.Na]\ncharge = 1.0\n",
+		"[species.Cl]\ncharge = -1.0\n",
+	))
+	.unwrap();
+	let neutral = rock_salt(5.64, parameters.clone());
+	let charged = rock_salt(
+		5.64,
+		SimulationParameters {
+			electrostatics: Some(Electrostatics::Ewald(Ewald::default())),
+			..parameters
+		},
+	);
+	let electrostatics = charged.evaluate_electrostatics().unwrap();
+	assert!(neutral.evaluate_electrostatics().is_none());
+	assert_approx_eq!(
+		charged.evaluate_forces().energy,
+		neutral.evaluate_forces().energy + electrostatics.energy
+	);
+}
+
+/// A water molecule, bent by an angle and with its bonds stretched by the given length, without initial velocities
+fn water(stretch: f64, parameters: SimulationParameters) -> System {
+	let length = 1.0 + stretch;
+	let half_angle = (104.52f64 / 2.0).to_radians();
+	let (x, y) = (length * half_angle.sin(), length * half_angle.cos());
+	let xyz = format!(
+		"3\nProperties=species:S:1:pos:R:3:velo:R:3\nO 0 0 0 0 0 0\nH {x} {y} 0 0 0 0\nH {} {y} 0 0 0 0\n",
+		-x
+	);
+	let mut system = System::from_str_with_parameters(&xyz, 0, parameters).unwrap();
+	let bond = |h| Bond {
+		particles: [0, h],
+		stiffness: 450.0,
+		length: 1.0,
+	};
+	let topology = Topology {
+		bonds: vec![bond(1), bond(2)],
+		angles: vec![Angle {
+			particles: [1, 0, 2],
+			stiffness: 55.0,
+			angle: 109.47,
+		}],
+		..Default::default()
+	};
+	system.set_topology(topology).unwrap();
+	return system;
+}
+
+#[test]
+fn bonded_pairs_skip_the_pair_potential() {
+	// All the pairs are bonded or the ends of the angle, so only the bonded interactions remain
+	let evaluation = evaluate_with_every_pair_search(SimulationParameters::default(), |parameters| water(0.1, parameters));
+	let bonded = water(0.1, SimulationParameters::default()).evaluate_bonded().unwrap();
+	assert!(bonded.energy > 0.0);
+	assert_approx_eq!(evaluation.energy, bonded.energy);
+	assert_approx_eq!(evaluation.virial, bonded.virial);
+	for (force, bonded_force) in evaluation.gradients.iter().zip(&bonded.gradients) {
+		assert!(
+			(*force - *bonded_force).norm() < 1e-9 * bonded_force.norm(),
+			"{force:?} != {bonded_force:?}"
+		);
+	}
+}
+
+#[test]
+fn exclusions_are_used_by_every_routine() {
+	let with_exclusions = |parameters| {
+		let mut system = dataset(parameters);
+		let topology = Topology {
+			extra_exclusions: (1..40).map(|j| [0, j]).collect(),
+			..Default::default()
+		};
+		system.set_topology(topology).unwrap();
+		return system;
+	};
+	let system = with_exclusions(SimulationParameters::default());
+	let evaluation = evaluate_with_every_pair_search(SimulationParameters::default(), with_exclusions);
+	assert_equivalent_to_pair_matrices(&system, &evaluation);
+	assert!((evaluation.energy - dataset(SimulationParameters::default()).evaluate_forces().energy).abs() > 1e-3);
+
+	// Without periodic conditions
+	let all_pairs = dataset(SimulationParameters::default());
+	let excluded_energy: f64 = (1..40)
+		.map(|j| all_pairs.potential().energy(all_pairs.distance_between_squared(0, j)))
+		.sum();
+	assert_approx_eq!(system.microscopic_energy(), all_pairs.microscopic_energy() - excluded_energy);
+	assert!(System::sum_of_forces(&system.compute_forces()).norm() < 1e-5);
+	assert_eq!(system.energy_gradient(0, 1), Vector3::zero());
+	assert_eq!(system.compute_forces()[1][0], Vector3::zero());
+}
+
+#[test]
+fn excluded_pairs_skip_the_electrostatics() {
+	for electrostatics in ["ewald", "particle_mesh_ewald"] {
+		let parameters = SimulationParameters::from_toml(&format!(
+			concat!(
+				"[species.O]\ncharge = -0.8476\n",
+				"[species.H]\ncharge = 0.4238\n",
+				"[electrostatics]\nkind = \"{}\"\naccuracy = 1e-8\n",
+			),
+			electrostatics
+		))
+		.unwrap();
+		let molecule = water(0.1, parameters.clone());
+		let mut free_charges = molecule.clone();
+		free_charges.set_topology(Topology::default()).unwrap();
+
+		// The excluded pairs lose their whole Coulomb interaction, but not the one with the images of the other particle
+		let (excluded, free) = (
+			molecule.evaluate_electrostatics().unwrap(),
+			free_charges.evaluate_electrostatics().unwrap(),
+		);
+		let mut coulomb = ForceEvaluation::zero(3);
+		let position = |k: usize| {
+			let (x, y, z) = molecule.particles()[k].xyz();
+			Vector3::from(x, y, z)
+		};
+		for (i, j) in [(0, 1), (0, 2), (1, 2)] {
+			let displacement = position(i) - position(j);
+			let charges = molecule.particles()[i].charge() * molecule.particles()[j].charge();
+			let energy = COULOMB_CONSTANT * charges / displacement.norm();
+			let gradient = displacement * (-energy / displacement.norm_squared());
+			coulomb.energy += energy;
+			coulomb.gradients[i] += gradient;
+			coulomb.gradients[j] -= gradient;
+		}
+		assert!(coulomb.energy.abs() > 1.0);
+		assert!((excluded.energy - (free.energy - coulomb.energy)).abs() < 1e-6 * coulomb.energy.abs());
+		for k in 0..3 {
+			let expected = free.gradients[k] - coulomb.gradients[k];
+			assert!((excluded.gradients[k] - expected).norm() < 1e-5 * coulomb.gradients[k].norm());
+		}
+	}
+}
+
+#[test]
+fn step_integrates_the_bonded_forces() {
+	let mut molecule = water(
+		0.05,
+		SimulationParameters {
+			delta_time: 0.1,
+			..Default::default()
+		},
+	);
+	// The step integrates the momenta in amu.Å/fs, so the energy it conserves is p²/(2m) divided by the conversion
+	let hamiltonian = |system: &System| {
+		let masses = system.masses();
+		let kinetic_energy: f64 = system
+			.particles()
+			.iter()
+			.map(|particle| particle.kinetic_moment().norm_squared() / (2.0 * masses[particle.species()]))
+			.sum();
+		kinetic_energy / system.parameters().conversion_force + system.potential_energy()
+	};
+	let initial_energy = hamiltonian(&molecule);
+	let mut shortest_bond = f64::INFINITY;
+	for _ in 0..500 {
+		molecule.step();
+		shortest_bond = shortest_bond.min(molecule.distance_between(0, 1));
+		let energy = hamiltonian(&molecule);
+		assert!(
+			(energy - initial_energy).abs() < 1e-3 * initial_energy,
+			"{energy} != {initial_energy}"
+		);
+	}
+	// The stretched bonds oscillate around their length
+	assert!(shortest_bond < 1.0);
+}
+
+/// The masses of oxygen and hydrogen, with only the oxygens interacting with the Lennard-Jones potential of SPC/E.
+/// With the default potential on every species, the hydrogens of both molecules, 1.5 Å apart, would repel each other
+/// far beyond what a step of the thermal momenta can integrate.
+fn water_parameters() -> SimulationParameters {
+	SimulationParameters::from_toml(
+		"[species.O]\nmass = 16.0\nr_star = 3.55\nepsilon_star = 0.155\n[species.H]\nmass = 1.008\nepsilon_star = 0.0",
+	)
+	.unwrap()
+}
+
+/// Two rigid SPC/E water molecules, a few Å apart
+fn rigid_waters(parameters: SimulationParameters) -> System {
+	let half_angle = (109.47f64 / 2.0).to_radians();
+	let (x, y) = (half_angle.sin(), half_angle.cos());
+	let mut xyz = "6\nProperties=species:S:1:pos:R:3:velo:R:3\n".to_string();
+	for offset in [0.0, 3.1] {
+		xyz += &format!("O {offset} 0 0 0 0 0\nH {} {y} 0 0 0 0\nH {} {y} 0 0 0 0\n", offset + x, offset - x);
+	}
+	let mut system = System::from_str_with_parameters(&xyz, 0, parameters).unwrap();
+	let topology = Topology {
+		rigid_waters: vec![RigidWater::spc_e([0, 1, 2]), RigidWater::spc_e([3, 4, 5])],
+		..Default::default()
+	};
+	system.set_topology(topology).unwrap();
+	return system;
+}
+
+#[test]
+fn rigid_waters_keep_their_geometry() {
+	let mut waters = rigid_waters(SimulationParameters {
+		delta_time: 0.5,
+		..water_parameters()
+	});
+	// 6 particles, minus the center of mass and the 3 distances of each molecule
+	assert_eq!(waters.degrees_of_liberty(), (3 * 6 - 3 - 2 * 3) as f64);
+	waters.init_particles_momentums_with_rng(&mut StdRng::seed_from_u64(23));
+	assert_approx_eq!(waters.kinetic_energy_and_temperature().1, waters.parameters().t_0);
+
+	// The molecules may cross the sides of the box
+	let displacement = |system: &System, i: usize, j: usize| {
+		let (first, second) = (system.particles()[i].xyz(), system.particles()[j].xyz());
+		let displacement = Vector3::from(first.0 - second.0, first.1 - second.1, first.2 - second.2);
+		minimum_image(displacement, system.parameters().box_side)
+	};
+	let distance = |system: &System, i: usize, j: usize| displacement(system, i, j).norm();
+
+	// The initial momenta, without the one of the center of mass, don't stretch the molecules
+	let masses = waters.masses();
+	let velocity = |i: usize| {
+		let particle = &waters.particles()[i];
+		particle.kinetic_moment() / masses[particle.species()]
+	};
+	for (i, j) in [(0, 1), (0, 2), (1, 2), (3, 4), (3, 5), (4, 5)] {
+		assert!(displacement(&waters, i, j).dot(&(velocity(i) - velocity(j))).abs() < 1e-9);
+	}
+
+	let geometry = RigidWater::spc_e([0, 1, 2]);
+	for _ in 0..1000 {
+		waters.step();
+		for first in [0, 3] {
+			for (i, j, length) in [(0, 1, geometry.oh_length), (0, 2, geometry.oh_length), (1, 2, geometry.hh_length)] {
+				let distance = distance(&waters, first + i, first + j);
+				assert!((distance - length).abs() < 1e-6, "{distance} != {length}");
+			}
+		}
+	}
+}
+
+/// The particles of `dataset/3_particles.xyz`, starting at 300 K, which first repel each other strongly then fly apart
+fn three_particles(integrator: Integration) -> System {
+	let parameters = SimulationParameters {
+		integrator,
+		delta_time: 0.1,
+		seed: Some(24),
+		..Default::default()
+	};
+	let mut system = System::from_file_with_parameters(Path::new("dataset/3_particles.xyz"), 0, parameters).unwrap();
+	system.init_particles_momentums();
+	return system;
+}
+
+#[test]
+fn velocity_verlet_conserves_the_energy() {
+	let mut system = three_particles(Integration::VelocityVerlet);
+	let initial_energy = system.energy_breakdown().total_energy();
+	for _ in 0..10_000 {
+		system.step();
+		let energy = system.energy_breakdown().total_energy();
+		assert!(
+			(energy - initial_energy).abs() < 1e-3 * initial_energy,
+			"{energy} != {initial_energy}"
+		);
+	}
+	// The potential energy was converted into kinetic energy
+	assert!(system.energy_breakdown().kinetic_energy > 0.99 * initial_energy);
+}
+
+#[test]
+fn leapfrog_conserves_the_energy() {
+	// The stretched bonds of the molecule keep it bound, so the forces never vanish during the run
+	let molecule = |integrator| {
+		water(
+			0.05,
+			SimulationParameters {
+				integrator,
+				delta_time: 0.1,
+				..Default::default()
+			},
+		)
+	};
+	let (mut leapfrog, mut velocity_verlet) = (molecule(Integration::Leapfrog), molecule(Integration::VelocityVerlet));
+	let initial_energy = leapfrog.energy_breakdown().total_energy();
+	for _ in 0..2000 {
+		leapfrog.step();
+		velocity_verlet.step();
+		assert!((leapfrog.distance_between(0, 1) - 1.0).abs() < 0.1);
+
+		// Started half a step behind, the momenta of leapfrog give the positions of velocity Verlet
+		for (first, second) in leapfrog.particles().iter().zip(velocity_verlet.particles()) {
+			let (first, second) = (first.xyz(), second.xyz());
+			assert!((Vector3::from(first.0 - second.0, first.1 - second.1, first.2 - second.2)).norm() < 1e-9);
+		}
+		// The kinetic energy is reported at the time of the positions
+		let energy = leapfrog.energy_breakdown().total_energy();
+		assert!((energy - velocity_verlet.energy_breakdown().total_energy()).abs() < 1e-9 * initial_energy);
+		assert!(
+			(energy - initial_energy).abs() < 1e-3 * initial_energy,
+			"{energy} != {initial_energy}"
+		);
+	}
+}
+
+#[test]
+fn integrators_conserve_the_energy_of_rigid_waters() {
+	for integrator in [
+		Integration::VelocityVerlet,
+		Integration::Leapfrog,
+		Integration::Respa(Respa::default()),
+	] {
+		let mut waters = rigid_waters(SimulationParameters {
+			integrator,
+			..water_parameters()
+		});
+		waters.init_particles_momentums_with_rng(&mut StdRng::seed_from_u64(23));
+		let initial_energy = waters.energy_breakdown().total_energy();
+		for _ in 0..2000 {
+			waters.step();
+			let energy = waters.energy_breakdown().total_energy();
+			assert!(
+				(energy - initial_energy).abs() < 1e-3 * initial_energy,
+				"{integrator:?}: {energy} != {initial_energy}"
+			);
+		}
+	}
+}
+
+/// The largest relative change of the total energy of `dataset/particles.xyz` over 50 steps of 1 fs with the given
+/// integrator, with the potential switched to 0 at the cut so that the energy is smooth
+fn energy_drift(integrator: Integration) -> f64 {
+	let parameters = SimulationParameters {
+		integrator,
+		seed: Some(25),
+		cutoff: Cutoff::Switched { r_switch: 8.0 },
+		..Default::default()
+	};
+	let mut system = System::from_file_with_parameters(Path::new("dataset/particles.xyz"), 0, parameters).unwrap();
+	system.init_particles_momentums();
+	let initial_energy = system.energy_breakdown().total_energy();
+	let mut drift: f64 = 0.0;
+	for _ in 0..50 {
+		system.step();
+		drift = drift.max((system.energy_breakdown().total_energy() - initial_energy).abs());
+	}
+	return drift / initial_energy.abs();
+}
+
+#[test]
+fn respa_integrates_the_fast_forces_more_accurately() {
+	let single_step = energy_drift(Integration::VelocityVerlet);
+	let respa = energy_drift(Integration::Respa(Respa::default()));
+	// The error of velocity Verlet grows with the square of the time step, and the fast forces dominate it
+	assert!(respa < 0.25 * single_step, "{respa} >= {single_step} / 4");
+	assert!(respa < 1e-5, "{respa}");
+}
@@ -0,0 +1,95 @@
+use std::io::{Cursor, Write};
+use std::path::Path;
+
+use mlom::system::System;
+use mlom::trajectory::{TrajectoryReader, TrajectoryWriter};
+
+#[test]
+fn written_frames_are_read_back() {
+	let mut system = System::from_file(Path::new("dataset/3_particles.xyz"), 0).unwrap();
+	let mut writer = TrajectoryWriter::new(Vec::new(), 2).with_momenta().with_forces();
+
+	let mut snapshots = vec![];
+	writer.record(&system, 0).unwrap();
+	snapshots.push(system.clone());
+	for step in 1..=5 {
+		system.step();
+		writer.record(&system, step).unwrap();
+		if step % 2 == 0 {
+			snapshots.push(system.clone());
+		}
+	}
+
+	let frames: Vec<_> = TrajectoryReader::new(Cursor::new(writer.into_inner()))
+		.collect::<Result<_, _>>()
+		.unwrap();
+	assert_eq!(frames.len(), 3);
+
+	for (frame, snapshot) in frames.iter().zip(&snapshots) {
+		assert_eq!(frame.nb_atoms(), 3);
+		assert_eq!(frame.box_side(), Some(snapshot.parameters().box_side));
+		for (i, particle) in snapshot.particles().iter().enumerate() {
+			assert_eq!(frame.positions()[i].x(), particle.x());
+			assert_eq!(frame.positions()[i].y(), particle.y());
+			assert_eq!(frame.positions()[i].z(), particle.z());
+			assert_eq!(frame.momenta().unwrap()[i], particle.kinetic_moment());
+		}
+
+		// The forces applied to the particles, which follow Newton's third law
+		let forces = frame.forces().unwrap();
+		assert_eq!(forces, snapshot.forces_per_particle());
+		assert!((forces[0] + forces[1] + forces[2]).norm() < 1e-9);
+	}
+	let steps: Vec<_> = frames.iter().map(|frame| frame.step()).collect();
+	assert_eq!(steps, [Some(0), Some(2), Some(4)]);
+
+	// A frame can be turned back into a system to replay the run
+	let replayed = System::from_frame(frames[2].clone(), 0, system.parameters().clone()).unwrap();
+	assert_eq!(replayed.particles(), snapshots[2].particles());
+}
+
+#[test]
+fn frames_are_read_lazily() {
+	let contents = "1\nStep=0\nAr 0 0 0\n1\nStep=1\nAr 1 0 0\n1\nStep=2\nAr 2 0 oops\n";
+	let mut reader = TrajectoryReader::new(Cursor::new(contents));
+
+	// The corrupted frame is only reached on the third frame
+	assert_eq!(reader.next().unwrap().unwrap().step(), Some(0));
+	assert_eq!(reader.next().unwrap().unwrap().step(), Some(1));
+	let error = reader.next().unwrap().unwrap_err();
+	assert_eq!(error.line(), 9);
+	assert!(reader.next().is_none());
+}
+
+/// An in-memory output which counts how many times it is flushed
+#[derive(Default)]
+struct CountingFlushes {
+	bytes: Vec<u8>,
+	flushes: usize,
+}
+
+impl Write for CountingFlushes {
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		return self.bytes.write(buf);
+	}
+
+	fn flush(&mut self) -> std::io::Result<()> {
+		self.flushes += 1;
+		return Ok(());
+	}
+}
+
+#[test]
+fn frames_are_flushed_on_demand_only() {
+	let mut system = System::from_file(Path::new("dataset/3_particles.xyz"), 0).unwrap();
+	let mut writer = TrajectoryWriter::new(CountingFlushes::default(), 1);
+	for step in 0..3 {
+		writer.record(&system, step).unwrap();
+		system.step();
+	}
+	writer.flush().unwrap();
+
+	let output = writer.into_inner();
+	assert_eq!(output.flushes, 1);
+	assert_eq!(TrajectoryReader::new(Cursor::new(output.bytes)).count(), 3);
+}
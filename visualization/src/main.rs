@@ -62,7 +62,7 @@ fn setup(
 	mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>, mut materials: ResMut<Assets<StandardMaterial>>,
 	asset_server: Res<AssetServer>,
 ) {
-	let system = System::from_file(Path::new("../dataset/particles.xyz"), 0);
+	let system = System::from_file(Path::new("../dataset/particles.xyz"), 0).unwrap_or_else(|error| panic!("{error}"));
 
 	let texture_handle = asset_server.load("particle.png");
 